@@ -1,3 +1,62 @@
+from typing import Callable
+
+class Match:
+    """
+    result of wait functions
+    """
+
+    index: int
+    """which pattern matched"""
+    text: str
+    """matched text"""
+    groups: list[str | None]
+    """regex capture groups"""
+
+class ExecResult:
+    """
+    result of ssh_script_run_seperate
+    """
+
+    code: int
+    """exit code, -1 if killed by signal"""
+    stdout: str
+    stderr: str
+
+class JobStatus:
+    """
+    status of a background job
+    """
+
+    state: str
+    """running, exited or killed"""
+    code: int | None
+    """exit code if exited"""
+    signal: str | None
+    """signal name without SIG if killed, like TERM"""
+
+class JobOutput:
+    """
+    everything a background job printed so far
+    """
+
+    stdout: str
+    stderr: str
+
+class Console:
+    """
+    text console by name, returned by Driver.console
+    """
+
+    name: str
+    def script_run(self, cmd: str, timeout: int, on_output: Callable[[str], None] | None = None) -> tuple[int, str]: ...
+    def assert_script_run(self, cmd: str, timeout: int) -> str: ...
+    def write(self, s: str): ...
+    def writeln(self, s: str): ...
+    def wait_string(self, s: str, timeout: int) -> Match: ...
+    def wait_regex(self, pattern: str, timeout: int) -> Match: ...
+    def wait_prompt(self, timeout: int): ...
+    def wait_ready(self, timeout: int): ...
+
 class Driver:
     """
     A driver for running test
@@ -16,6 +75,11 @@ class Driver:
         stop the runner
         """
 
+    def run_script(self, path: str):
+        """
+        run a js, lua or perl case file with this driver, throw exception if the case failed
+        """
+
     def sleep(self, secs: int):
         """
         sleep for secs, you can use this function to simulate a long running script
@@ -31,9 +95,13 @@ class Driver:
         run script in console, return stdout, throw exception if return code is not 0
         """
 
-    def script_run(self, cmd: str, timeout: int) -> str:
+    def script_run(
+        self, cmd: str, timeout: int, on_output: Callable[[str], None] | None = None
+    ) -> tuple[int, str]:
         """
         like assert_script_run, but not throw exception if return code is not 0
+
+        on_output is called with each output line while cmd is running
         """
 
     def write(self, s: str):
@@ -46,7 +114,7 @@ class Driver:
         write string with '\n' to console
         """
 
-    def wait_string(self, s: str, timeout: int):
+    def wait_string(self, s: str, timeout: int) -> Match:
         """
         wait pattern in console output, if timeout, throw exception
         """
@@ -56,6 +124,41 @@ class Driver:
         wait pattern in console output, return bool
         """
 
+    def wait_string_ntimes(self, s: str, n: int, timeout: int) -> bool:
+        """
+        wait pattern in console output show n times
+        """
+
+    def assert_wait_string_ntimes(self, s: str, n: int, timeout: int) -> Match:
+        """
+        wait pattern in console output show n times, if timeout, throw exception
+        """
+
+    def wait_regex(self, pattern: str, timeout: int) -> Match:
+        """
+        wait regex in console output, if timeout, throw exception
+        """
+
+    def wait_any(self, patterns: list[str], timeout: int, regex: bool = False) -> Match:
+        """
+        wait any of patterns in console output, Match.index tells which one matched
+        """
+
+    def wait_prompt(self, timeout: int):
+        """
+        wait until console shows shell prompt, if timeout, throw exception
+        """
+
+    def wait_console_ready(self, timeout: int):
+        """
+        wait until all consoles are connected again, like after reboot, if timeout, throw exception
+        """
+
+    def console(self, name: str) -> Console:
+        """
+        console by name from config, like client for [ssh.client], or ssh.client if serial has a client too
+        """
+
     def ssh_assert_script_run(self, cmd: str, timeout: int) -> str:
         """
         run script in ssh, return stdout, throw exception if return code is not 0
@@ -71,6 +174,58 @@ class Driver:
         write string to ssh console
         """
 
+    def upload(self, local: str, remote: str, mode: int | None = None):
+        """
+        copy local file or directory to remote over ssh, mode like 0o755, default to mode of local file
+        """
+
+    def download(self, remote: str, local: str):
+        """
+        copy remote file or directory to local over ssh, relative local path is under log_dir
+        """
+
+    def remote_exists(self, remote: str) -> bool:
+        """
+        check if remote path exists
+        """
+
+    def spawn(self, cmd: str) -> int:
+        """
+        run cmd in background on ssh, return job id, ssh console stays usable
+        """
+
+    def job_status(self, id: int) -> JobStatus:
+        """
+        get status of a background job
+        """
+
+    def job_wait(self, id: int, timeout: int) -> JobStatus:
+        """
+        wait until job exited, throw exception if timeout
+        """
+
+    def job_kill(self, id: int) -> JobStatus:
+        """
+        kill job and all its children with SIGTERM, SIGKILL if it's still running after 5s
+        """
+
+    def job_output(self, id: int) -> JobOutput:
+        """
+        get stdout and stderr of a background job
+        """
+
+    def ssh_script_run_seperate(
+        self,
+        cmd: str,
+        timeout: int,
+        env: dict[str, str] | None = None,
+        cwd: str | None = None,
+    ) -> ExecResult:
+        """
+        run script in seperate ssh channel with extra env and cwd, throw exception and kill it
+        if timeout
+        """
+
     def ssh_assert_script_run_seperate(self, cmd: str, timeout: int) -> str:
         """
         run script in seperate ssh session, return stdout, throw exception if return code is not 0
@@ -91,14 +246,26 @@ class Driver:
         write string to ssh console
         """
 
-    def assert_screen(self, tag: str, timeout: int):
+    def serial_upload(self, local: str, remote: str, timeout: int, mode: int | None = None):
+        """
+        copy local file to remote over serial console, base64 encoded and checked with md5sum, slow, only for small files
+        """
+
+    def serial_download(self, remote: str, local: str, timeout: int):
+        """
+        copy remote file to local over serial console, relative local path is under log_dir
+        """
+
+    def assert_screen(self, tag: str | list[str], timeout: int) -> str:
         """
-        check screen, throw exception if timeout, or not similar to tag
+        check screen against every needle carrying tag, or any of tags, return name of the
+        matched needle, throw exception if timeout, or none is similar
         """
 
-    def check_screen(self, tag: str, timeout: int) -> bool:
+    def check_screen(self, tag: str | list[str], timeout: int) -> bool:
         """
-        check screen, return false if timeout, or not similar to tag
+        like assert_screen, but return false if timeout, or none is similar. still throw
+        exception if no needle carries tag, or its png or json can't be used
         """
 
     def type_string(self, s: str):
@@ -145,3 +312,31 @@ class Driver:
         """
         hide mouse
         """
+
+    def power_off(self):
+        """
+        power off the machine, with [power] commands or qemu
+        """
+
+    def power_on(self, timeout: int):
+        """
+        power on, then wait boot_pattern on serial for timeout secs, 0 means don't wait
+        """
+
+    def power_reset(self, timeout: int):
+        """
+        like power_on, but reset the machine
+        """
+
+    def power_cycle(self, timeout: int):
+        """
+        like power_on, but power off and on again
+        """
+
+    def reboot_and_wait(
+        self, boot_timeout: int, login_timeout: int, ssh_timeout: int, cmd: str | None = None
+    ) -> float:
+        """
+        write cmd (default reboot) to serial, or ssh if no serial, then wait boot_pattern on serial,
+        auto login on serial and all ssh reconnected, 0 skips a stage. return boot time in secs
+        """
@@ -1,7 +1,7 @@
 use pyo3::Python;
 use std::thread;
 use std::{sync::mpsc, time::Duration};
-use t_binding::api::Api;
+use t_binding::api::{Api, OnOutput};
 use t_binding::error::{ApiError, Result};
 use t_binding::msg::VNC;
 use t_binding::{
@@ -50,6 +50,37 @@ impl<'a> Api for PyApi<'a> {
         }
     }
 
+    fn req_stream(
+        &self,
+        req: MsgReq,
+        output_rx: mpsc::Receiver<String>,
+        on_output: OnOutput,
+    ) -> Result<MsgRes> {
+        let msg_tx = self.tx();
+
+        trace!(msg = "sending req");
+        let (tx, rx) = mpsc::channel::<MsgRes>();
+        msg_tx
+            .send((req, tx))
+            .map_err(|_| ApiError::ServerStopped)?;
+
+        trace!(msg = "waiting output");
+        loop {
+            match output_rx.try_recv() {
+                Ok(chunk) => {
+                    on_output(chunk)?;
+                    continue;
+                }
+                Err(mpsc::TryRecvError::Empty) => {}
+                // server done with req
+                Err(mpsc::TryRecvError::Disconnected) => break,
+            }
+            self.py.check_signals().map_err(|_| ApiError::Interrupt)?;
+            thread::sleep(Duration::from_millis(100));
+        }
+        rx.recv().map_err(|_| ApiError::ServerStopped)
+    }
+
     fn sleep(&self, secs: u64) {
         for i in 0..secs {
             std::thread::sleep(Duration::from_secs(1));
@@ -8,7 +8,9 @@ use pyo3::{
     prelude::*,
 };
 use std::{
+    collections::HashMap,
     env,
+    path::Path,
     sync::{
         mpsc::{Receiver, Sender},
         Arc, Mutex,
@@ -17,7 +19,7 @@ use std::{
 };
 use t_binding::{
     api::{Api, ApiTx},
-    ApiError, MsgReq, MsgRes,
+    new_engine, ApiError, MsgReq, MsgRes, TextConsole, WaitMatch,
 };
 use t_config::{Config, ConsoleSSH};
 use t_console::SSH;
@@ -44,6 +46,30 @@ fn into_pyerr(e: ApiError) -> PyErr {
     }
 }
 
+// keep exception raised by on_output for caller
+fn script_run(
+    api: &PyApi,
+    console: Option<TextConsole>,
+    cmd: String,
+    timeout: i32,
+    on_output: Option<Bound<'_, PyAny>>,
+) -> PyResult<(i32, String)> {
+    let Some(on_output) = on_output else {
+        return api._script_run(cmd, console, timeout).map_err(into_pyerr);
+    };
+    let mut callback_err = None;
+    let res = api._script_run_stream(cmd, console, timeout, &mut |chunk: String| {
+        on_output.call1((chunk,)).map(|_| ()).map_err(|e| {
+            callback_err = Some(e);
+            ApiError::Interrupt
+        })
+    });
+    match callback_err {
+        Some(e) => Err(e),
+        None => res.map_err(into_pyerr),
+    }
+}
+
 /// Entrypoint, A Python module implemented in Rust.
 #[pymodule]
 fn pyautotest(py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
@@ -51,6 +77,11 @@ fn pyautotest(py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
 
     tracing::info!("pyautotest module initialized");
     m.add_class::<Driver>()?;
+    m.add_class::<Console>()?;
+    m.add_class::<Match>()?;
+    m.add_class::<JobStatus>()?;
+    m.add_class::<JobOutput>()?;
+    m.add_class::<ExecResult>()?;
     Ok(())
 }
 
@@ -109,7 +140,7 @@ impl Driver {
 
     // ssh
     fn new_ssh(&self) -> PyResult<DriverSSH> {
-        let Some(ssh) = self.config.ssh.clone() else {
+        let Some(ssh) = self.config.ssh.get_default().cloned() else {
             return Err(DriverException::new_err("no ssh config"));
         };
         DriverSSH::new(ssh)
@@ -119,6 +150,22 @@ impl Driver {
         self.driver.stop();
     }
 
+    // run a js/lua/perl case over the connections of this driver
+    fn run_script(&self, py: Python<'_>, path: String) -> PyResult<()> {
+        let ext = Path::new(&path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let tx = self.tx.clone();
+        let report = py
+            .allow_threads(|| new_engine(&ext, tx).map(|mut e| e.run_file(&path)))
+            .ok_or_else(|| UserException::new_err(format!("unsupported script type: {}", ext)))?;
+        match report.failure {
+            Some(e) if !report.is_success() => Err(AssertException::new_err(e)),
+            _ => Ok(()),
+        }
+    }
+
     fn sleep(&self, py: Python<'_>, miles: i32) {
         PyApi::new(&self.tx, py).sleep(miles as u64);
     }
@@ -133,10 +180,23 @@ impl Driver {
             .map_err(into_pyerr)
     }
 
-    fn script_run(&self, py: Python<'_>, cmd: String, timeout: i32) -> PyResult<(i32, String)> {
-        PyApi::new(&self.tx, py)
-            .script_run(cmd, timeout)
-            .map_err(into_pyerr)
+    #[pyo3(signature = (cmd, timeout, on_output = None))]
+    fn script_run(
+        &self,
+        py: Python<'_>,
+        cmd: String,
+        timeout: i32,
+        on_output: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<(i32, String)> {
+        script_run(&PyApi::new(&self.tx, py), None, cmd, timeout, on_output)
+    }
+
+    /// console by name from config, like client, or ssh.client if names clash
+    fn console(&self, name: String) -> Console {
+        Console {
+            tx: self.tx.clone(),
+            name,
+        }
     }
 
     fn write(&self, py: Python<'_>, s: String) -> PyResult<()> {
@@ -153,9 +213,60 @@ impl Driver {
         Ok(PyApi::new(&self.tx, py).wait_string(s, timeout).is_ok())
     }
 
-    fn wait_string(&self, py: Python<'_>, s: String, timeout: i32) -> PyResult<()> {
+    fn wait_string(&self, py: Python<'_>, s: String, timeout: i32) -> PyResult<Match> {
         PyApi::new(&self.tx, py)
             .wait_string(s, timeout)
+            .map(Match::from)
+            .map_err(into_pyerr)
+    }
+
+    fn wait_string_ntimes(&self, py: Python<'_>, s: String, n: usize, timeout: i32) -> bool {
+        PyApi::new(&self.tx, py).wait_string_ntimes(s, n, timeout)
+    }
+
+    fn assert_wait_string_ntimes(
+        &self,
+        py: Python<'_>,
+        s: String,
+        n: usize,
+        timeout: i32,
+    ) -> PyResult<Match> {
+        PyApi::new(&self.tx, py)
+            .assert_wait_string_ntimes(s, n, timeout)
+            .map(Match::from)
+            .map_err(into_pyerr)
+    }
+
+    fn wait_regex(&self, py: Python<'_>, pattern: String, timeout: i32) -> PyResult<Match> {
+        PyApi::new(&self.tx, py)
+            .wait_regex(pattern, timeout)
+            .map(Match::from)
+            .map_err(into_pyerr)
+    }
+
+    #[pyo3(signature = (patterns, timeout, regex = false))]
+    fn wait_any(
+        &self,
+        py: Python<'_>,
+        patterns: Vec<String>,
+        timeout: i32,
+        regex: bool,
+    ) -> PyResult<Match> {
+        PyApi::new(&self.tx, py)
+            .wait_any(patterns, regex, timeout)
+            .map(Match::from)
+            .map_err(into_pyerr)
+    }
+
+    fn wait_prompt(&self, py: Python<'_>, timeout: i32) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .wait_prompt(timeout)
+            .map_err(into_pyerr)
+    }
+
+    fn wait_console_ready(&self, py: Python<'_>, timeout: i32) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .wait_console_ready(timeout)
             .map_err(into_pyerr)
     }
 
@@ -176,6 +287,79 @@ impl Driver {
         PyApi::new(&self.tx, py).ssh_write(s);
     }
 
+    #[pyo3(signature = (local, remote, mode = None))]
+    fn upload(
+        &self,
+        py: Python<'_>,
+        local: String,
+        remote: String,
+        mode: Option<i32>,
+    ) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .upload(local, remote, mode)
+            .map_err(into_pyerr)
+    }
+
+    fn download(&self, py: Python<'_>, remote: String, local: String) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .download(remote, local)
+            .map_err(into_pyerr)
+    }
+
+    fn remote_exists(&self, py: Python<'_>, remote: String) -> PyResult<bool> {
+        PyApi::new(&self.tx, py)
+            .remote_exists(remote)
+            .map_err(into_pyerr)
+    }
+
+    fn spawn(&self, py: Python<'_>, cmd: String) -> PyResult<u32> {
+        PyApi::new(&self.tx, py).spawn(cmd).map_err(into_pyerr)
+    }
+
+    fn job_status(&self, py: Python<'_>, id: u32) -> PyResult<JobStatus> {
+        PyApi::new(&self.tx, py)
+            .job_status(id)
+            .map(JobStatus::from)
+            .map_err(into_pyerr)
+    }
+
+    fn job_wait(&self, py: Python<'_>, id: u32, timeout: i32) -> PyResult<JobStatus> {
+        PyApi::new(&self.tx, py)
+            .job_wait(id, timeout)
+            .map(JobStatus::from)
+            .map_err(into_pyerr)
+    }
+
+    fn job_kill(&self, py: Python<'_>, id: u32) -> PyResult<JobStatus> {
+        PyApi::new(&self.tx, py)
+            .job_kill(id)
+            .map(JobStatus::from)
+            .map_err(into_pyerr)
+    }
+
+    fn job_output(&self, py: Python<'_>, id: u32) -> PyResult<JobOutput> {
+        PyApi::new(&self.tx, py)
+            .job_output(id)
+            .map(JobOutput::from)
+            .map_err(into_pyerr)
+    }
+
+    #[pyo3(signature = (cmd, timeout, env = None, cwd = None))]
+    fn ssh_script_run_seperate(
+        &self,
+        py: Python<'_>,
+        cmd: String,
+        timeout: i32,
+        env: Option<HashMap<String, String>>,
+        cwd: Option<String>,
+    ) -> PyResult<ExecResult> {
+        let env = env.unwrap_or_default().into_iter().collect();
+        PyApi::new(&self.tx, py)
+            .ssh_script_run_seperate(cmd, env, cwd, timeout)
+            .map(ExecResult::from)
+            .map_err(into_pyerr)
+    }
+
     fn ssh_assert_script_run_seperate(
         &self,
         py: Python<'_>,
@@ -214,16 +398,42 @@ impl Driver {
         PyApi::new(&self.tx, py).serial_write(s);
     }
 
+    #[pyo3(signature = (local, remote, timeout, mode = None))]
+    fn serial_upload(
+        &self,
+        py: Python<'_>,
+        local: String,
+        remote: String,
+        timeout: i32,
+        mode: Option<i32>,
+    ) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .serial_upload(local, remote, mode, timeout)
+            .map_err(into_pyerr)
+    }
+
+    fn serial_download(
+        &self,
+        py: Python<'_>,
+        remote: String,
+        local: String,
+        timeout: i32,
+    ) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .serial_download(remote, local, timeout)
+            .map_err(into_pyerr)
+    }
+
     // vnc
-    fn check_screen(&self, py: Python<'_>, tag: String, timeout: i32) -> PyResult<bool> {
+    fn check_screen(&self, py: Python<'_>, tag: Tags, timeout: i32) -> PyResult<bool> {
         PyApi::new(&self.tx, py)
-            .vnc_check_screen(tag, timeout)
+            .vnc_check_screen(tag.into(), timeout)
             .map_err(into_pyerr)
     }
 
-    fn assert_screen(&self, py: Python<'_>, tag: String, timeout: i32) -> PyResult<()> {
+    fn assert_screen(&self, py: Python<'_>, tag: Tags, timeout: i32) -> PyResult<String> {
         PyApi::new(&self.tx, py)
-            .vnc_assert_screen(tag, timeout)
+            .vnc_assert_screen(tag.into(), timeout)
             .map_err(into_pyerr)
     }
 
@@ -241,15 +451,15 @@ impl Driver {
         PyApi::new(&self.tx, py).vnc_refresh().map_err(into_pyerr)
     }
 
-    fn check_and_click(&self, py: Python<'_>, tag: String, timeout: i32) -> PyResult<bool> {
+    fn check_and_click(&self, py: Python<'_>, tag: Tags, timeout: i32) -> PyResult<bool> {
         PyApi::new(&self.tx, py)
-            .vnc_check_and_click(tag, timeout)
+            .vnc_check_and_click(tag.into(), timeout)
             .map_err(into_pyerr)
     }
 
-    fn assert_and_click(&self, py: Python<'_>, tag: String, timeout: i32) -> PyResult<()> {
+    fn assert_and_click(&self, py: Python<'_>, tag: Tags, timeout: i32) -> PyResult<()> {
         PyApi::new(&self.tx, py)
-            .vnc_assert_and_click(tag, timeout)
+            .vnc_assert_and_click(tag.into(), timeout)
             .map_err(into_pyerr)
     }
 
@@ -288,6 +498,310 @@ impl Driver {
             .vnc_mouse_hide()
             .map_err(into_pyerr)
     }
+
+    fn power_off(&self, py: Python<'_>) -> PyResult<()> {
+        PyApi::new(&self.tx, py).power_off().map_err(into_pyerr)
+    }
+
+    fn power_on(&self, py: Python<'_>, timeout: i32) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .power_on(timeout)
+            .map_err(into_pyerr)
+    }
+
+    fn power_reset(&self, py: Python<'_>, timeout: i32) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .power_reset(timeout)
+            .map_err(into_pyerr)
+    }
+
+    fn power_cycle(&self, py: Python<'_>, timeout: i32) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .power_cycle(timeout)
+            .map_err(into_pyerr)
+    }
+
+    #[pyo3(signature = (boot_timeout, login_timeout, ssh_timeout, cmd = None))]
+    fn reboot_and_wait(
+        &self,
+        py: Python<'_>,
+        boot_timeout: i32,
+        login_timeout: i32,
+        ssh_timeout: i32,
+        cmd: Option<String>,
+    ) -> PyResult<f64> {
+        PyApi::new(&self.tx, py)
+            .reboot_and_wait(cmd, boot_timeout, login_timeout, ssh_timeout)
+            .map_err(into_pyerr)
+    }
+}
+
+/// text console by name, returned by Driver.console
+#[pyclass]
+struct Console {
+    tx: ApiTx,
+    #[pyo3(get)]
+    name: String,
+}
+
+#[pymethods]
+impl Console {
+    fn assert_script_run(&self, py: Python<'_>, cmd: String, timeout: i32) -> PyResult<String> {
+        PyApi::new(&self.tx, py)
+            .console_assert_script_run(self.name.clone(), cmd, timeout)
+            .map_err(into_pyerr)
+    }
+
+    #[pyo3(signature = (cmd, timeout, on_output = None))]
+    fn script_run(
+        &self,
+        py: Python<'_>,
+        cmd: String,
+        timeout: i32,
+        on_output: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<(i32, String)> {
+        let console = Some(TextConsole::Named(self.name.clone()));
+        script_run(&PyApi::new(&self.tx, py), console, cmd, timeout, on_output)
+    }
+
+    fn write(&self, py: Python<'_>, s: String) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .console_write(self.name.clone(), s)
+            .map_err(into_pyerr)
+    }
+
+    fn writeln(&self, py: Python<'_>, s: String) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .console_write(self.name.clone(), format!("{s}\n"))
+            .map_err(into_pyerr)
+    }
+
+    fn wait_string(&self, py: Python<'_>, s: String, timeout: i32) -> PyResult<Match> {
+        PyApi::new(&self.tx, py)
+            .console_wait_string(self.name.clone(), s, timeout)
+            .map(Match::from)
+            .map_err(into_pyerr)
+    }
+
+    fn wait_regex(&self, py: Python<'_>, pattern: String, timeout: i32) -> PyResult<Match> {
+        PyApi::new(&self.tx, py)
+            .console_wait_regex(self.name.clone(), pattern, timeout)
+            .map(Match::from)
+            .map_err(into_pyerr)
+    }
+
+    fn wait_prompt(&self, py: Python<'_>, timeout: i32) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .console_wait_prompt(self.name.clone(), timeout)
+            .map_err(into_pyerr)
+    }
+
+    fn wait_ready(&self, py: Python<'_>, timeout: i32) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .console_wait_ready(self.name.clone(), timeout)
+            .map_err(into_pyerr)
+    }
+
+    #[pyo3(signature = (cmd, timeout, env = None, cwd = None))]
+    fn ssh_script_run_seperate(
+        &self,
+        py: Python<'_>,
+        cmd: String,
+        timeout: i32,
+        env: Option<HashMap<String, String>>,
+        cwd: Option<String>,
+    ) -> PyResult<ExecResult> {
+        let env = env.unwrap_or_default().into_iter().collect();
+        PyApi::new(&self.tx, py)
+            .console_ssh_script_run_seperate(self.name.clone(), cmd, env, cwd, timeout)
+            .map(ExecResult::from)
+            .map_err(into_pyerr)
+    }
+
+    #[pyo3(signature = (local, remote, mode = None))]
+    fn upload(
+        &self,
+        py: Python<'_>,
+        local: String,
+        remote: String,
+        mode: Option<i32>,
+    ) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .console_upload(self.name.clone(), local, remote, mode)
+            .map_err(into_pyerr)
+    }
+
+    fn download(&self, py: Python<'_>, remote: String, local: String) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .console_download(self.name.clone(), remote, local)
+            .map_err(into_pyerr)
+    }
+
+    fn remote_exists(&self, py: Python<'_>, remote: String) -> PyResult<bool> {
+        PyApi::new(&self.tx, py)
+            .console_remote_exists(self.name.clone(), remote)
+            .map_err(into_pyerr)
+    }
+
+    fn spawn(&self, py: Python<'_>, cmd: String) -> PyResult<u32> {
+        PyApi::new(&self.tx, py)
+            .console_spawn(self.name.clone(), cmd)
+            .map_err(into_pyerr)
+    }
+
+    fn job_status(&self, py: Python<'_>, id: u32) -> PyResult<JobStatus> {
+        PyApi::new(&self.tx, py)
+            .console_job_status(self.name.clone(), id)
+            .map(JobStatus::from)
+            .map_err(into_pyerr)
+    }
+
+    fn job_wait(&self, py: Python<'_>, id: u32, timeout: i32) -> PyResult<JobStatus> {
+        PyApi::new(&self.tx, py)
+            .console_job_wait(self.name.clone(), id, timeout)
+            .map(JobStatus::from)
+            .map_err(into_pyerr)
+    }
+
+    fn job_kill(&self, py: Python<'_>, id: u32) -> PyResult<JobStatus> {
+        PyApi::new(&self.tx, py)
+            .console_job_kill(self.name.clone(), id)
+            .map(JobStatus::from)
+            .map_err(into_pyerr)
+    }
+
+    fn job_output(&self, py: Python<'_>, id: u32) -> PyResult<JobOutput> {
+        PyApi::new(&self.tx, py)
+            .console_job_output(self.name.clone(), id)
+            .map(JobOutput::from)
+            .map_err(into_pyerr)
+    }
+
+    #[pyo3(signature = (local, remote, timeout, mode = None))]
+    fn serial_upload(
+        &self,
+        py: Python<'_>,
+        local: String,
+        remote: String,
+        timeout: i32,
+        mode: Option<i32>,
+    ) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .console_serial_upload(self.name.clone(), local, remote, mode, timeout)
+            .map_err(into_pyerr)
+    }
+
+    fn serial_download(
+        &self,
+        py: Python<'_>,
+        remote: String,
+        local: String,
+        timeout: i32,
+    ) -> PyResult<()> {
+        PyApi::new(&self.tx, py)
+            .console_serial_download(self.name.clone(), remote, local, timeout)
+            .map_err(into_pyerr)
+    }
+}
+
+/// a tag or a list of tags, needles carrying any of them match
+#[derive(FromPyObject)]
+enum Tags {
+    One(String),
+    Any(Vec<String>),
+}
+
+impl From<Tags> for Vec<String> {
+    fn from(t: Tags) -> Self {
+        match t {
+            Tags::One(tag) => vec![tag],
+            Tags::Any(tags) => tags,
+        }
+    }
+}
+
+/// result of wait_* functions
+#[pyclass]
+struct Match {
+    /// which pattern matched
+    #[pyo3(get)]
+    index: usize,
+    #[pyo3(get)]
+    text: String,
+    #[pyo3(get)]
+    groups: Vec<Option<String>>,
+}
+
+impl From<WaitMatch> for Match {
+    fn from(m: WaitMatch) -> Self {
+        Self {
+            index: m.index,
+            text: m.text,
+            groups: m.groups,
+        }
+    }
+}
+
+/// result of ssh_script_run_seperate
+#[pyclass]
+struct ExecResult {
+    /// -1 if killed by signal
+    #[pyo3(get)]
+    code: i32,
+    #[pyo3(get)]
+    stdout: String,
+    #[pyo3(get)]
+    stderr: String,
+}
+
+impl From<t_binding::msg::ExecResult> for ExecResult {
+    fn from(r: t_binding::msg::ExecResult) -> Self {
+        Self {
+            code: r.code,
+            stdout: r.stdout,
+            stderr: r.stderr,
+        }
+    }
+}
+
+/// status of a background job
+#[pyclass]
+struct JobStatus {
+    /// running, exited or killed
+    #[pyo3(get)]
+    state: String,
+    #[pyo3(get)]
+    code: Option<i32>,
+    #[pyo3(get)]
+    signal: Option<String>,
+}
+
+impl From<t_binding::msg::JobStatus> for JobStatus {
+    fn from(s: t_binding::msg::JobStatus) -> Self {
+        Self {
+            state: s.name().to_string(),
+            code: s.code(),
+            signal: s.signal().map(|s| s.to_string()),
+        }
+    }
+}
+
+/// everything a background job printed so far
+#[pyclass]
+struct JobOutput {
+    #[pyo3(get)]
+    stdout: String,
+    #[pyo3(get)]
+    stderr: String,
+}
+
+impl From<t_binding::msg::JobOutput> for JobOutput {
+    fn from(o: t_binding::msg::JobOutput) -> Self {
+        Self {
+            stdout: o.stdout,
+            stderr: o.stderr,
+        }
+    }
 }
 
 #[pyclass]
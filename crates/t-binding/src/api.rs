@@ -1,24 +1,39 @@
 use super::error::{ApiError, Result};
 use crate::{
-    msg::{TextConsole, VNC},
+    msg::{
+        ExecResult, Job, JobId, JobOutput, JobStatus, MsgResError, Power, SerialTransfer,
+        TextConsole, Transfer, WaitMatch, QMP, VNC,
+    },
+    report::Recorder,
     MsgReq, MsgRes,
 };
 use std::{
     sync::{mpsc, Arc},
-    time::Duration,
+    time::{Duration, Instant},
 };
-use tracing::{info, trace, Level};
+use tracing::{info, trace, warn, Level};
 
 pub type ApiTx = mpsc::Sender<(MsgReq, mpsc::Sender<MsgRes>)>;
 
+/// called with each output chunk, an error stops waiting for the rest
+pub type OnOutput<'a> = &'a mut dyn FnMut(String) -> Result<()>;
+
 #[derive(Clone)]
 pub struct RustApi {
     pub tx: ApiTx,
+    recorder: Option<Recorder>,
 }
 
 impl RustApi {
     pub fn new(tx: ApiTx) -> Self {
-        Self { tx }
+        Self { tx, recorder: None }
+    }
+
+    pub fn with_recorder(tx: ApiTx, recorder: Recorder) -> Self {
+        Self {
+            tx,
+            recorder: Some(recorder),
+        }
     }
 }
 
@@ -26,24 +41,85 @@ impl Api for RustApi {
     fn tx(&self) -> &ApiTx {
         &self.tx
     }
+
+    fn req(&self, req: MsgReq) -> Result<MsgRes> {
+        let Some(recorder) = self.recorder.as_ref() else {
+            return send_req(&self.tx, req);
+        };
+        let name = format!("{:?}", req);
+        let start = Instant::now();
+        let res = send_req(&self.tx, req);
+        recorder.record(name, start, &res);
+        res
+    }
+
+    fn req_stream(
+        &self,
+        req: MsgReq,
+        output_rx: mpsc::Receiver<String>,
+        on_output: OnOutput,
+    ) -> Result<MsgRes> {
+        let Some(recorder) = self.recorder.as_ref() else {
+            return send_req_stream(&self.tx, req, output_rx, on_output);
+        };
+        let name = format!("{:?}", req);
+        let start = Instant::now();
+        let res = send_req_stream(&self.tx, req, output_rx, on_output);
+        recorder.record(name, start, &res);
+        res
+    }
+}
+
+fn send_req(msg_tx: &ApiTx, req: MsgReq) -> Result<MsgRes> {
+    trace!(msg = "sending req");
+    let (tx, rx) = mpsc::channel::<MsgRes>();
+    msg_tx
+        .send((req, tx))
+        .map_err(|_| ApiError::ServerStopped)?;
+
+    trace!(msg = "waiting res");
+    let res = rx.recv().map_err(|_| ApiError::ServerStopped)?;
+    trace!(msg = "received res");
+    Ok(res)
+}
+
+// server drops the output sender with req once handled
+fn send_req_stream(
+    msg_tx: &ApiTx,
+    req: MsgReq,
+    output_rx: mpsc::Receiver<String>,
+    on_output: OnOutput,
+) -> Result<MsgRes> {
+    trace!(msg = "sending req");
+    let (tx, rx) = mpsc::channel::<MsgRes>();
+    msg_tx
+        .send((req, tx))
+        .map_err(|_| ApiError::ServerStopped)?;
+
+    for chunk in output_rx {
+        on_output(chunk)?;
+    }
+
+    trace!(msg = "waiting res");
+    let res = rx.recv().map_err(|_| ApiError::ServerStopped)?;
+    trace!(msg = "received res");
+    Ok(res)
 }
 
 pub trait Api {
     fn tx(&self) -> &ApiTx;
 
     fn req(&self, req: MsgReq) -> Result<MsgRes> {
-        let msg_tx = &self.tx();
-
-        trace!(msg = "sending req");
-        let (tx, rx) = mpsc::channel::<MsgRes>();
-        msg_tx
-            .send((req, tx))
-            .map_err(|_| ApiError::ServerStopped)?;
+        send_req(self.tx(), req)
+    }
 
-        trace!(msg = "waiting res");
-        let res = rx.recv().map_err(|_| ApiError::ServerStopped)?;
-        trace!(msg = "received res");
-        Ok(res)
+    fn req_stream(
+        &self,
+        req: MsgReq,
+        output_rx: mpsc::Receiver<String>,
+        on_output: OnOutput,
+    ) -> Result<MsgRes> {
+        send_req_stream(self.tx(), req, output_rx, on_output)
     }
 
     fn _script_run(
@@ -63,6 +139,30 @@ pub trait Api {
         }
     }
 
+    fn _script_run_stream(
+        &self,
+        cmd: String,
+        console: Option<TextConsole>,
+        timeout: i32,
+        on_output: OnOutput,
+    ) -> Result<(i32, String)> {
+        let (output, output_rx) = mpsc::channel();
+        match self.req_stream(
+            MsgReq::ScriptRunStream {
+                cmd,
+                console,
+                timeout: Duration::from_secs(timeout as u64),
+                output,
+            },
+            output_rx,
+            on_output,
+        )? {
+            MsgRes::ScriptRun { code, value } => Ok((code, value)),
+            MsgRes::Error(e) => Err(e.into()),
+            _ => Err(ApiError::ServerInvalidResponse),
+        }
+    }
+
     fn _assert_script_run(
         &self,
         cmd: String,
@@ -98,18 +198,36 @@ pub trait Api {
         }
     }
 
-    fn _wait_string(&self, console: Option<TextConsole>, s: String, timeout: i32) -> Result<()> {
+    fn _wait_match(
+        &self,
+        console: Option<TextConsole>,
+        patterns: Vec<String>,
+        regex: bool,
+        n: usize,
+        timeout: i32,
+    ) -> Result<WaitMatch> {
         match self.req(MsgReq::WaitString {
             console,
-            s,
+            patterns,
+            regex,
+            n,
             timeout: Duration::from_secs(timeout as u64),
         })? {
-            MsgRes::Done => Ok(()),
+            MsgRes::Matched(m) => Ok(m),
             MsgRes::Error(e) => Err(e.into()),
             _ => Err(ApiError::ServerInvalidResponse),
         }
     }
 
+    fn _wait_string(
+        &self,
+        console: Option<TextConsole>,
+        s: String,
+        timeout: i32,
+    ) -> Result<WaitMatch> {
+        self._wait_match(console, vec![s], false, 1, timeout)
+    }
+
     // general
     fn print(&self, level: tracing::Level, msg: String) {
         match level {
@@ -150,6 +268,16 @@ pub trait Api {
         self._assert_script_run(cmd, None, timeout)
     }
 
+    // like script_run, on_output gets each output line while cmd is running
+    fn script_run_stream(
+        &self,
+        cmd: String,
+        timeout: i32,
+        on_output: OnOutput,
+    ) -> Result<(i32, String)> {
+        self._script_run_stream(cmd, None, timeout, on_output)
+    }
+
     fn write(&self, s: String) -> Result<()> {
         self._write(s, None)
     }
@@ -158,10 +286,182 @@ pub trait Api {
         self._wait_string(None, s, timeout).is_ok()
     }
 
-    fn wait_string(&self, s: String, timeout: i32) -> Result<()> {
+    fn wait_string(&self, s: String, timeout: i32) -> Result<WaitMatch> {
         self._wait_string(None, s, timeout)
     }
 
+    fn wait_string_ntimes(&self, s: String, n: usize, timeout: i32) -> bool {
+        self._wait_match(None, vec![s], false, n, timeout).is_ok()
+    }
+
+    fn assert_wait_string_ntimes(&self, s: String, n: usize, timeout: i32) -> Result<WaitMatch> {
+        self._wait_match(None, vec![s], false, n, timeout)
+    }
+
+    fn wait_regex(&self, pattern: String, timeout: i32) -> Result<WaitMatch> {
+        self._wait_match(None, vec![pattern], true, 1, timeout)
+    }
+
+    // index of the result tells which pattern matched
+    fn wait_any(&self, patterns: Vec<String>, regex: bool, timeout: i32) -> Result<WaitMatch> {
+        self._wait_match(None, patterns, regex, 1, timeout)
+    }
+
+    fn _wait_prompt(&self, console: Option<TextConsole>, timeout: i32) -> Result<()> {
+        match self.req(MsgReq::WaitPrompt {
+            console,
+            timeout: Duration::from_secs(timeout as u64),
+        })? {
+            MsgRes::Done => Ok(()),
+            MsgRes::Error(e) => Err(e.into()),
+            _ => Err(ApiError::ServerInvalidResponse),
+        }
+    }
+
+    // prompt is from console config, or any line ending with a common prompt char
+    fn wait_prompt(&self, timeout: i32) -> Result<()> {
+        self._wait_prompt(None, timeout)
+    }
+
+    fn _wait_console_ready(&self, console: Option<TextConsole>, timeout: i32) -> Result<()> {
+        match self.req(MsgReq::WaitConsoleReady {
+            console,
+            timeout: Duration::from_secs(timeout as u64),
+        })? {
+            MsgRes::Done => Ok(()),
+            MsgRes::Error(e) => Err(e.into()),
+            _ => Err(ApiError::ServerInvalidResponse),
+        }
+    }
+
+    // consoles reconnect by themselves after connection lost, wait until all of them are back
+    fn wait_console_ready(&self, timeout: i32) -> Result<()> {
+        self._wait_console_ready(None, timeout)
+    }
+
+    // console by name from config, like client, or ssh.client if names clash
+    fn console_script_run(&self, name: String, cmd: String, timeout: i32) -> Result<(i32, String)> {
+        self._script_run(cmd, Some(TextConsole::Named(name)), timeout)
+    }
+
+    fn console_assert_script_run(&self, name: String, cmd: String, timeout: i32) -> Result<String> {
+        self._assert_script_run(cmd, Some(TextConsole::Named(name)), timeout)
+    }
+
+    fn console_script_run_stream(
+        &self,
+        name: String,
+        cmd: String,
+        timeout: i32,
+        on_output: OnOutput,
+    ) -> Result<(i32, String)> {
+        self._script_run_stream(cmd, Some(TextConsole::Named(name)), timeout, on_output)
+    }
+
+    fn console_write(&self, name: String, s: String) -> Result<()> {
+        self._write(s, Some(TextConsole::Named(name)))
+    }
+
+    fn console_wait_string(&self, name: String, s: String, timeout: i32) -> Result<WaitMatch> {
+        self._wait_string(Some(TextConsole::Named(name)), s, timeout)
+    }
+
+    fn console_wait_regex(&self, name: String, pattern: String, timeout: i32) -> Result<WaitMatch> {
+        self._wait_match(
+            Some(TextConsole::Named(name)),
+            vec![pattern],
+            true,
+            1,
+            timeout,
+        )
+    }
+
+    fn console_wait_prompt(&self, name: String, timeout: i32) -> Result<()> {
+        self._wait_prompt(Some(TextConsole::Named(name)), timeout)
+    }
+
+    fn console_wait_ready(&self, name: String, timeout: i32) -> Result<()> {
+        self._wait_console_ready(Some(TextConsole::Named(name)), timeout)
+    }
+
+    // the ones below need a console of the right type, ssh or serial
+    fn console_ssh_script_run_seperate(
+        &self,
+        name: String,
+        cmd: String,
+        env: Vec<(String, String)>,
+        cwd: Option<String>,
+        timeout: i32,
+    ) -> Result<ExecResult> {
+        self._ssh_script_run_seperate(Some(TextConsole::Named(name)), cmd, env, cwd, timeout)
+    }
+
+    fn console_upload(
+        &self,
+        name: String,
+        local: String,
+        remote: String,
+        mode: Option<i32>,
+    ) -> Result<()> {
+        self._upload(Some(TextConsole::Named(name)), local, remote, mode)
+    }
+
+    fn console_download(&self, name: String, remote: String, local: String) -> Result<()> {
+        self._download(Some(TextConsole::Named(name)), remote, local)
+    }
+
+    fn console_remote_exists(&self, name: String, remote: String) -> Result<bool> {
+        self._remote_exists(Some(TextConsole::Named(name)), remote)
+    }
+
+    // job ids only mean something on the console that spawned them
+    fn console_spawn(&self, name: String, cmd: String) -> Result<JobId> {
+        self._spawn(Some(TextConsole::Named(name)), cmd)
+    }
+
+    fn console_job_status(&self, name: String, id: JobId) -> Result<JobStatus> {
+        self._job_status(Some(TextConsole::Named(name)), Job::Status(id))
+    }
+
+    fn console_job_wait(&self, name: String, id: JobId, timeout: i32) -> Result<JobStatus> {
+        self._job_status(
+            Some(TextConsole::Named(name)),
+            Job::Wait {
+                id,
+                timeout: Duration::from_secs(timeout as u64),
+            },
+        )
+    }
+
+    fn console_job_kill(&self, name: String, id: JobId) -> Result<JobStatus> {
+        self._job_status(Some(TextConsole::Named(name)), Job::Kill(id))
+    }
+
+    fn console_job_output(&self, name: String, id: JobId) -> Result<JobOutput> {
+        self._job_output(Some(TextConsole::Named(name)), id)
+    }
+
+    fn console_serial_upload(
+        &self,
+        name: String,
+        local: String,
+        remote: String,
+        mode: Option<i32>,
+        timeout: i32,
+    ) -> Result<()> {
+        self._serial_upload(Some(TextConsole::Named(name)), local, remote, mode, timeout)
+    }
+
+    fn console_serial_download(
+        &self,
+        name: String,
+        remote: String,
+        local: String,
+        timeout: i32,
+    ) -> Result<()> {
+        self._serial_download(Some(TextConsole::Named(name)), remote, local, timeout)
+    }
+
     // serial
     fn serial_script_run(&self, cmd: String, timeout: i32) -> Result<(i32, String)> {
         self._script_run(cmd, Some(TextConsole::Serial), timeout)
@@ -175,24 +475,121 @@ pub trait Api {
         self._write(s, Some(TextConsole::Serial))
     }
 
+    // file transfer over serial shell, mode defaults to mode of local file
+    fn serial_upload(
+        &self,
+        local: String,
+        remote: String,
+        mode: Option<i32>,
+        timeout: i32,
+    ) -> Result<()> {
+        self._serial_upload(None, local, remote, mode, timeout)
+    }
+
+    // relative local path is under log_dir
+    fn serial_download(&self, remote: String, local: String, timeout: i32) -> Result<()> {
+        self._serial_download(None, remote, local, timeout)
+    }
+
+    fn _serial_upload(
+        &self,
+        console: Option<TextConsole>,
+        local: String,
+        remote: String,
+        mode: Option<i32>,
+        timeout: i32,
+    ) -> Result<()> {
+        self._serial_transfer(
+            console,
+            SerialTransfer::Upload {
+                local: local.into(),
+                remote,
+                mode,
+                timeout: Duration::from_secs(timeout as u64),
+            },
+        )
+    }
+
+    fn _serial_download(
+        &self,
+        console: Option<TextConsole>,
+        remote: String,
+        local: String,
+        timeout: i32,
+    ) -> Result<()> {
+        self._serial_transfer(
+            console,
+            SerialTransfer::Download {
+                remote,
+                local: local.into(),
+                timeout: Duration::from_secs(timeout as u64),
+            },
+        )
+    }
+
+    fn _serial_transfer(&self, console: Option<TextConsole>, req: SerialTransfer) -> Result<()> {
+        match self.req(MsgReq::SerialTransfer { console, req })? {
+            MsgRes::Done => Ok(()),
+            MsgRes::Error(e) => Err(e.into()),
+            _ => Err(ApiError::ServerInvalidResponse),
+        }
+    }
+
+    // telnet
+    fn telnet_script_run(&self, cmd: String, timeout: i32) -> Result<(i32, String)> {
+        self._script_run(cmd, Some(TextConsole::Telnet), timeout)
+    }
+
+    fn telnet_assert_script_run(&self, cmd: String, timeout: i32) -> Result<String> {
+        self._assert_script_run(cmd, Some(TextConsole::Telnet), timeout)
+    }
+
+    fn telnet_write(&self, s: String) -> Result<()> {
+        self._write(s, Some(TextConsole::Telnet))
+    }
+
     // ssh
-    fn ssh_assert_script_run_seperate(&self, cmd: String, timeout: i32) -> Result<String> {
+    // run in a new channel with extra env and cwd, cmd is killed after timeout
+    fn ssh_script_run_seperate(
+        &self,
+        cmd: String,
+        env: Vec<(String, String)>,
+        cwd: Option<String>,
+        timeout: i32,
+    ) -> Result<ExecResult> {
+        self._ssh_script_run_seperate(None, cmd, env, cwd, timeout)
+    }
+
+    fn _ssh_script_run_seperate(
+        &self,
+        console: Option<TextConsole>,
+        cmd: String,
+        env: Vec<(String, String)>,
+        cwd: Option<String>,
+        timeout: i32,
+    ) -> Result<ExecResult> {
         match self.req(MsgReq::SSHScriptRunSeperate {
+            console,
             cmd,
+            env,
+            cwd,
             timeout: Duration::from_secs(timeout as u64),
         })? {
-            MsgRes::ScriptRun { code, value } => {
-                if code == 0 {
-                    Ok(value)
-                } else {
-                    Err(ApiError::AssertFailed)
-                }
-            }
+            MsgRes::ExecResult(res) => Ok(res),
             MsgRes::Error(e) => Err(e.into()),
             _ => Err(ApiError::ServerInvalidResponse),
         }
     }
 
+    fn ssh_assert_script_run_seperate(&self, cmd: String, timeout: i32) -> Result<String> {
+        let res = self.ssh_script_run_seperate(cmd, Vec::new(), None, timeout)?;
+        if res.code == 0 {
+            Ok(res.stdout)
+        } else {
+            Err(ApiError::AssertFailed)
+        }
+    }
+
     fn ssh_script_run(&self, cmd: String, timeout: i32) -> Result<(i32, String)> {
         self._script_run(cmd, Some(TextConsole::SSH), timeout)
     }
@@ -205,69 +602,178 @@ pub trait Api {
         self._write(s, Some(TextConsole::SSH))
     }
 
-    // vnc
-    fn vnc_check_screen(&self, tag: String, timeout: i32) -> Result<bool> {
-        match self.req(MsgReq::VNC(VNC::CheckScreen {
-            tag: tag.clone(),
-            threshold: 0.95,
-            timeout: Duration::from_secs(timeout as u64),
-            click: false,
-            r#move: false,
-            delay: None,
-        }))? {
-            MsgRes::Done => Ok(true),
-            MsgRes::Error(_) => Ok(false),
+    // file transfer over ssh, mode defaults to mode of local file
+    fn upload(&self, local: String, remote: String, mode: Option<i32>) -> Result<()> {
+        self._upload(None, local, remote, mode)
+    }
+
+    // relative local path is under log_dir
+    fn download(&self, remote: String, local: String) -> Result<()> {
+        self._download(None, remote, local)
+    }
+
+    fn remote_exists(&self, remote: String) -> Result<bool> {
+        self._remote_exists(None, remote)
+    }
+
+    fn _upload(
+        &self,
+        console: Option<TextConsole>,
+        local: String,
+        remote: String,
+        mode: Option<i32>,
+    ) -> Result<()> {
+        self._transfer(
+            console,
+            Transfer::Upload {
+                local: local.into(),
+                remote,
+                mode,
+            },
+        )
+    }
+
+    fn _download(&self, console: Option<TextConsole>, remote: String, local: String) -> Result<()> {
+        self._transfer(
+            console,
+            Transfer::Download {
+                remote,
+                local: local.into(),
+            },
+        )
+    }
+
+    fn _transfer(&self, console: Option<TextConsole>, req: Transfer) -> Result<()> {
+        match self.req(MsgReq::Transfer { console, req })? {
+            MsgRes::Done => Ok(()),
+            MsgRes::Error(e) => Err(e.into()),
             _ => Err(ApiError::ServerInvalidResponse),
         }
     }
 
-    fn vnc_assert_screen(&self, tag: String, timeout: i32) -> Result<()> {
-        if self.vnc_check_screen(tag, timeout)? {
-            Ok(())
-        } else {
-            Err(ApiError::AssertFailed)
+    fn _remote_exists(&self, console: Option<TextConsole>, remote: String) -> Result<bool> {
+        let req = Transfer::Exists(remote);
+        match self.req(MsgReq::Transfer { console, req })? {
+            MsgRes::Exists(b) => Ok(b),
+            MsgRes::Error(e) => Err(e.into()),
+            _ => Err(ApiError::ServerInvalidResponse),
         }
     }
 
-    fn vnc_check_and_click(&self, tag: String, timeout: i32) -> Result<bool> {
-        match self.req(MsgReq::VNC(VNC::CheckScreen {
-            tag: tag.clone(),
-            threshold: 0.95,
-            timeout: Duration::from_secs(timeout as u64),
-            click: true,
-            r#move: false,
-            delay: None,
-        }))? {
-            MsgRes::Done => Ok(true),
-            MsgRes::Error(_) => Ok(false),
+    // background jobs on ssh
+    fn spawn(&self, cmd: String) -> Result<JobId> {
+        self._spawn(None, cmd)
+    }
+
+    fn _spawn(&self, console: Option<TextConsole>, cmd: String) -> Result<JobId> {
+        let req = Job::Spawn(cmd);
+        match self.req(MsgReq::Job { console, req })? {
+            MsgRes::JobId(id) => Ok(id),
+            MsgRes::Error(e) => Err(e.into()),
             _ => Err(ApiError::ServerInvalidResponse),
         }
     }
 
-    fn vnc_assert_and_click(&self, tag: String, timeout: i32) -> Result<()> {
-        match self.vnc_check_and_click(tag, timeout)? {
-            true => Ok(()),
-            false => Err(ApiError::AssertFailed),
+    fn _job_status(&self, console: Option<TextConsole>, req: Job) -> Result<JobStatus> {
+        match self.req(MsgReq::Job { console, req })? {
+            MsgRes::JobStatus(status) => Ok(status),
+            MsgRes::Error(e) => Err(e.into()),
+            _ => Err(ApiError::ServerInvalidResponse),
+        }
+    }
+
+    fn job_status(&self, id: JobId) -> Result<JobStatus> {
+        self._job_status(None, Job::Status(id))
+    }
+
+    // Timeout if job is still running
+    fn job_wait(&self, id: JobId, timeout: i32) -> Result<JobStatus> {
+        self._job_status(
+            None,
+            Job::Wait {
+                id,
+                timeout: Duration::from_secs(timeout as u64),
+            },
+        )
+    }
+
+    fn job_kill(&self, id: JobId) -> Result<JobStatus> {
+        self._job_status(None, Job::Kill(id))
+    }
+
+    // everything the job printed so far
+    fn job_output(&self, id: JobId) -> Result<JobOutput> {
+        self._job_output(None, id)
+    }
+
+    fn _job_output(&self, console: Option<TextConsole>, id: JobId) -> Result<JobOutput> {
+        let req = Job::Output(id);
+        match self.req(MsgReq::Job { console, req })? {
+            MsgRes::JobOutput(output) => Ok(output),
+            MsgRes::Error(e) => Err(e.into()),
+            _ => Err(ApiError::ServerInvalidResponse),
         }
     }
 
-    fn vnc_check_and_move(&self, tag: String, timeout: i32) -> Result<bool> {
+    // vnc
+    // name of the best needle carrying any of tags, None if not found before timeout.
+    // missing or broken needles are errors
+    fn _check_screen(
+        &self,
+        tags: Vec<String>,
+        timeout: i32,
+        click: bool,
+        r#move: bool,
+    ) -> Result<Option<String>> {
         match self.req(MsgReq::VNC(VNC::CheckScreen {
-            tag: tag.clone(),
+            tags,
             threshold: 0.95,
             timeout: Duration::from_secs(timeout as u64),
-            click: false,
-            r#move: true,
+            click,
+            r#move,
             delay: None,
         }))? {
-            MsgRes::Done => Ok(true),
-            MsgRes::Error(_) => Ok(false),
+            MsgRes::Needle(name) => Ok(Some(name)),
+            // a broken needle is a bug of the case, anything else is just not matched
+            MsgRes::Error(e @ MsgResError::Needle(_)) => Err(e.into()),
+            MsgRes::Error(e) => {
+                warn!(msg = "check screen failed", reason = ?e);
+                Ok(None)
+            }
             _ => Err(ApiError::ServerInvalidResponse),
         }
     }
 
-    fn vnc_assert_and_move(&self, tag: String, timeout: i32) -> Result<()> {
-        match self.vnc_check_and_move(tag, timeout)? {
+    fn vnc_check_screen(&self, tags: Vec<String>, timeout: i32) -> Result<bool> {
+        self._check_screen(tags, timeout, false, false)
+            .map(|n| n.is_some())
+    }
+
+    // returns name of the matched needle
+    fn vnc_assert_screen(&self, tags: Vec<String>, timeout: i32) -> Result<String> {
+        self._check_screen(tags, timeout, false, false)?
+            .ok_or(ApiError::AssertFailed)
+    }
+
+    fn vnc_check_and_click(&self, tags: Vec<String>, timeout: i32) -> Result<bool> {
+        self._check_screen(tags, timeout, true, false)
+            .map(|n| n.is_some())
+    }
+
+    fn vnc_assert_and_click(&self, tags: Vec<String>, timeout: i32) -> Result<()> {
+        match self.vnc_check_and_click(tags, timeout)? {
+            true => Ok(()),
+            false => Err(ApiError::AssertFailed),
+        }
+    }
+
+    fn vnc_check_and_move(&self, tags: Vec<String>, timeout: i32) -> Result<bool> {
+        self._check_screen(tags, timeout, false, true)
+            .map(|n| n.is_some())
+    }
+
+    fn vnc_assert_and_move(&self, tags: Vec<String>, timeout: i32) -> Result<()> {
+        match self.vnc_check_and_move(tags, timeout)? {
             true => Ok(()),
             false => Err(ApiError::AssertFailed),
         }
@@ -368,4 +874,80 @@ pub trait Api {
             _ => Err(ApiError::ServerInvalidResponse),
         }
     }
+
+    // qemu
+    fn _qmp(&self, req: QMP) -> Result<()> {
+        match self.req(MsgReq::QMP(req))? {
+            MsgRes::Done => Ok(()),
+            MsgRes::Error(e) => Err(e.into()),
+            _ => Err(ApiError::ServerInvalidResponse),
+        }
+    }
+
+    fn qemu_system_reset(&self) -> Result<()> {
+        self._qmp(QMP::SystemReset)
+    }
+
+    fn qemu_savevm(&self, tag: String) -> Result<()> {
+        self._qmp(QMP::SaveVM(tag))
+    }
+
+    fn qemu_loadvm(&self, tag: String) -> Result<()> {
+        self._qmp(QMP::LoadVM(tag))
+    }
+
+    fn qemu_quit(&self) -> Result<()> {
+        self._qmp(QMP::Quit)
+    }
+
+    // power, timeout is secs to wait for boot on serial, 0 means don't wait
+    fn _power(&self, action: Power, timeout: i32) -> Result<()> {
+        let boot_timeout = (timeout > 0).then(|| Duration::from_secs(timeout as u64));
+        match self.req(MsgReq::Power {
+            action,
+            boot_timeout,
+        })? {
+            MsgRes::Done => Ok(()),
+            MsgRes::Error(e) => Err(e.into()),
+            _ => Err(ApiError::ServerInvalidResponse),
+        }
+    }
+
+    fn power_off(&self) -> Result<()> {
+        self._power(Power::Off, 0)
+    }
+
+    fn power_on(&self, timeout: i32) -> Result<()> {
+        self._power(Power::On, timeout)
+    }
+
+    fn power_reset(&self, timeout: i32) -> Result<()> {
+        self._power(Power::Reset, timeout)
+    }
+
+    fn power_cycle(&self, timeout: i32) -> Result<()> {
+        self._power(Power::Cycle, timeout)
+    }
+
+    // timeouts are secs of each stage, 0 skips it: boot pattern on serial, serial auto login,
+    // all ssh reconnected. return boot time in secs
+    fn reboot_and_wait(
+        &self,
+        cmd: Option<String>,
+        boot_timeout: i32,
+        login_timeout: i32,
+        ssh_timeout: i32,
+    ) -> Result<f64> {
+        let stage = |t: i32| (t > 0).then(|| Duration::from_secs(t as u64));
+        match self.req(MsgReq::Reboot {
+            cmd: cmd.unwrap_or("reboot".to_string()),
+            boot_timeout: stage(boot_timeout),
+            login_timeout: stage(login_timeout),
+            ssh_timeout: stage(ssh_timeout),
+        })? {
+            MsgRes::Rebooted { boot_time } => Ok(boot_time.as_secs_f64()),
+            MsgRes::Error(e) => Err(e.into()),
+            _ => Err(ApiError::ServerInvalidResponse),
+        }
+    }
 }
@@ -1,4 +1,20 @@
+use crate::{api::ApiTx, ScriptEngine};
+
 mod js;
+mod lua;
 mod perl;
 
 pub use js::JSEngine;
+pub use lua::LuaEngine;
+pub use perl::PerlEngine;
+
+/// pick script engine by case file extension
+pub fn new_engine(ext: &str, tx: ApiTx) -> Option<Box<dyn ScriptEngine>> {
+    let e: Box<dyn ScriptEngine> = match ext {
+        "js" => Box::new(JSEngine::new(tx)),
+        "lua" => Box::new(LuaEngine::new(tx)),
+        "pm" | "pl" => Box::new(PerlEngine::new(tx)),
+        _ => return None,
+    };
+    Some(e)
+}
@@ -1,23 +1,28 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{mpsc, Arc};
+use std::time::Duration;
 
 use crate::api::{Api, RustApi};
-use crate::{ApiError, MsgReq, MsgRes, ScriptEngine};
-use rquickjs::function::Args;
+use crate::msg::Job;
+use crate::report::{CaseReport, PhaseReport, Recorder, Status};
+use crate::{ApiError, MsgReq, MsgRes, ScriptEngine, TextConsole};
+use rquickjs::function::{Args, Opt};
 use rquickjs::Function;
-use rquickjs::{Context, Runtime};
+use rquickjs::{Context, Ctx, FromJs, Runtime};
 use serde::{Deserialize, Serialize};
 use tracing::{error, Level};
 
 pub struct JSEngine {
     _runtime: rquickjs::Runtime,
     context: rquickjs::Context,
+    recorder: Recorder,
 }
 
 impl ScriptEngine for JSEngine {
-    fn run_file(&mut self, content: &str) {
-        self.run_file(content).unwrap();
+    fn run_file(&mut self, content: &str) -> CaseReport {
+        self.run_file(content)
     }
 
     fn run_string(&mut self, content: &str) {
@@ -25,18 +30,56 @@ impl ScriptEngine for JSEngine {
     }
 }
 
+// a tag or an array of tags, needles carrying any of them match
+struct Tags(Vec<String>);
+
+impl<'js> FromJs<'js> for Tags {
+    fn from_js(ctx: &Ctx<'js>, value: rquickjs::Value<'js>) -> rquickjs::Result<Self> {
+        if value.is_array() {
+            Vec::<String>::from_js(ctx, value).map(Tags)
+        } else {
+            String::from_js(ctx, value).map(|s| Tags(vec![s]))
+        }
+    }
+}
+
 fn into_jserr(_: ApiError) -> rquickjs::Error {
     rquickjs::Error::Exception
 }
 
+// output of a failed cmd is dropped, exception thrown by on_output is passed to caller
+fn script_run(
+    api: &RustApi,
+    console: Option<TextConsole>,
+    cmd: String,
+    timeout: i32,
+    on_output: Option<Function>,
+) -> rquickjs::Result<Option<String>> {
+    let Some(on_output) = on_output else {
+        return Ok(api._script_run(cmd, console, timeout).map(|v| v.1).ok());
+    };
+    let mut callback_err = None;
+    let res = api._script_run_stream(cmd, console, timeout, &mut |chunk: String| {
+        on_output.call::<_, ()>((chunk,)).map_err(|e| {
+            callback_err = Some(e);
+            ApiError::Interrupt
+        })
+    });
+    match callback_err {
+        Some(e) => Err(e),
+        None => Ok(res.map(|v| v.1).ok()),
+    }
+}
+
 impl JSEngine {
     pub fn new(tx: mpsc::Sender<(MsgReq, mpsc::Sender<MsgRes>)>) -> Self {
         let runtime = Runtime::new().unwrap();
         let context = Context::full(&runtime).unwrap();
+        let recorder = Recorder::default();
 
         context
             .with(|ctx| -> Result<(), ()> {
-                let rustapi = Arc::new(RustApi::new(tx));
+                let rustapi = Arc::new(RustApi::with_recorder(tx, recorder.clone()));
 
                 // general
                 let api = rustapi.clone();
@@ -84,17 +127,111 @@ impl JSEngine {
                         }),
                     )
                     .unwrap();
+                // console("client") is a console by name from config, console.log still logs
                 ctx.eval(
                     r#"
-                        var console = Object.freeze({
+                        var console = Object.freeze(Object.assign(function (name) {
+                            return Object.freeze(Object.assign(__ssh_serial_functions__(name), {
+                                script_run(cmd, timeout, on_output) {
+                                    return __rust_console_script_run__(name, cmd, timeout, on_output);
+                                },
+                                assert_script_run(cmd, timeout) {
+                                    return __rust_console_assert_script_run__(name, cmd, timeout);
+                                },
+                                write(s) { __rust_console_write__(name, s) },
+                                writeln(s) { __rust_console_write__(name, s + "\n") },
+                                wait_string(s, timeout) {
+                                    return JSON.parse(__rust_wait__([s], false, 1, timeout, name));
+                                },
+                                wait_regex(pattern, timeout) {
+                                    return JSON.parse(__rust_wait__([pattern], true, 1, timeout, name));
+                                },
+                                wait_prompt(timeout) { __rust_console_wait_prompt__(name, timeout) },
+                                wait_ready(timeout) { __rust_console_wait_ready__(name, timeout) },
+                            }));
+                        }, {
                             log(data){__rust_log__("log",JSON.stringify(data))},
                             info(data){__rust_log__("info",JSON.stringify(data))},
                             error(data){__rust_log__("error",JSON.stringify(data))},
                             debug(data){__rust_log__("debug",JSON.stringify(data))},
-                        });"#,
+                        }));"#,
                 )
                 .map_err(|_| ())?;
 
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_console_script_run__",
+                        Function::new(
+                            ctx.clone(),
+                            move |name: String,
+                                  cmd: String,
+                                  timeout: i32,
+                                  on_output: Option<Function>|
+                                  -> rquickjs::Result<Option<String>> {
+                                let console = Some(TextConsole::Named(name));
+                                script_run(&api, console, cmd, timeout, on_output)
+                            },
+                        ),
+                    )
+                    .unwrap();
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_console_assert_script_run__",
+                        Function::new(
+                            ctx.clone(),
+                            move |name: String,
+                                  cmd: String,
+                                  timeout: i32|
+                                  -> rquickjs::Result<String> {
+                                api.console_assert_script_run(name, cmd, timeout)
+                                    .map_err(into_jserr)
+                            },
+                        ),
+                    )
+                    .unwrap();
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_console_write__",
+                        Function::new(
+                            ctx.clone(),
+                            move |name: String, s: String| -> rquickjs::Result<()> {
+                                api.console_write(name, s).map_err(into_jserr)
+                            },
+                        ),
+                    )
+                    .unwrap();
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_console_wait_prompt__",
+                        Function::new(
+                            ctx.clone(),
+                            move |name: String, timeout: i32| -> rquickjs::Result<()> {
+                                api.console_wait_prompt(name, timeout).map_err(into_jserr)
+                            },
+                        ),
+                    )
+                    .unwrap();
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_console_wait_ready__",
+                        Function::new(
+                            ctx.clone(),
+                            move |name: String, timeout: i32| -> rquickjs::Result<()> {
+                                api.console_wait_ready(name, timeout).map_err(into_jserr)
+                            },
+                        ),
+                    )
+                    .unwrap();
+
                 // general console
                 let api = rustapi.clone();
                 ctx.globals()
@@ -116,8 +253,11 @@ impl JSEngine {
                         "script_run",
                         Function::new(
                             ctx.clone(),
-                            move |cmd: String, timeout: i32| -> Option<String> {
-                                api.script_run(cmd, timeout).map(|v| v.1).ok()
+                            move |cmd: String,
+                                  timeout: i32,
+                                  on_output: Opt<Function>|
+                                  -> rquickjs::Result<Option<String>> {
+                                script_run(&api, None, cmd, timeout, on_output.0)
                             },
                         ),
                     )
@@ -143,18 +283,77 @@ impl JSEngine {
                     )
                     .unwrap();
 
+                // match result is passed as json, wrapped into objects below
                 let api = rustapi.clone();
                 ctx.globals()
                     .set(
-                        "wait_string",
+                        "__rust_wait__",
                         Function::new(
                             ctx.clone(),
-                            move |s: String, timeout: i32| -> rquickjs::Result<()> {
-                                api.wait_string(s, timeout).map_err(into_jserr)
+                            move |patterns: Vec<String>,
+                                  regex: bool,
+                                  n: i32,
+                                  timeout: i32,
+                                  console: Opt<String>|
+                                  -> rquickjs::Result<String> {
+                                let console = console.0.map(TextConsole::Named);
+                                let m = api
+                                    ._wait_match(console, patterns, regex, n as usize, timeout)
+                                    .map_err(into_jserr)?;
+                                Ok(serde_json::json!({
+                                    "index": m.index,
+                                    "text": m.text,
+                                    "groups": m.groups,
+                                })
+                                .to_string())
                             },
                         ),
                     )
                     .unwrap();
+                ctx.eval(
+                    r#"
+                        function wait_string(s, timeout) {
+                            return JSON.parse(__rust_wait__([s], false, 1, timeout));
+                        }
+                        function wait_regex(pattern, timeout) {
+                            return JSON.parse(__rust_wait__([pattern], true, 1, timeout));
+                        }
+                        function wait_any(patterns, timeout, regex) {
+                            return JSON.parse(__rust_wait__(patterns, !!regex, 1, timeout));
+                        }
+                        function assert_wait_string_ntimes(s, n, timeout) {
+                            return JSON.parse(__rust_wait__([s], false, n, timeout));
+                        }
+                        function wait_string_ntimes(s, n, timeout) {
+                            try {
+                                assert_wait_string_ntimes(s, n, timeout);
+                                return true;
+                            } catch (e) {
+                                return false;
+                            }
+                        }"#,
+                )
+                .map_err(|_| ())?;
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "wait_prompt",
+                        Function::new(ctx.clone(), move |timeout| -> rquickjs::Result<()> {
+                            api.wait_prompt(timeout).map_err(into_jserr)
+                        }),
+                    )
+                    .unwrap();
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "wait_console_ready",
+                        Function::new(ctx.clone(), move |timeout| -> rquickjs::Result<()> {
+                            api.wait_console_ready(timeout).map_err(into_jserr)
+                        }),
+                    )
+                    .unwrap();
 
                 let api = rustapi.clone();
                 ctx.globals()
@@ -202,6 +401,43 @@ impl JSEngine {
                     )
                     .unwrap();
 
+                // env is passed as json, result is wrapped into object below
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_exec_seperate__",
+                        Function::new(
+                            ctx.clone(),
+                            move |cmd: String,
+                                  timeout: i32,
+                                  env: String,
+                                  cwd: String,
+                                  console: Option<String>|
+                                  -> rquickjs::Result<String> {
+                                let env: HashMap<String, String> =
+                                    serde_json::from_str(&env).map_err(|_| {
+                                        rquickjs::Error::new_from_js("env", "object of strings")
+                                    })?;
+                                let cwd = (!cwd.is_empty()).then_some(cwd);
+                                let res = api
+                                    ._ssh_script_run_seperate(
+                                        console.map(TextConsole::Named),
+                                        cmd,
+                                        env.into_iter().collect(),
+                                        cwd,
+                                        timeout,
+                                    )
+                                    .map_err(into_jserr)?;
+                                Ok(serde_json::json!({
+                                    "code": res.code,
+                                    "stdout": res.stdout,
+                                    "stderr": res.stderr,
+                                })
+                                .to_string())
+                            },
+                        ),
+                    )
+                    .unwrap();
                 let api = rustapi.clone();
                 ctx.globals()
                     .set(
@@ -226,6 +462,114 @@ impl JSEngine {
                     )
                     .unwrap();
 
+                // file transfer over ssh
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_upload__",
+                        Function::new(
+                            ctx.clone(),
+                            move |local: String,
+                                  remote: String,
+                                  mode: Option<i32>,
+                                  console: Option<String>|
+                                  -> rquickjs::Result<()> {
+                                let console = console.map(TextConsole::Named);
+                                api._upload(console, local, remote, mode)
+                                    .map_err(into_jserr)
+                            },
+                        ),
+                    )
+                    .unwrap();
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_download__",
+                        Function::new(
+                            ctx.clone(),
+                            move |remote: String,
+                                  local: String,
+                                  console: Option<String>|
+                                  -> rquickjs::Result<()> {
+                                let console = console.map(TextConsole::Named);
+                                api._download(console, remote, local).map_err(into_jserr)
+                            },
+                        ),
+                    )
+                    .unwrap();
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_remote_exists__",
+                        Function::new(
+                            ctx.clone(),
+                            move |remote: String,
+                                  console: Option<String>|
+                                  -> rquickjs::Result<bool> {
+                                let console = console.map(TextConsole::Named);
+                                api._remote_exists(console, remote).map_err(into_jserr)
+                            },
+                        ),
+                    )
+                    .unwrap();
+
+                // background jobs on ssh
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_spawn__",
+                        Function::new(
+                            ctx.clone(),
+                            move |cmd: String, console: Option<String>| -> rquickjs::Result<u32> {
+                                api._spawn(console.map(TextConsole::Named), cmd)
+                                    .map_err(into_jserr)
+                            },
+                        ),
+                    )
+                    .unwrap();
+
+                // job status and output are passed as json, wrapped into objects below
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_job__",
+                        Function::new(
+                            ctx.clone(),
+                            move |action: String,
+                                  id: u32,
+                                  timeout: i32,
+                                  console: Option<String>|
+                                  -> rquickjs::Result<String> {
+                                let console = console.map(TextConsole::Named);
+                                let timeout = Duration::from_secs(timeout as u64);
+                                let status = match action.as_str() {
+                                    "status" => api._job_status(console, Job::Status(id)),
+                                    "wait" => api._job_status(console, Job::Wait { id, timeout }),
+                                    "kill" => api._job_status(console, Job::Kill(id)),
+                                    _ => {
+                                        let output =
+                                            api._job_output(console, id).map_err(into_jserr)?;
+                                        return Ok(serde_json::json!({
+                                            "stdout": output.stdout,
+                                            "stderr": output.stderr,
+                                        })
+                                        .to_string());
+                                    }
+                                }
+                                .map_err(into_jserr)?;
+                                Ok(serde_json::json!({
+                                    "state": status.name(),
+                                    "code": status.code(),
+                                    "signal": status.signal(),
+                                })
+                                .to_string())
+                            },
+                        ),
+                    )
+                    .unwrap();
+
                 // serial
 
                 let api = rustapi.clone();
@@ -265,6 +609,84 @@ impl JSEngine {
                     )
                     .unwrap();
 
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_serial_upload__",
+                        Function::new(
+                            ctx.clone(),
+                            move |local: String,
+                                  remote: String,
+                                  timeout: i32,
+                                  mode: Option<i32>,
+                                  console: Option<String>|
+                                  -> rquickjs::Result<()> {
+                                let console = console.map(TextConsole::Named);
+                                api._serial_upload(console, local, remote, mode, timeout)
+                                    .map_err(into_jserr)
+                            },
+                        ),
+                    )
+                    .unwrap();
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "__rust_serial_download__",
+                        Function::new(
+                            ctx.clone(),
+                            move |remote: String,
+                                  local: String,
+                                  timeout: i32,
+                                  console: Option<String>|
+                                  -> rquickjs::Result<()> {
+                                let console = console.map(TextConsole::Named);
+                                api._serial_download(console, remote, local, timeout)
+                                    .map_err(into_jserr)
+                            },
+                        ),
+                    )
+                    .unwrap();
+
+                // functions only ssh or serial can do, as globals on the default console and
+                // on console(name)
+                ctx.eval(
+                    r#"
+                        function __ssh_serial_functions__(name) {
+                            return {
+                                ssh_script_run_seperate(cmd, timeout, opts) {
+                                    opts = opts || {};
+                                    let env = JSON.stringify(opts.env || {});
+                                    return JSON.parse(__rust_exec_seperate__(cmd, timeout, env, opts.cwd || "", name));
+                                },
+                                upload(local, remote, mode) { __rust_upload__(local, remote, mode, name) },
+                                download(remote, local) { __rust_download__(remote, local, name) },
+                                remote_exists(remote) { return __rust_remote_exists__(remote, name) },
+                                spawn(cmd) { return __rust_spawn__(cmd, name) },
+                                job_status(id) {
+                                    return JSON.parse(__rust_job__("status", id, 0, name));
+                                },
+                                job_wait(id, timeout) {
+                                    return JSON.parse(__rust_job__("wait", id, timeout, name));
+                                },
+                                job_kill(id) {
+                                    return JSON.parse(__rust_job__("kill", id, 0, name));
+                                },
+                                job_output(id) {
+                                    return JSON.parse(__rust_job__("output", id, 0, name));
+                                },
+                                serial_upload(local, remote, timeout, mode) {
+                                    __rust_serial_upload__(local, remote, timeout, mode, name)
+                                },
+                                serial_download(remote, local, timeout) {
+                                    __rust_serial_download__(remote, local, timeout, name)
+                                },
+                            };
+                        }
+                        Object.assign(globalThis, __ssh_serial_functions__(null));"#,
+                )
+                .map_err(|_| ())?;
+
                 // vnc
 
                 let api = rustapi.clone();
@@ -273,8 +695,8 @@ impl JSEngine {
                         "assert_screen",
                         Function::new(
                             ctx.clone(),
-                            move |tag: String, timeout: i32| -> rquickjs::Result<()> {
-                                api.vnc_assert_screen(tag.clone(), timeout)
+                            move |tags: Tags, timeout: i32| -> rquickjs::Result<String> {
+                                api.vnc_assert_screen(tags.0, timeout)
                                     .map_err(into_jserr)
                             },
                         ),
@@ -287,8 +709,8 @@ impl JSEngine {
                         "check_screen",
                         Function::new(
                             ctx.clone(),
-                            move |tag: String, timeout: i32| -> rquickjs::Result<bool> {
-                                api.vnc_check_screen(tag.clone(), timeout)
+                            move |tags: Tags, timeout: i32| -> rquickjs::Result<bool> {
+                                api.vnc_check_screen(tags.0, timeout)
                                     .map_err(into_jserr)
                             },
                         ),
@@ -301,8 +723,8 @@ impl JSEngine {
                         "assert_and_click",
                         Function::new(
                             ctx.clone(),
-                            move |tag: String, timeout: i32| -> rquickjs::Result<()> {
-                                api.vnc_assert_and_click(tag.clone(), timeout)
+                            move |tags: Tags, timeout: i32| -> rquickjs::Result<()> {
+                                api.vnc_assert_and_click(tags.0, timeout)
                                     .map_err(into_jserr)
                             },
                         ),
@@ -315,8 +737,8 @@ impl JSEngine {
                         "check_and_click",
                         Function::new(
                             ctx.clone(),
-                            move |tag: String, timeout: i32| -> rquickjs::Result<bool> {
-                                api.vnc_check_and_click(tag.clone(), timeout)
+                            move |tags: Tags, timeout: i32| -> rquickjs::Result<bool> {
+                                api.vnc_check_and_click(tags.0, timeout)
                                     .map_err(into_jserr)
                             },
                         ),
@@ -329,8 +751,8 @@ impl JSEngine {
                         "assert_and_move",
                         Function::new(
                             ctx.clone(),
-                            move |tag: String, timeout: i32| -> rquickjs::Result<()> {
-                                api.vnc_assert_and_move(tag.clone(), timeout)
+                            move |tags: Tags, timeout: i32| -> rquickjs::Result<()> {
+                                api.vnc_assert_and_move(tags.0, timeout)
                                     .map_err(into_jserr)
                             },
                         ),
@@ -342,8 +764,8 @@ impl JSEngine {
                         "check_and_move",
                         Function::new(
                             ctx.clone(),
-                            move |tag: String, timeout: i32| -> rquickjs::Result<bool> {
-                                api.vnc_check_and_move(tag.clone(), timeout)
+                            move |tags: Tags, timeout: i32| -> rquickjs::Result<bool> {
+                                api.vnc_check_and_move(tags.0, timeout)
                                     .map_err(into_jserr)
                             },
                         ),
@@ -389,6 +811,65 @@ impl JSEngine {
                     )
                     .unwrap();
 
+                // power, timeout is secs to wait for boot on serial
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "power_off",
+                        Function::new(ctx.clone(), move || -> rquickjs::Result<()> {
+                            api.power_off().map_err(into_jserr)
+                        }),
+                    )
+                    .unwrap();
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "power_on",
+                        Function::new(ctx.clone(), move |timeout| -> rquickjs::Result<()> {
+                            api.power_on(timeout).map_err(into_jserr)
+                        }),
+                    )
+                    .unwrap();
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "power_reset",
+                        Function::new(ctx.clone(), move |timeout| -> rquickjs::Result<()> {
+                            api.power_reset(timeout).map_err(into_jserr)
+                        }),
+                    )
+                    .unwrap();
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "power_cycle",
+                        Function::new(ctx.clone(), move |timeout| -> rquickjs::Result<()> {
+                            api.power_cycle(timeout).map_err(into_jserr)
+                        }),
+                    )
+                    .unwrap();
+
+                let api = rustapi.clone();
+                ctx.globals()
+                    .set(
+                        "reboot_and_wait",
+                        Function::new(
+                            ctx.clone(),
+                            move |boot_timeout: i32,
+                                  login_timeout: i32,
+                                  ssh_timeout: i32,
+                                  cmd: Opt<String>|
+                                  -> rquickjs::Result<f64> {
+                                api.reboot_and_wait(cmd.0, boot_timeout, login_timeout, ssh_timeout)
+                                    .map_err(into_jserr)
+                            },
+                        ),
+                    )
+                    .unwrap();
+
                 let api = rustapi.clone();
                 ctx.globals()
                     .set(
@@ -416,6 +897,7 @@ impl JSEngine {
         Self {
             _runtime: runtime,
             context,
+            recorder,
         }
     }
 
@@ -449,58 +931,80 @@ impl JSEngine {
         })
     }
 
-    pub fn run_file(&mut self, file: &str) -> Result<(), String> {
+    pub fn run_file(&mut self, file: &str) -> CaseReport {
+        let mut report = CaseReport::new(file);
         let base_folder = Path::new(file).parent().unwrap();
         let filename = Path::new(file).file_name().unwrap().to_str().unwrap();
-        let script = fs::read_to_string(file).unwrap();
+        let script = match fs::read_to_string(file) {
+            Ok(script) => script,
+            Err(e) => return report.fail(format!("case file read failed: [{}]", e)),
+        };
         let pre_libs = search_path(&script);
+        let recorder = self.recorder.clone();
         self.context.with(|ctx| {
             for path in pre_libs {
                 let mut fullpath = PathBuf::new();
                 fullpath.push(base_folder);
                 fullpath.push(&path);
-                let _ = ctx
-                    .clone()
-                    .compile(path.as_str(), fs::read_to_string(fullpath).unwrap())
-                    .map_err(|e| {
-                        format!("lib file: [{}] compile failed: [{}]", path.as_str(), e)
-                    })?;
+                let lib = fs::read_to_string(fullpath)
+                    .map_err(|e| e.to_string())
+                    .and_then(|lib| {
+                        ctx.clone()
+                            .compile(path.as_str(), lib)
+                            .map_err(|e| e.to_string())
+                    });
+                if let Err(e) = lib {
+                    let msg = format!("lib file: [{}] compile failed: [{}]", path.as_str(), e);
+                    error!(msg);
+                    return report.fail(msg);
+                }
             }
-            let module_entry = ctx
-                .clone()
-                .compile(format!("./{filename}"), script)
-                .map_err(|e| format!("entry file compile failed: [{}]", e))?;
+            let module_entry = match ctx.clone().compile(format!("./{filename}"), script) {
+                Ok(module_entry) => module_entry,
+                Err(e) => {
+                    let msg = format!("entry file compile failed: [{}]", e);
+                    error!(msg);
+                    return report.fail(msg);
+                }
+            };
 
             let Ok(main) = module_entry
                 .get("main")
                 .unwrap_or_else(|_| module_entry.get::<&str, Function>("run"))
             else {
-                return Err(r#"function "main" or "run" must exists"#.to_string());
+                return report.fail(r#"function "main" or "run" must exists"#);
             };
 
-            // try run prehook, return if run failed
+            let call = |name: &str, f: Function| {
+                recorder.phase(name, || {
+                    f.call_arg::<()>(Args::new(ctx.clone(), 0)).map_err(|e| {
+                        error!("{} run failed: {}", name, e);
+                        e.to_string()
+                    })
+                })
+            };
+
+            // try run prehook, skip main and afterhook if failed
             if let Ok(prehook) = module_entry.get::<&str, Function>("prehook") {
-                if let Err(e) = prehook.call_arg::<()>(Args::new(ctx.clone(), 0)) {
-                    let msg = format!("prehook run failed: {}", e);
-                    error!(msg);
-                    return Err(msg);
+                let phase = call("prehook", prehook);
+                let failed = phase.status == Status::Failed;
+                report.push_phase(phase);
+                if failed {
+                    report.push_phase(PhaseReport::skipped("main"));
+                    report.push_phase(PhaseReport::skipped("afterhook"));
+                    return report;
                 }
             }
 
             // continue if failed
-            if let Err(e) = main.call_arg::<()>(Args::new(ctx.clone(), 0)) {
-                error!("main run failed: {}", e)
-            }
+            report.push_phase(call("main", main));
 
             // try run afterhook
             if let Ok(afterhook) = module_entry.get::<&str, Function>("afterhook") {
-                if let Err(e) = afterhook.call_arg::<()>(Args::new(ctx.clone(), 0)) {
-                    error!("afterhook run failed: {}", e);
-                }
+                report.push_phase(call("afterhook", afterhook));
             }
-            Ok(())
-        })?;
-        Ok(())
+            report
+        })
     }
 }
 
@@ -0,0 +1,994 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use crate::api::{Api, RustApi};
+use crate::msg::{Job, JobStatus};
+use crate::report::{CaseReport, PhaseReport, Recorder, Status};
+use crate::{ApiError, MsgReq, MsgRes, ScriptEngine, TextConsole, WaitMatch};
+use mlua::{FromLua, Function, Lua, Table, Value};
+use tracing::{error, Level};
+
+pub struct LuaEngine {
+    lua: Lua,
+    recorder: Recorder,
+}
+
+impl ScriptEngine for LuaEngine {
+    fn run_file(&mut self, content: &str) -> CaseReport {
+        self.run_file(content)
+    }
+
+    fn run_string(&mut self, content: &str) {
+        self.run_string(content).unwrap();
+    }
+}
+
+// index is 1-based like everything else in lua
+fn match_table(lua: &Lua, m: WaitMatch) -> mlua::Result<Table<'_>> {
+    let t = lua.create_table()?;
+    t.set("index", m.index + 1)?;
+    t.set("text", m.text)?;
+    let groups = lua.create_table()?;
+    for (i, g) in m.groups.into_iter().enumerate() {
+        groups.set(i + 1, g)?;
+    }
+    t.set("groups", groups)?;
+    Ok(t)
+}
+
+fn job_status_table(lua: &Lua, s: JobStatus) -> mlua::Result<Table<'_>> {
+    let t = lua.create_table()?;
+    t.set("state", s.name())?;
+    t.set("code", s.code())?;
+    t.set("signal", s.signal())?;
+    Ok(t)
+}
+
+// a tag or a list of tags, needles carrying any of them match
+struct Tags(Vec<String>);
+
+impl<'lua> FromLua<'lua> for Tags {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        match value {
+            Value::Table(_) => Vec::<String>::from_lua(value, lua).map(Tags),
+            v => String::from_lua(v, lua).map(|s| Tags(vec![s])),
+        }
+    }
+}
+
+fn into_luaerr(e: ApiError) -> mlua::Error {
+    mlua::Error::external(e)
+}
+
+// returns output first, error raised by on_output is passed to caller
+fn script_run(
+    api: &RustApi,
+    console: Option<TextConsole>,
+    cmd: String,
+    timeout: i32,
+    on_output: Option<Function>,
+) -> mlua::Result<(String, i32)> {
+    let Some(on_output) = on_output else {
+        return api
+            ._script_run(cmd, console, timeout)
+            .map(|(code, value)| (value, code))
+            .map_err(into_luaerr);
+    };
+    let mut callback_err = None;
+    let res = api._script_run_stream(cmd, console, timeout, &mut |chunk: String| {
+        on_output.call::<_, ()>(chunk).map_err(|e| {
+            callback_err = Some(e);
+            ApiError::Interrupt
+        })
+    });
+    match callback_err {
+        Some(e) => Err(e),
+        None => res.map(|(code, value)| (value, code)).map_err(into_luaerr),
+    }
+}
+
+// same functions as the globals, on the console with this name
+fn console_table<'lua>(
+    lua: &'lua Lua,
+    rustapi: &Arc<RustApi>,
+    name: String,
+) -> mlua::Result<Table<'lua>> {
+    let t = lua.create_table()?;
+
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "script_run",
+        lua.create_function(
+            move |_, (cmd, timeout, on_output): (String, i32, Option<Function>)| {
+                script_run(
+                    &api,
+                    Some(TextConsole::Named(n.clone())),
+                    cmd,
+                    timeout,
+                    on_output,
+                )
+            },
+        )?,
+    )?;
+
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "assert_script_run",
+        lua.create_function(move |_, (cmd, timeout): (String, i32)| {
+            api.console_assert_script_run(n.clone(), cmd, timeout)
+                .map_err(into_luaerr)
+        })?,
+    )?;
+
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "write",
+        lua.create_function(move |_, s: String| {
+            api.console_write(n.clone(), s).map_err(into_luaerr)
+        })?,
+    )?;
+
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "wait_string",
+        lua.create_function(move |lua, (s, timeout): (String, i32)| {
+            let m = api
+                .console_wait_string(n.clone(), s, timeout)
+                .map_err(into_luaerr)?;
+            match_table(lua, m)
+        })?,
+    )?;
+
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "wait_prompt",
+        lua.create_function(move |_, timeout: i32| {
+            api.console_wait_prompt(n.clone(), timeout)
+                .map_err(into_luaerr)
+        })?,
+    )?;
+
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "wait_ready",
+        lua.create_function(move |_, timeout: i32| {
+            api.console_wait_ready(n.clone(), timeout)
+                .map_err(into_luaerr)
+        })?,
+    )?;
+
+    ssh_serial_functions(lua, &t, rustapi, Some(name))?;
+    Ok(t)
+}
+
+// functions only ssh or serial can do, on the default console if no name given
+fn ssh_serial_functions(
+    lua: &Lua,
+    t: &Table,
+    rustapi: &Arc<RustApi>,
+    name: Option<String>,
+) -> mlua::Result<()> {
+    let console = |n: &Option<String>| n.clone().map(TextConsole::Named);
+
+    // opts is { env = { K = "v" }, cwd = "/tmp" }, both optional
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "ssh_script_run_seperate",
+        lua.create_function(
+            move |lua, (cmd, timeout, opts): (String, i32, Option<Table>)| {
+                let (env, cwd) = match opts {
+                    Some(opts) => (
+                        opts.get::<_, Option<HashMap<String, String>>>("env")?,
+                        opts.get::<_, Option<String>>("cwd")?,
+                    ),
+                    None => (None, None),
+                };
+                let env = env.unwrap_or_default().into_iter().collect();
+                let res = api
+                    ._ssh_script_run_seperate(console(&n), cmd, env, cwd, timeout)
+                    .map_err(into_luaerr)?;
+                let t = lua.create_table()?;
+                t.set("code", res.code)?;
+                t.set("stdout", res.stdout)?;
+                t.set("stderr", res.stderr)?;
+                Ok(t)
+            },
+        )?,
+    )?;
+
+    // file transfer over ssh
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "upload",
+        lua.create_function(
+            move |_, (local, remote, mode): (String, String, Option<i32>)| {
+                api._upload(console(&n), local, remote, mode)
+                    .map_err(into_luaerr)
+            },
+        )?,
+    )?;
+
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "download",
+        lua.create_function(move |_, (remote, local): (String, String)| {
+            api._download(console(&n), remote, local)
+                .map_err(into_luaerr)
+        })?,
+    )?;
+
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "remote_exists",
+        lua.create_function(move |_, remote: String| {
+            api._remote_exists(console(&n), remote).map_err(into_luaerr)
+        })?,
+    )?;
+
+    // background jobs on ssh
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "spawn",
+        lua.create_function(move |_, cmd: String| {
+            api._spawn(console(&n), cmd).map_err(into_luaerr)
+        })?,
+    )?;
+
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "job_status",
+        lua.create_function(move |lua, id: u32| {
+            let s = api
+                ._job_status(console(&n), Job::Status(id))
+                .map_err(into_luaerr)?;
+            job_status_table(lua, s)
+        })?,
+    )?;
+
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "job_wait",
+        lua.create_function(move |lua, (id, timeout): (u32, i32)| {
+            let timeout = Duration::from_secs(timeout as u64);
+            let s = api
+                ._job_status(console(&n), Job::Wait { id, timeout })
+                .map_err(into_luaerr)?;
+            job_status_table(lua, s)
+        })?,
+    )?;
+
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "job_kill",
+        lua.create_function(move |lua, id: u32| {
+            let s = api
+                ._job_status(console(&n), Job::Kill(id))
+                .map_err(into_luaerr)?;
+            job_status_table(lua, s)
+        })?,
+    )?;
+
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "job_output",
+        lua.create_function(move |lua, id: u32| {
+            let output = api._job_output(console(&n), id).map_err(into_luaerr)?;
+            let t = lua.create_table()?;
+            t.set("stdout", output.stdout)?;
+            t.set("stderr", output.stderr)?;
+            Ok(t)
+        })?,
+    )?;
+
+    // file transfer over serial shell
+    let (api, n) = (rustapi.clone(), name.clone());
+    t.set(
+        "serial_upload",
+        lua.create_function(
+            move |_, (local, remote, timeout, mode): (String, String, i32, Option<i32>)| {
+                api._serial_upload(console(&n), local, remote, mode, timeout)
+                    .map_err(into_luaerr)
+            },
+        )?,
+    )?;
+
+    let (api, n) = (rustapi.clone(), name);
+    t.set(
+        "serial_download",
+        lua.create_function(move |_, (remote, local, timeout): (String, String, i32)| {
+            api._serial_download(console(&n), remote, local, timeout)
+                .map_err(into_luaerr)
+        })?,
+    )?;
+    Ok(())
+}
+
+impl LuaEngine {
+    pub fn new(tx: mpsc::Sender<(MsgReq, mpsc::Sender<MsgRes>)>) -> Self {
+        let lua = Lua::new();
+        let recorder = Recorder::default();
+        let rustapi = Arc::new(RustApi::with_recorder(tx, recorder.clone()));
+
+        Self::register(&lua, rustapi).unwrap();
+
+        Self { lua, recorder }
+    }
+
+    fn register(lua: &Lua, rustapi: Arc<RustApi>) -> mlua::Result<()> {
+        let globals = lua.globals();
+
+        // general
+        let api = rustapi.clone();
+        globals.set(
+            "print",
+            lua.create_function(move |_, msg: String| {
+                api.print(Level::INFO, msg);
+                Ok(())
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "sleep",
+            lua.create_function(move |_, s: i32| {
+                api.sleep(s as u64);
+                Ok(())
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "get_env",
+            lua.create_function(move |_, key: String| api.get_env(key).map_err(into_luaerr))?,
+        )?;
+
+        // general console
+        let api = rustapi.clone();
+        globals.set(
+            "assert_script_run",
+            lua.create_function(move |_, (cmd, timeout): (String, i32)| {
+                api.assert_script_run(cmd, timeout).map_err(into_luaerr)
+            })?,
+        )?;
+
+        // returns output first, so `local out = script_run(...)` works like js
+        let api = rustapi.clone();
+        globals.set(
+            "script_run",
+            lua.create_function(
+                move |_, (cmd, timeout, on_output): (String, i32, Option<Function>)| {
+                    script_run(&api, None, cmd, timeout, on_output)
+                },
+            )?,
+        )?;
+
+        // console by name from config, like console("client").script_run(...)
+        let api = rustapi.clone();
+        globals.set(
+            "console",
+            lua.create_function(move |lua, name: String| console_table(lua, &api, name))?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "write",
+            lua.create_function(move |_, s: String| api.write(s).map_err(into_luaerr))?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "writeln",
+            lua.create_function(move |_, s: String| {
+                api.write(format!("{s}\n")).map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "wait_string",
+            lua.create_function(move |lua, (s, timeout): (String, i32)| {
+                let m = api.wait_string(s, timeout).map_err(into_luaerr)?;
+                match_table(lua, m)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "wait_regex",
+            lua.create_function(move |lua, (pattern, timeout): (String, i32)| {
+                let m = api.wait_regex(pattern, timeout).map_err(into_luaerr)?;
+                match_table(lua, m)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "wait_any",
+            lua.create_function(
+                move |lua, (patterns, timeout, regex): (Vec<String>, i32, Option<bool>)| {
+                    let m = api
+                        .wait_any(patterns, regex.unwrap_or(false), timeout)
+                        .map_err(into_luaerr)?;
+                    match_table(lua, m)
+                },
+            )?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "wait_prompt",
+            lua.create_function(move |_, timeout: i32| {
+                api.wait_prompt(timeout).map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "wait_console_ready",
+            lua.create_function(move |_, timeout: i32| {
+                api.wait_console_ready(timeout).map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "wait_string_ntimes",
+            lua.create_function(move |_, (s, n, timeout): (String, usize, i32)| {
+                Ok(api.wait_string_ntimes(s, n, timeout))
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "assert_wait_string_ntimes",
+            lua.create_function(move |lua, (s, n, timeout): (String, usize, i32)| {
+                let m = api
+                    .assert_wait_string_ntimes(s, n, timeout)
+                    .map_err(into_luaerr)?;
+                match_table(lua, m)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "try_wait_string",
+            lua.create_function(move |_, (s, timeout): (String, i32)| {
+                Ok(api.try_wait_string(s, timeout))
+            })?,
+        )?;
+
+        // ssh
+        let api = rustapi.clone();
+        globals.set(
+            "ssh_assert_script_run",
+            lua.create_function(move |_, (cmd, timeout): (String, i32)| {
+                api.ssh_assert_script_run(cmd, timeout).map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "ssh_script_run",
+            lua.create_function(move |_, (cmd, timeout): (String, i32)| {
+                api.ssh_script_run(cmd, timeout)
+                    .map(|(code, value)| (value, code))
+                    .map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "ssh_assert_script_run_seperate",
+            lua.create_function(move |_, (cmd, timeout): (String, i32)| {
+                api.ssh_assert_script_run_seperate(cmd, timeout)
+                    .map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "ssh_write",
+            lua.create_function(move |_, s: String| api.ssh_write(s).map_err(into_luaerr))?,
+        )?;
+
+        ssh_serial_functions(lua, &globals, &rustapi, None)?;
+
+        // serial
+        let api = rustapi.clone();
+        globals.set(
+            "serial_assert_script_run",
+            lua.create_function(move |_, (cmd, timeout): (String, i32)| {
+                api.serial_assert_script_run(cmd, timeout)
+                    .map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "serial_script_run",
+            lua.create_function(move |_, (cmd, timeout): (String, i32)| {
+                api.serial_script_run(cmd, timeout)
+                    .map(|(code, value)| (value, code))
+                    .map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "serial_write",
+            lua.create_function(move |_, s: String| api.serial_write(s).map_err(into_luaerr))?,
+        )?;
+
+        // vnc
+        let api = rustapi.clone();
+        globals.set(
+            "assert_screen",
+            lua.create_function(move |_, (tags, timeout): (Tags, i32)| {
+                api.vnc_assert_screen(tags.0, timeout).map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "check_screen",
+            lua.create_function(move |_, (tags, timeout): (Tags, i32)| {
+                api.vnc_check_screen(tags.0, timeout).map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "assert_and_click",
+            lua.create_function(move |_, (tags, timeout): (Tags, i32)| {
+                api.vnc_assert_and_click(tags.0, timeout)
+                    .map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "check_and_click",
+            lua.create_function(move |_, (tags, timeout): (Tags, i32)| {
+                api.vnc_check_and_click(tags.0, timeout)
+                    .map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "assert_and_move",
+            lua.create_function(move |_, (tags, timeout): (Tags, i32)| {
+                api.vnc_assert_and_move(tags.0, timeout)
+                    .map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "check_and_move",
+            lua.create_function(move |_, (tags, timeout): (Tags, i32)| {
+                api.vnc_check_and_move(tags.0, timeout).map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "vnc_refresh",
+            lua.create_function(move |_, ()| api.vnc_refresh().map_err(into_luaerr))?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "take_screenshot",
+            lua.create_function(move |_, ()| api.vnc_take_screenshot().map_err(into_luaerr))?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "mouse_click",
+            lua.create_function(move |_, ()| api.vnc_mouse_click().map_err(into_luaerr))?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "mouse_rclick",
+            lua.create_function(move |_, ()| api.vnc_mouse_rclick().map_err(into_luaerr))?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "mouse_keydown",
+            lua.create_function(move |_, ()| api.vnc_mouse_keydown().map_err(into_luaerr))?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "mouse_keyup",
+            lua.create_function(move |_, ()| api.vnc_mouse_keyup().map_err(into_luaerr))?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "mouse_move",
+            lua.create_function(move |_, (x, y): (u16, u16)| {
+                api.vnc_mouse_move(x, y).map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "mouse_drag",
+            lua.create_function(move |_, (x, y): (u16, u16)| {
+                api.vnc_mouse_drag(x, y).map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "mouse_hide",
+            lua.create_function(move |_, ()| api.vnc_mouse_hide().map_err(into_luaerr))?,
+        )?;
+
+        // power, timeout is secs to wait for boot on serial
+        let api = rustapi.clone();
+        globals.set(
+            "power_off",
+            lua.create_function(move |_, ()| api.power_off().map_err(into_luaerr))?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "power_on",
+            lua.create_function(move |_, timeout: i32| api.power_on(timeout).map_err(into_luaerr))?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "power_reset",
+            lua.create_function(move |_, timeout: i32| {
+                api.power_reset(timeout).map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "power_cycle",
+            lua.create_function(move |_, timeout: i32| {
+                api.power_cycle(timeout).map_err(into_luaerr)
+            })?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "reboot_and_wait",
+            lua.create_function(
+                move |_,
+                      (boot_timeout, login_timeout, ssh_timeout, cmd): (
+                    i32,
+                    i32,
+                    i32,
+                    Option<String>,
+                )| {
+                    api.reboot_and_wait(cmd, boot_timeout, login_timeout, ssh_timeout)
+                        .map_err(into_luaerr)
+                },
+            )?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "send_key",
+            lua.create_function(move |_, s: String| api.vnc_send_key(s).map_err(into_luaerr))?,
+        )?;
+
+        let api = rustapi.clone();
+        globals.set(
+            "type_string",
+            lua.create_function(move |_, s: String| api.vnc_type_string(s).map_err(into_luaerr))?,
+        )?;
+
+        Ok(())
+    }
+
+    // let `require("lib")` find lib.lua or lib/init.lua next to the case file
+    fn set_search_path(&self, base_folder: &Path) -> mlua::Result<()> {
+        let package: Table = self.lua.globals().get("package")?;
+        let path: String = package.get("path")?;
+        let base = base_folder.display();
+        package.set("path", format!("{base}/?.lua;{base}/?/init.lua;{path}"))
+    }
+
+    // case file may return a table of hooks, or define them as globals
+    fn get_hook<'lua>(&'lua self, module: &Value<'lua>, name: &str) -> Option<Function<'lua>> {
+        if let Value::Table(t) = module {
+            if let Ok(Some(f)) = t.get::<_, Option<Function>>(name) {
+                return Some(f);
+            }
+        }
+        self.lua
+            .globals()
+            .get::<_, Option<Function>>(name)
+            .ok()
+            .flatten()
+    }
+
+    pub fn run_string(&mut self, script: &str) -> Result<(), String> {
+        let module: Value = self
+            .lua
+            .load(script)
+            .set_name("entry.lua")
+            .eval()
+            .map_err(|e| {
+                let msg = format!("lua script load failed: [{}]", e);
+                error!(msg = msg);
+                msg
+            })?;
+
+        let main = self
+            .get_hook(&module, "main")
+            .or_else(|| self.get_hook(&module, "run"))
+            .ok_or_else(|| {
+                let msg = r#"function "main" or "run" must exists"#.to_string();
+                error!(msg = msg);
+                msg
+            })?;
+
+        main.call::<_, ()>(()).map_err(|e| {
+            let msg = format!("main run failed: {}", e);
+            error!(msg = msg);
+            msg
+        })
+    }
+
+    pub fn run_file(&mut self, file: &str) -> CaseReport {
+        let mut report = CaseReport::new(file);
+        let base_folder = case_dir(file);
+        let script = match fs::read_to_string(file) {
+            Ok(script) => script,
+            Err(e) => return report.fail(format!("case file read failed: [{}]", e)),
+        };
+        if let Err(e) = self.set_search_path(base_folder) {
+            return report.fail(format!("set lua package path failed: [{}]", e));
+        }
+
+        let module: Value = match self.lua.load(&script).set_name(format!("@{file}")).eval() {
+            Ok(module) => module,
+            Err(e) => {
+                let msg = format!("entry file load failed: [{}]", e);
+                error!(msg);
+                return report.fail(msg);
+            }
+        };
+
+        let Some(main) = self
+            .get_hook(&module, "main")
+            .or_else(|| self.get_hook(&module, "run"))
+        else {
+            return report.fail(r#"function "main" or "run" must exists"#);
+        };
+
+        let call = |name: &str, f: Function| {
+            self.recorder.phase(name, || {
+                f.call::<_, ()>(()).map_err(|e| {
+                    error!("{} run failed: {}", name, e);
+                    e.to_string()
+                })
+            })
+        };
+
+        // try run prehook, skip main and afterhook if failed
+        if let Some(prehook) = self.get_hook(&module, "prehook") {
+            let phase = call("prehook", prehook);
+            let failed = phase.status == Status::Failed;
+            report.push_phase(phase);
+            if failed {
+                report.push_phase(PhaseReport::skipped("main"));
+                report.push_phase(PhaseReport::skipped("afterhook"));
+                return report;
+            }
+        }
+
+        // continue if failed
+        report.push_phase(call("main", main));
+
+        // try run afterhook
+        if let Some(afterhook) = self.get_hook(&module, "afterhook") {
+            report.push_phase(call("afterhook", afterhook));
+        }
+        report
+    }
+}
+
+// a bare case.lua has "" as parent, and "/" has none
+fn case_dir(file: &str) -> &Path {
+    Path::new(file)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{case_dir, LuaEngine};
+    use crate::msg::{ExecResult, Job, JobOutput, JobStatus};
+    use crate::{report::Status, MsgReq, MsgRes, TextConsole};
+    use std::{fs, sync::mpsc, thread};
+
+    fn spawn_server() -> mpsc::Sender<(MsgReq, mpsc::Sender<MsgRes>)> {
+        let (tx, rx) = mpsc::channel::<(MsgReq, mpsc::Sender<MsgRes>)>();
+        thread::spawn(move || {
+            while let Ok((req, tx)) = rx.recv() {
+                let res = match req {
+                    MsgReq::ScriptRun { cmd, console, .. } => MsgRes::ScriptRun {
+                        code: if cmd.starts_with("false") { 1 } else { 0 },
+                        value: match console {
+                            Some(TextConsole::Named(name)) => format!("{name}: {cmd}"),
+                            _ => cmd.trim_start_matches("echo ").to_string(),
+                        },
+                    },
+                    MsgReq::SSHScriptRunSeperate {
+                        console,
+                        cmd,
+                        env,
+                        cwd,
+                        ..
+                    } => MsgRes::ExecResult(ExecResult {
+                        code: 0,
+                        stdout: match console {
+                            Some(TextConsole::Named(name)) => format!("{name}: {cmd}"),
+                            _ => format!("{cmd} {env:?} {cwd:?}"),
+                        },
+                        stderr: String::new(),
+                    }),
+                    MsgReq::Job {
+                        console,
+                        req: Job::Spawn(_),
+                    } => MsgRes::JobId(if console.is_some() { 2 } else { 1 }),
+                    MsgReq::Job {
+                        req: Job::Output(_),
+                        ..
+                    } => MsgRes::JobOutput(JobOutput {
+                        stdout: "out".to_string(),
+                        stderr: String::new(),
+                    }),
+                    MsgReq::Job { .. } => MsgRes::JobStatus(JobStatus::Killed("TERM".to_string())),
+                    _ => MsgRes::Done,
+                };
+                tx.send(res).unwrap();
+            }
+        });
+        tx
+    }
+
+    #[test]
+    fn test_lua_require_and_hooks() {
+        let dir = std::env::temp_dir().join("autotest-lua-case");
+        if fs::metadata(&dir).is_ok() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(dir.join("util")).unwrap();
+        fs::write(
+            dir.join("lib.lua"),
+            r#"
+                local M = {}
+                function M.add(a, b) return a + b end
+                return M
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("util").join("init.lua"),
+            r#"return { name = "util" }"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("case.lua"),
+            r#"
+                local lib = require("lib")
+                local util = require("util")
+                return {
+                    prehook = function()
+                        assert(util.name == "util")
+                    end,
+                    run = function()
+                        local out, code = script_run("echo " .. lib.add(1, 2), 10)
+                        assert(out == "3" and code == 0)
+                        assert_script_run("false", 10)
+                    end,
+                    afterhook = function()
+                        writeln("exit")
+                    end,
+                }
+            "#,
+        )
+        .unwrap();
+
+        let mut e = LuaEngine::new(spawn_server());
+        let report = e.run_file(dir.join("case.lua").to_str().unwrap());
+
+        assert_eq!(report.phases.len(), 3);
+        assert_eq!(report.phases[0].status, Status::Passed);
+        assert_eq!(report.phases[1].status, Status::Failed);
+        assert_eq!(report.phases[1].calls.len(), 2);
+        assert_eq!(report.phases[2].status, Status::Passed);
+        assert!(!report.is_success());
+    }
+
+    #[test]
+    fn test_case_dir() {
+        assert_eq!(case_dir("cases/case.lua"), std::path::Path::new("cases"));
+        assert_eq!(case_dir("case.lua"), std::path::Path::new("."));
+        assert_eq!(case_dir("/"), std::path::Path::new("."));
+    }
+
+    #[test]
+    fn test_lua_global_hooks() {
+        let mut e = LuaEngine::new(spawn_server());
+        e.run_string(
+            r#"
+                function main()
+                    assert_script_run("echo 1", 10)
+                end
+            "#,
+        )
+        .unwrap();
+
+        let mut e = LuaEngine::new(spawn_server());
+        assert!(e.run_string("local a = 1").is_err());
+    }
+
+    #[test]
+    fn test_lua_jobs() {
+        let mut e = LuaEngine::new(spawn_server());
+        e.run_string(
+            r#"
+                function main()
+                    local id = spawn("stress-ng --cpu 4")
+                    local s = job_kill(id)
+                    assert(s.state == "killed" and s.signal == "TERM" and s.code == nil)
+                    assert(job_output(id).stdout == "out")
+                end
+            "#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_lua_exec_seperate() {
+        let mut e = LuaEngine::new(spawn_server());
+        e.run_string(
+            r#"
+                function main()
+                    local res = ssh_script_run_seperate("make", 10, { env = { CC = "gcc" }, cwd = "/src" })
+                    assert(res.code == 0)
+                    assert(res.stdout == 'make [("CC", "gcc")] Some("/src")', res.stdout)
+                    assert(ssh_script_run_seperate("ls", 10).stdout == "ls [] None")
+                end
+            "#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_lua_console() {
+        let mut e = LuaEngine::new(spawn_server());
+        e.run_string(
+            r#"
+                function main()
+                    local client = console("ssh.client")
+                    local out, code = client.script_run("hostname", 10)
+                    assert(out == "ssh.client: hostname" and code == 0, out)
+                    assert(console("server").assert_script_run("ls", 10) == "server: ls")
+                    assert(script_run("echo hi", 10) == "hi")
+                    assert(client.ssh_script_run_seperate("ls", 10).stdout == "ssh.client: ls")
+                    assert(client.spawn("sleep 10") == 2 and spawn("sleep 10") == 1)
+                    assert(client.job_kill(2).state == "killed")
+                end
+            "#,
+        )
+        .unwrap();
+    }
+}
@@ -1 +1,502 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
 
+use crate::api::{Api, RustApi};
+use crate::report::{CaseReport, PhaseReport, Recorder, Status};
+use crate::{ApiError, MsgReq, MsgRes, ScriptEngine, TextConsole};
+use serde_json::{json, Value};
+use tracing::{error, info, Level};
+
+/// Run openQA style test modules with the system perl.
+///
+/// The case is loaded by a small runner which provides `testapi` and `basetest`,
+/// every testapi call is forwarded to rust as one json line on stdout and
+/// answered on stdin. Output printed by the case goes to stderr.
+pub struct PerlEngine {
+    api: RustApi,
+    recorder: Recorder,
+}
+
+impl ScriptEngine for PerlEngine {
+    fn run_file(&mut self, content: &str) -> CaseReport {
+        self.run_file(content)
+    }
+
+    fn run_string(&mut self, content: &str) {
+        self.run_string(content).unwrap();
+    }
+}
+
+const RUNNER: &str = r##"
+use strict;
+use warnings;
+use JSON::PP;
+use IO::Handle;
+
+our $JSON = JSON::PP->new->canonical->allow_nonref;
+open(our $RPC, '>&', \*STDOUT) or die "dup stdout failed: $!";
+$RPC->autoflush(1);
+open(STDOUT, '>&', \*STDERR) or die "redirect stdout failed: $!";
+STDOUT->autoflush(1);
+
+sub emit { print {$main::RPC} $main::JSON->encode($_[0]), "\n" }
+
+package testapi;
+use Exporter 'import';
+our @EXPORT = qw(
+    assert_script_run script_run script_output
+    assert_screen check_screen assert_and_click
+    type_string enter_cmd send_key wait_serial
+    get_var check_var set_var
+    mouse_set mouse_click mouse_hide save_screenshot
+    record_info diag power
+);
+our %vars;
+
+sub _call {
+    my ($fn, @args) = @_;
+    main::emit({fn => $fn, args => \@args});
+    my $line = <STDIN>;
+    die "autotest: runner closed\n" unless defined $line;
+    my $res = $main::JSON->decode($line);
+    die "$res->{err}\n" if exists $res->{err};
+    return $res->{ok};
+}
+
+# openQA accepts both `f($x, 30)` and `f($x, timeout => 30)`
+sub _timeout {
+    my ($default, @args) = @_;
+    return $default unless @args;
+    return $args[0] if @args == 1;
+    my %args = @args;
+    return $args{timeout} // $default;
+}
+
+sub assert_script_run { my ($cmd, @args) = @_; _call('assert_script_run', $cmd, _timeout(90, @args)); return }
+sub script_run        { my ($cmd, @args) = @_; return _call('script_run', $cmd, _timeout(30, @args)) }
+sub script_output     { my ($cmd, @args) = @_; return _call('script_output', $cmd, _timeout(30, @args)) }
+sub assert_screen     { my ($tag, @args) = @_; return _call('assert_screen', $tag, _timeout(30, @args)) }
+sub check_screen      { my ($tag, @args) = @_; return _call('check_screen', $tag, _timeout(0, @args)) }
+sub assert_and_click  { my ($tag, @args) = @_; return _call('assert_and_click', $tag, _timeout(30, @args)) }
+sub type_string       { my ($s) = @_; _call('type_string', $s); return }
+sub enter_cmd         { my ($s) = @_; _call('type_string', "$s\n"); return }
+sub send_key          { my ($key) = @_; _call('send_key', $key); return }
+sub wait_serial {
+    my ($pattern, @args) = @_;
+    # qr// stringifies to perl only syntax, send the bare pattern instead
+    if (ref($pattern) eq 'Regexp') {
+        my ($p, $mods) = re::regexp_pattern($pattern);
+        $pattern = $mods =~ /i/ ? "(?i)$p" : $p;
+    }
+    return _call('wait_serial', $pattern, _timeout(90, @args));
+}
+sub get_var {
+    my ($name, $default) = @_;
+    return $vars{$name} if exists $vars{$name};
+    return _call('get_var', $name) // $default;
+}
+sub check_var         { my ($name, $value) = @_; my $v = get_var($name); return defined $v && $v eq $value }
+sub set_var           { my ($name, $value) = @_; $vars{$name} = $value; return }
+sub mouse_set         { my ($x, $y) = @_; _call('mouse_set', $x + 0, $y + 0); return }
+sub mouse_click       { _call('mouse_click'); return }
+sub mouse_hide        { _call('mouse_hide'); return }
+sub save_screenshot   { _call('save_screenshot'); return }
+sub record_info       { my ($title, $output) = @_; _call('diag', join(' ', grep { defined } $title, $output)); return }
+sub diag              { _call('diag', join('', @_)); return }
+sub power             { my ($action) = @_; _call('power', $action); return }
+
+package basetest;
+sub new { my ($class, %args) = @_; return bless {%args}, $class }
+
+# base classes commonly used by openQA distributions
+@opensusebasetest::ISA = ('basetest');
+@consoletest::ISA      = ('opensusebasetest');
+@x11test::ISA          = ('opensusebasetest');
+@installbasetest::ISA  = ('opensusebasetest');
+
+package main;
+$INC{"$_.pm"} = __FILE__ for qw(testapi basetest opensusebasetest consoletest x11test installbasetest);
+
+my $file = shift @ARGV;
+my $pkg  = 'main';
+if (open(my $fh, '<', $file)) {
+    local $/;
+    my $src = <$fh>;
+    $pkg = $1 if $src =~ /^\s*package\s+([\w:]+)\s*;/m;
+}
+
+my $loaded = do $file;
+if (!defined $loaded && $@) {
+    emit({died => "$@"});
+    exit 1;
+}
+my $self = $pkg->can('new') ? $pkg->new : bless({}, $pkg);
+emit({loaded => [grep { $pkg->can($_) } qw(run pre_run_hook post_run_hook post_fail_hook)]});
+
+while (my $line = <STDIN>) {
+    my $hook = $JSON->decode($line)->{hook};
+    my $f = $pkg->can($hook);
+    if (eval { $self->$f(); 1 }) {
+        emit({done => JSON::PP::true});
+    } else {
+        emit({died => "$@"});
+    }
+}
+"##;
+
+enum Event {
+    Loaded(Vec<String>),
+    Done,
+    Died(String),
+}
+
+struct Session<'a> {
+    api: &'a RustApi,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl<'a> Session<'a> {
+    fn send(&mut self, v: Value) -> Result<(), String> {
+        writeln!(self.stdin, "{}", v).map_err(|e| format!("perl runner closed: {}", e))
+    }
+
+    // answer testapi calls until the runner reports an event
+    fn wait(&mut self) -> Result<Event, String> {
+        loop {
+            let mut line = String::new();
+            match self.stdout.read_line(&mut line) {
+                Ok(0) => return Err("perl runner exited unexpectedly".to_string()),
+                Ok(_) => {}
+                Err(e) => return Err(format!("read perl runner failed: {}", e)),
+            }
+            let msg: Value = serde_json::from_str(&line)
+                .map_err(|e| format!("invalid message from perl runner: {}", e))?;
+
+            if let Some(f) = msg.get("fn").and_then(|f| f.as_str()) {
+                let empty = Vec::new();
+                let args = msg.get("args").and_then(|a| a.as_array()).unwrap_or(&empty);
+                let res = match self.dispatch(f, args) {
+                    Ok(v) => json!({ "ok": v }),
+                    Err(e) => json!({ "err": e }),
+                };
+                self.send(res)?;
+            } else if let Some(hooks) = msg.get("loaded").and_then(|h| h.as_array()) {
+                return Ok(Event::Loaded(
+                    hooks
+                        .iter()
+                        .filter_map(|h| h.as_str().map(|h| h.to_string()))
+                        .collect(),
+                ));
+            } else if msg.get("done").is_some() {
+                return Ok(Event::Done);
+            } else if let Some(e) = msg.get("died") {
+                return Ok(Event::Died(
+                    e.as_str().unwrap_or_default().trim_end().to_string(),
+                ));
+            }
+        }
+    }
+
+    fn call_hook(&mut self, hook: &str) -> Result<(), String> {
+        self.send(json!({ "hook": hook }))?;
+        match self.wait()? {
+            Event::Done => Ok(()),
+            Event::Died(e) => Err(e),
+            Event::Loaded(_) => Err("unexpected message from perl runner".to_string()),
+        }
+    }
+
+    fn dispatch(&self, f: &str, args: &[Value]) -> Result<Value, String> {
+        let s = |i: usize| {
+            args.get(i)
+                .map(|v| match v {
+                    Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                })
+                .unwrap_or_default()
+        };
+        let n = |i: usize| -> i32 {
+            args.get(i)
+                .and_then(|v| v.as_i64().or_else(|| v.as_str()?.parse().ok()))
+                .unwrap_or(0) as i32
+        };
+        // openQA takes a tag or an array ref of tags
+        let tags = |i: usize| match args.get(i) {
+            Some(Value::Array(tags)) => tags
+                .iter()
+                .filter_map(|t| t.as_str().map(|t| t.to_string()))
+                .collect(),
+            _ => vec![s(i)],
+        };
+        let api = self.api;
+        let e = |e: ApiError| e.to_string();
+        match f {
+            "assert_script_run" => api.assert_script_run(s(0), n(1)).map(Value::from),
+            "script_run" => api
+                .script_run(s(0), n(1))
+                .map(|(code, _)| Value::from(code)),
+            "script_output" => api.assert_script_run(s(0), n(1)).map(Value::from),
+            "assert_screen" => api.vnc_assert_screen(tags(0), n(1)).map(Value::from),
+            "check_screen" => api.vnc_check_screen(tags(0), n(1)).map(Value::from),
+            "assert_and_click" => api
+                .vnc_assert_and_click(tags(0), n(1))
+                .map(|_| Value::from(true)),
+            "type_string" => api.vnc_type_string(s(0)).map(|_| Value::Null),
+            "send_key" => api.vnc_send_key(s(0)).map(|_| Value::Null),
+            // openQA returns undef on timeout instead of dying
+            "wait_serial" => {
+                match api._wait_match(Some(TextConsole::Serial), vec![s(0)], true, 1, n(1)) {
+                    Ok(m) => Ok(Value::from(m.text)),
+                    Err(ApiError::Timeout) => Ok(Value::Null),
+                    Err(e) => Err(e),
+                }
+            }
+            "get_var" => api.get_env(s(0)).map(Value::from),
+            "mouse_set" => api
+                .vnc_mouse_move(n(0) as u16, n(1) as u16)
+                .map(|_| Value::Null),
+            "mouse_click" => api.vnc_mouse_click().map(|_| Value::Null),
+            "mouse_hide" => api.vnc_mouse_hide().map(|_| Value::Null),
+            "save_screenshot" => api.vnc_take_screenshot().map(|_| Value::Null),
+            "diag" => {
+                api.print(Level::INFO, s(0));
+                Ok(Value::Null)
+            }
+            // openQA doesn't wait for boot here, cases use wait_serial after it
+            "power" => match s(0).as_str() {
+                "on" => api.power_on(0),
+                "off" => api.power_off(),
+                "reset" => api.power_reset(0),
+                "cycle" => api.power_cycle(0),
+                a => return Err(format!("power: unknown action {}", a)),
+            }
+            .map(|_| Value::Null),
+            f => return Err(format!("testapi::{} is not supported", f)),
+        }
+        .map_err(e)
+    }
+}
+
+impl PerlEngine {
+    pub fn new(tx: mpsc::Sender<(MsgReq, mpsc::Sender<MsgRes>)>) -> Self {
+        let recorder = Recorder::default();
+        Self {
+            api: RustApi::with_recorder(tx, recorder.clone()),
+            recorder,
+        }
+    }
+
+    fn spawn(file: &Path) -> std::io::Result<Child> {
+        let base_folder = file.parent().unwrap_or(Path::new("."));
+        let mut child = Command::new("perl")
+            .arg("-I")
+            .arg(base_folder)
+            .arg("-I")
+            .arg(base_folder.join("lib"))
+            .arg("-e")
+            .arg(RUNNER)
+            .arg(file)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // everything the case prints ends up in log
+        if let Some(stderr) = child.stderr.take() {
+            thread::spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    info!(msg = "perl output", line = line);
+                }
+            });
+        }
+        Ok(child)
+    }
+
+    pub fn run_string(&mut self, script: &str) -> Result<(), String> {
+        let file = std::env::temp_dir().join(format!("autotest-{}.pm", std::process::id()));
+        fs::write(&file, script).map_err(|e| e.to_string())?;
+        let report = self.run_file(&file.display().to_string());
+        let _ = fs::remove_file(&file);
+        match report.failure {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    pub fn run_file(&mut self, file: &str) -> CaseReport {
+        let mut report = CaseReport::new(file);
+        let path = match fs::canonicalize(file) {
+            Ok(path) => path,
+            Err(e) => return report.fail(format!("case file read failed: [{}]", e)),
+        };
+        let mut child = match Self::spawn(&path) {
+            Ok(child) => child,
+            Err(e) => return report.fail(format!("start perl failed: [{}]", e)),
+        };
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return report.fail("perl runner has no stdio");
+        };
+        let mut session = Session {
+            api: &self.api,
+            stdin,
+            stdout: BufReader::new(stdout),
+        };
+
+        let hooks = match session.wait() {
+            Ok(Event::Loaded(hooks)) => hooks,
+            Ok(Event::Died(e)) | Err(e) => {
+                let msg = format!("test module load failed: [{}]", e);
+                error!(msg);
+                let _ = child.kill();
+                return report.fail(msg);
+            }
+            Ok(Event::Done) => {
+                let _ = child.kill();
+                return report.fail("unexpected message from perl runner");
+            }
+        };
+        if !hooks.iter().any(|h| h == "run") {
+            let _ = child.kill();
+            return report.fail(r#"sub "run" must exists"#);
+        }
+
+        let recorder = self.recorder.clone();
+        let mut call = |name: &str, hook: &str| {
+            recorder.phase(name, || {
+                session.call_hook(hook).map_err(|e| {
+                    error!("{} run failed: {}", hook, e);
+                    e
+                })
+            })
+        };
+
+        // try run pre_run_hook, skip the others if failed
+        if hooks.iter().any(|h| h == "pre_run_hook") {
+            let phase = call("prehook", "pre_run_hook");
+            let failed = phase.status == Status::Failed;
+            report.push_phase(phase);
+            if failed {
+                report.push_phase(PhaseReport::skipped("main"));
+                report.push_phase(PhaseReport::skipped("afterhook"));
+            }
+        }
+
+        if report.phases.is_empty() || report.is_success() {
+            let phase = call("main", "run");
+            let hook = if phase.status == Status::Failed {
+                "post_fail_hook"
+            } else {
+                "post_run_hook"
+            };
+            report.push_phase(phase);
+
+            if hooks.iter().any(|h| h == hook) {
+                report.push_phase(call("afterhook", hook));
+            }
+        }
+
+        // close stdin, runner exits after the last hook
+        drop(session);
+        if let Err(e) = child.wait() {
+            error!(msg = "wait perl runner failed", reason = ?e);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PerlEngine;
+    use crate::{report::Status, MsgReq, MsgRes};
+    use std::{fs, process::Command, sync::mpsc, thread};
+
+    fn spawn_server() -> mpsc::Sender<(MsgReq, mpsc::Sender<MsgRes>)> {
+        let (tx, rx) = mpsc::channel::<(MsgReq, mpsc::Sender<MsgRes>)>();
+        thread::spawn(move || {
+            while let Ok((req, tx)) = rx.recv() {
+                let res = match req {
+                    MsgReq::ScriptRun { cmd, .. } => MsgRes::ScriptRun {
+                        code: if cmd.starts_with("false") { 1 } else { 0 },
+                        value: cmd.trim_start_matches("echo ").to_string(),
+                    },
+                    MsgReq::GetConfig { .. } => MsgRes::ConfigValue(Some("riscv64".to_string())),
+                    _ => MsgRes::Done,
+                };
+                tx.send(res).unwrap();
+            }
+        });
+        tx
+    }
+
+    fn has_perl() -> bool {
+        Command::new("perl")
+            .args(["-MJSON::PP", "-e", "1"])
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn test_openqa_module() {
+        if !has_perl() {
+            return;
+        }
+        let dir = std::env::temp_dir().join("autotest-perl-case");
+        if fs::metadata(&dir).is_ok() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("boot.pm"),
+            r#"
+                package boot;
+                use base 'consoletest';
+                use strict;
+                use warnings;
+                use testapi;
+
+                sub run {
+                    my ($self) = @_;
+                    assert_script_run('echo hi', timeout => 10);
+                    die "bad output" unless script_output('echo 3') eq '3';
+                    die "bad var" unless check_var('ARCH', 'riscv64');
+                    print "not a testapi call\n";
+                    assert_script_run('false');
+                }
+
+                sub post_fail_hook {
+                    send_key('ctrl-c');
+                }
+
+                1;
+            "#,
+        )
+        .unwrap();
+
+        let mut e = PerlEngine::new(spawn_server());
+        let report = e.run_file(dir.join("boot.pm").to_str().unwrap());
+
+        assert_eq!(report.phases.len(), 2);
+        assert_eq!(report.phases[0].status, Status::Failed);
+        assert_eq!(report.phases[0].calls.len(), 4);
+        assert_eq!(report.phases[1].name, "afterhook");
+        assert_eq!(report.phases[1].status, Status::Passed);
+    }
+
+    #[test]
+    fn test_missing_run() {
+        if !has_perl() {
+            return;
+        }
+        let mut e = PerlEngine::new(spawn_server());
+        assert!(e.run_string("use testapi; sub foo {} 1;").is_err());
+        assert!(e
+            .run_string("use testapi; sub run { diag('ok') } 1;")
+            .is_ok());
+    }
+}
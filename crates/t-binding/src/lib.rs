@@ -2,14 +2,16 @@ pub mod api;
 mod engine;
 pub mod error;
 pub mod msg;
+pub mod report;
 
-pub use engine::JSEngine;
+pub use engine::{new_engine, JSEngine, LuaEngine, PerlEngine};
 pub use error::{ApiError, Result};
-pub use msg::{MsgReq, MsgRes, MsgResError, TextConsole};
+pub use msg::{MsgReq, MsgRes, MsgResError, TextConsole, WaitMatch};
+pub use report::CaseReport;
 
 pub enum EngineError {}
 
 pub trait ScriptEngine {
-    fn run_file(&mut self, path: &str);
+    fn run_file(&mut self, path: &str) -> CaseReport;
     fn run_string(&mut self, content: &str);
 }
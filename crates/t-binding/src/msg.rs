@@ -1,6 +1,11 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    path::PathBuf,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
 
 use t_console::PNG;
+pub use t_console::{ExecResult, JobId, JobOutput, JobStatus, WaitMatch};
 
 use crate::ApiError;
 
@@ -8,6 +13,9 @@ use crate::ApiError;
 pub enum TextConsole {
     SSH,
     Serial,
+    Telnet,
+    // console by name, like client, or with type, like ssh.client
+    Named(String),
 }
 
 #[derive(Debug)]
@@ -19,9 +27,12 @@ pub enum MsgReq {
     GetConfig {
         key: String,
     },
-    // ssh
+    // ssh, default one if no console given
     SSHScriptRunSeperate {
+        console: Option<TextConsole>,
         cmd: String,
+        env: Vec<(String, String)>,
+        cwd: Option<String>,
         timeout: Duration,
     },
     ScriptRun {
@@ -29,17 +40,123 @@ pub enum MsgReq {
         cmd: String,
         timeout: Duration,
     },
+    // like ScriptRun, output lines are sent as they show, result is still ScriptRun
+    ScriptRunStream {
+        console: Option<TextConsole>,
+        cmd: String,
+        timeout: Duration,
+        output: mpsc::Sender<String>,
+    },
     WriteString {
         console: Option<TextConsole>,
         s: String,
         timeout: Duration,
     },
+    // wait any of patterns show n times
     WaitString {
         console: Option<TextConsole>,
-        s: String,
+        patterns: Vec<String>,
+        regex: bool,
+        n: usize,
+        timeout: Duration,
+    },
+    // wait until shell shows prompt
+    WaitPrompt {
+        console: Option<TextConsole>,
+        timeout: Duration,
+    },
+    // wait until console connected again, like after reboot. no console means all of them
+    WaitConsoleReady {
+        console: Option<TextConsole>,
         timeout: Duration,
     },
     VNC(VNC),
+    QMP(QMP),
+    // background commands on ssh, job ids belong to the console
+    Job {
+        console: Option<TextConsole>,
+        req: Job,
+    },
+    // file transfer over ssh
+    Transfer {
+        console: Option<TextConsole>,
+        req: Transfer,
+    },
+    // file transfer over serial shell
+    SerialTransfer {
+        console: Option<TextConsole>,
+        req: SerialTransfer,
+    },
+    // wait boot pattern on serial after power on if boot_timeout set
+    Power {
+        action: Power,
+        boot_timeout: Option<Duration>,
+    },
+    // write cmd to serial, or ssh if no serial, then wait each stage in order,
+    // stage without timeout is skipped
+    Reboot {
+        cmd: String,
+        boot_timeout: Option<Duration>,
+        login_timeout: Option<Duration>,
+        ssh_timeout: Option<Duration>,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Power {
+    On,
+    Off,
+    Reset,
+    Cycle,
+}
+
+#[derive(Debug)]
+pub enum Job {
+    Spawn(String),
+    Status(JobId),
+    Wait { id: JobId, timeout: Duration },
+    Kill(JobId),
+    Output(JobId),
+}
+
+// relative local path of download is under log_dir
+#[derive(Debug)]
+pub enum Transfer {
+    Upload {
+        local: PathBuf,
+        remote: String,
+        mode: Option<i32>,
+    },
+    Download {
+        remote: String,
+        local: PathBuf,
+    },
+    Exists(String),
+}
+
+// base64 through shell, slow, only for small files
+#[derive(Debug)]
+pub enum SerialTransfer {
+    Upload {
+        local: PathBuf,
+        remote: String,
+        mode: Option<i32>,
+        timeout: Duration,
+    },
+    Download {
+        remote: String,
+        local: PathBuf,
+        timeout: Duration,
+    },
+}
+
+// only available when qemu is started by runner
+#[derive(Debug)]
+pub enum QMP {
+    SystemReset,
+    SaveVM(String),
+    LoadVM(String),
+    Quit,
 }
 
 #[derive(Debug)]
@@ -48,7 +165,8 @@ pub enum VNC {
     GetScreenShot,
     Refresh,
     CheckScreen {
-        tag: String,
+        // any needle carrying one of tags
+        tags: Vec<String>,
         threshold: f32,
         timeout: Duration,
         click: bool,
@@ -75,13 +193,15 @@ pub enum VNC {
 pub enum MsgResError {
     Timeout,
     String(String),
+    // needles of the tags can't be loaded, not just a mismatch
+    Needle(String),
 }
 
 impl From<MsgResError> for ApiError {
     fn from(value: MsgResError) -> Self {
         match value {
             MsgResError::Timeout => Self::Timeout,
-            MsgResError::String(s) => Self::String(s),
+            MsgResError::String(s) | MsgResError::Needle(s) => Self::String(s),
         }
     }
 }
@@ -91,6 +211,16 @@ pub enum MsgRes {
     Done,
     ConfigValue(Option<String>),
     ScriptRun { code: i32, value: String },
+    ExecResult(ExecResult),
     Error(MsgResError),
     Screenshot(Arc<PNG>),
+    Matched(WaitMatch),
+    JobId(JobId),
+    JobStatus(JobStatus),
+    JobOutput(JobOutput),
+    Exists(bool),
+    // from reboot to boot pattern, or to ssh back if boot stage skipped
+    Rebooted { boot_time: Duration },
+    // name of the needle check screen matched
+    Needle(String),
 }
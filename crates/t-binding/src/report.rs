@@ -0,0 +1,229 @@
+use serde::{Serialize, Serializer};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{ApiError, MsgRes};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+/// one request sent to the server by a script
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiCall {
+    pub req: String,
+    #[serde(serialize_with = "as_secs")]
+    pub duration: Duration,
+    pub status: Status,
+    pub failure: Option<String>,
+}
+
+/// one of prehook, main, afterhook
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseReport {
+    pub name: String,
+    pub status: Status,
+    #[serde(serialize_with = "as_secs")]
+    pub duration: Duration,
+    pub failure: Option<String>,
+    pub calls: Vec<ApiCall>,
+}
+
+impl PhaseReport {
+    pub fn skipped(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: Status::Skipped,
+            duration: Duration::ZERO,
+            failure: None,
+            calls: Vec::new(),
+        }
+    }
+}
+
+/// console connection state changed while the case was running
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsoleEvent {
+    pub console: String,
+    // connected, lost or reconnecting
+    pub state: String,
+    pub time: String,
+    pub reason: Option<String>,
+}
+
+/// value measured while the case was running, like boot time
+#[derive(Debug, Clone, Serialize)]
+pub struct Metric {
+    pub name: String,
+    pub value: f64,
+    pub unit: String,
+}
+
+/// result of one case file
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseReport {
+    pub name: String,
+    pub file: String,
+    pub status: Status,
+    #[serde(serialize_with = "as_secs")]
+    pub duration: Duration,
+    pub failure: Option<String>,
+    /// failure of this case doesn't fail the whole run
+    pub ignore_failure: bool,
+    pub phases: Vec<PhaseReport>,
+    pub events: Vec<ConsoleEvent>,
+    pub metrics: Vec<Metric>,
+}
+
+impl CaseReport {
+    pub fn new(file: &str) -> Self {
+        Self {
+            name: Path::new(file)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or(file.to_string()),
+            file: file.to_string(),
+            status: Status::Passed,
+            duration: Duration::ZERO,
+            failure: None,
+            ignore_failure: false,
+            phases: Vec::new(),
+            events: Vec::new(),
+            metrics: Vec::new(),
+        }
+    }
+
+    /// mark the whole case failed, used when the case can't even be loaded
+    pub fn fail(mut self, reason: impl Into<String>) -> Self {
+        self.status = Status::Failed;
+        self.failure = Some(reason.into());
+        self
+    }
+
+    /// mark the whole case skipped, used when the case is not run at all
+    pub fn skip(mut self, reason: impl Into<String>) -> Self {
+        self.status = Status::Skipped;
+        self.failure = Some(reason.into());
+        self
+    }
+
+    pub fn push_phase(&mut self, phase: PhaseReport) {
+        self.duration += phase.duration;
+        if phase.status == Status::Failed && self.status != Status::Failed {
+            self.status = Status::Failed;
+            self.failure = phase
+                .failure
+                .as_ref()
+                .map(|f| format!("{} failed: {}", phase.name, f));
+        }
+        self.phases.push(phase);
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status != Status::Failed
+    }
+}
+
+/// collect api calls made by a script engine, shared with the api it hands to scripts
+#[derive(Clone, Default)]
+pub struct Recorder {
+    calls: Arc<Mutex<Vec<ApiCall>>>,
+}
+
+impl Recorder {
+    pub fn record(&self, req: String, start: Instant, res: &Result<MsgRes, ApiError>) {
+        let failure = match res {
+            Ok(MsgRes::Error(e)) => Some(format!("{:?}", e)),
+            Ok(_) => None,
+            Err(e) => Some(e.to_string()),
+        };
+        let call = ApiCall {
+            req,
+            duration: start.elapsed(),
+            status: if failure.is_some() {
+                Status::Failed
+            } else {
+                Status::Passed
+            },
+            failure,
+        };
+        if let Ok(mut calls) = self.calls.lock() {
+            calls.push(call);
+        }
+    }
+
+    pub fn take(&self) -> Vec<ApiCall> {
+        self.calls
+            .lock()
+            .map(|mut calls| std::mem::take(&mut *calls))
+            .unwrap_or_default()
+    }
+
+    /// run one phase, collect its duration, api calls and failure reason
+    pub fn phase(&self, name: &str, f: impl FnOnce() -> Result<(), String>) -> PhaseReport {
+        // drop calls made outside of any phase
+        self.take();
+
+        let start = Instant::now();
+        let res = f();
+        PhaseReport {
+            name: name.to_string(),
+            status: if res.is_ok() {
+                Status::Passed
+            } else {
+                Status::Failed
+            },
+            duration: start.elapsed(),
+            failure: res.err(),
+            calls: self.take(),
+        }
+    }
+}
+
+fn as_secs<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(d.as_secs_f64())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_case_status() {
+        let recorder = Recorder::default();
+        let mut case = CaseReport::new("./cases/boot.js");
+        assert_eq!(case.name, "boot");
+
+        case.push_phase(recorder.phase("prehook", || Ok(())));
+        assert!(case.is_success());
+
+        case.push_phase(recorder.phase("main", || Err("exit code 1".to_string())));
+        case.push_phase(recorder.phase("afterhook", || Err("ignored".to_string())));
+        assert!(!case.is_success());
+        assert_eq!(case.failure, Some("main failed: exit code 1".to_string()));
+    }
+
+    #[test]
+    fn test_recorder_take_calls() {
+        let recorder = Recorder::default();
+        let phase = recorder.phase("main", || {
+            recorder.record("ScriptRun".to_string(), Instant::now(), &Ok(MsgRes::Done));
+            recorder.record(
+                "WaitString".to_string(),
+                Instant::now(),
+                &Err(ApiError::Timeout),
+            );
+            Ok(())
+        });
+        assert_eq!(phase.calls.len(), 2);
+        assert_eq!(phase.calls[1].status, Status::Failed);
+        assert!(recorder.take().is_empty());
+    }
+}
@@ -1,5 +1,6 @@
 use clap::Parser;
 use std::path::PathBuf;
+use std::time::Duration;
 use t_config::ConsoleSSH;
 use t_console::SSH;
 use tracing::{info, Level};
@@ -38,6 +39,7 @@ fn main() {
         log_file: None,
         enable_echo: Some(false),
         linebreak: Some("\n".to_string()),
+        prompt: None,
     }) {
         Ok(mut ssh) => {
             info!("Connected");
@@ -48,8 +50,12 @@ fn main() {
                 .map(|x| x.as_ref()) // arguments are escaped manually since the SSH protocol doesn't support quoting
                 .collect::<Vec<_>>()
                 .join(";");
-            let code = ssh.exec_seperate(command_str).unwrap();
-            println!("Exitcode: {:?}", code);
+            let res = ssh
+                .exec_seperate(command_str, &[], None, Duration::from_secs(60))
+                .unwrap();
+            print!("{}", res.stdout);
+            eprint!("{}", res.stderr);
+            println!("Exitcode: {:?}", res.code);
         }
         Err(e) => {
             println!("connect failed: {:?}", e.to_string());
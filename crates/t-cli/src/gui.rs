@@ -530,7 +530,7 @@ impl eframe::App for Gui {
                             self.state
                                 .config
                                 .as_ref()
-                                .map(|c| c.ssh.is_some())
+                                .map(|c| !c.ssh.is_empty())
                                 .unwrap_or_default(),
                             |ui| ui.selectable_value(&mut self.state.tab, Tab::Ssh, "Ssh"),
                         );
@@ -538,7 +538,7 @@ impl eframe::App for Gui {
                             self.state
                                 .config
                                 .as_ref()
-                                .map(|c| c.serial.is_some())
+                                .map(|c| !c.serial.is_empty())
                                 .unwrap_or_default(),
                             |ui| ui.selectable_value(&mut self.state.tab, Tab::Serial, "Serial"),
                         );
@@ -546,20 +546,18 @@ impl eframe::App for Gui {
                     match self.state.tab {
                         Tab::Vnc => self.render_vnc(ui),
                         Tab::Serial => {
-                            let serial_log_file =
-                                self.state.config.as_ref().and_then(|c| {
-                                    c.serial.as_ref().and_then(|c| c.log_file.clone())
-                                });
+                            let serial_log_file = self.state.config.as_ref().and_then(|c| {
+                                c.serial.get_default().and_then(|c| c.log_file.clone())
+                            });
                             if let Some(path) = serial_log_file {
                                 self.viwer.render_file(ui, &path)
                             }
                         }
                         Tab::Ssh => {
-                            let serial_log_file = self
-                                .state
-                                .config
-                                .as_ref()
-                                .and_then(|c| c.ssh.as_ref().and_then(|c| c.log_file.clone()));
+                            let serial_log_file =
+                                self.state.config.as_ref().and_then(|c| {
+                                    c.ssh.get_default().and_then(|c| c.log_file.clone())
+                                });
                             if let Some(path) = serial_log_file {
                                 self.viwer.render_file(ui, &path)
                             }
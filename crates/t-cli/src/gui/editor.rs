@@ -375,7 +375,7 @@ impl NeedleSource {
         let mut areas = Vec::new();
         for DragedRect { rect, click, .. } in &self.rects {
             let area = t_runner::needle::Area {
-                type_field: "match".to_string(),
+                type_field: t_runner::needle::AreaType::Match,
                 left: rect.left as u16,
                 top: rect.top as u16,
                 width: rect.width as u16,
@@ -384,6 +384,8 @@ impl NeedleSource {
                     left: x as u16,
                     top: y as u16,
                 }),
+                margin: None,
+                text: None,
             };
             areas.push(area);
         }
@@ -3,7 +3,7 @@ pub mod gui;
 use clap::{Parser, Subcommand};
 use std::{env, fs, io::IsTerminal, path::Path};
 use t_binding::api::{Api, RustApi};
-use t_config::Config;
+use t_config::{Config, Suite};
 use t_runner::{DriverBuilder, DriverForScript};
 use tracing::{error, info, Level};
 use tracing_subscriber::FmtSubscriber;
@@ -19,8 +19,11 @@ enum Commands {
     Run {
         #[clap(short, long)]
         config: String,
-        #[clap(short, long)]
-        script: String,
+        #[clap(short, long, required_unless_present = "suite")]
+        script: Option<String>,
+        /// suite manifest, run the listed cases in order
+        #[clap(long, conflicts_with = "script")]
+        suite: Option<String>,
     },
     Record {
         #[clap(short, long)]
@@ -70,23 +73,55 @@ fn main() {
     info!(msg = "current cli", cli = ?cli);
 
     match cli.command {
-        Commands::Run { script, config } => {
+        Commands::Run {
+            script,
+            suite,
+            config,
+        } => {
             // init config
             let config = Config::from_toml_file(config.as_str()).expect("config not valid");
             info!(msg = "current config", config = ?config);
 
-            let ext = Path::new(script.as_str())
-                .extension()
-                .unwrap()
-                .to_string_lossy()
-                .to_string();
+            let suite = match suite.map(Suite::from_toml_file).transpose() {
+                Ok(suite) => suite,
+                Err(e) => {
+                    error!(msg = "suite not valid", reason = ?e);
+                    std::process::exit(1);
+                }
+            };
 
+            // engine is picked by every case file, this is only the fallback
+            let ext = script
+                .iter()
+                .chain(suite.iter().flat_map(|s| s.cases.iter().map(|c| &c.file)))
+                .find_map(|f| Path::new(f).extension())
+                .map(|e| e.to_string_lossy().to_string())
+                .unwrap_or("js".to_string());
+
+            let log_dir = config.log_dir.clone().unwrap_or("log".to_string());
             match DriverForScript::new_with_engine(config, ext.as_str()) {
                 Ok(mut d) => {
-                    d.start().run_file(script).stop();
+                    d.start();
+                    match (script, suite) {
+                        (Some(script), _) => d.run_file(script),
+                        (None, Some(suite)) => d.run_suite(&suite),
+                        (None, None) => unreachable!(),
+                    };
+                    d.stop();
+
+                    let report = d.report();
+                    println!("{}", report.summary());
+                    if let Err(e) = report.save(&log_dir) {
+                        error!(msg = "save report failed", reason = ?e);
+                    }
+                    if !report.is_success() {
+                        error!(msg = "test failed");
+                        std::process::exit(1);
+                    }
                 }
                 Err(e) => {
-                    error!(msg = "Driver init failed", reason = ?e)
+                    error!(msg = "Driver init failed", reason = ?e);
+                    std::process::exit(1);
                 }
             }
         }
@@ -105,8 +140,8 @@ fn main() {
             let mut config = Config::from_toml_str(config.as_str()).expect("config not valid");
             info!(msg = "current config", config = ?config);
 
-            config.ssh = None;
-            config.serial = None;
+            config.ssh.clear();
+            config.serial.clear();
             match DriverBuilder::new(Some(config)).build() {
                 Ok(mut d) => {
                     d.start();
@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
 use std::{collections::HashMap, fs, path::PathBuf, time::Duration};
 
 #[derive(Deserialize, Debug, Clone)]
@@ -10,9 +10,16 @@ pub struct Config {
     pub log_dir: Option<String>,
     pub env: Option<HashMap<String, toml::Value>>,
 
-    pub ssh: Option<ConsoleSSH>,
-    pub serial: Option<ConsoleSerial>,
+    #[serde(default)]
+    pub ssh: Consoles<ConsoleSSH>,
+    #[serde(default)]
+    pub serial: Consoles<ConsoleSerial>,
+    #[serde(default)]
+    pub telnet: Consoles<ConsoleTelnet>,
     pub vnc: Option<ConsoleVNC>,
+
+    pub qemu: Option<ConsoleQemu>,
+    pub power: Option<ConsolePower>,
 }
 
 impl Config {
@@ -24,11 +31,21 @@ impl Config {
 
     fn init(&mut self) {
         let log_dir = self.log_dir.clone().unwrap_or("log".to_string());
-        if let Some(serial) = self.serial.as_mut() {
-            serial.log_file = Some(PathBuf::from_iter(vec![&log_dir, "serial.log"]));
+        self.init_qemu(&log_dir);
+        for (name, serial) in self.serial.iter_mut() {
+            serial.log_file = Some(PathBuf::from_iter(vec![
+                &log_dir,
+                &log_name("serial", name),
+            ]));
+        }
+        for (name, ssh) in self.ssh.iter_mut() {
+            ssh.log_file = Some(PathBuf::from_iter(vec![&log_dir, &log_name("ssh", name)]));
         }
-        if let Some(ssh) = self.ssh.as_mut() {
-            ssh.log_file = Some(PathBuf::from_iter(vec![&log_dir, "ssh.log"]));
+        for (name, telnet) in self.telnet.iter_mut() {
+            telnet.log_file = Some(PathBuf::from_iter(vec![
+                &log_dir,
+                &log_name("telnet", name),
+            ]));
         }
         if let Some(vnc) = self.vnc.as_mut() {
             vnc.screenshot_dir = Some(PathBuf::from_iter(vec![&log_dir, "vnc"]));
@@ -39,6 +56,53 @@ impl Config {
         self.log_dir = Some(log_dir);
     }
 
+    // qemu is started by runner, point consoles to what it exposes
+    fn init_qemu(&mut self, log_dir: &str) {
+        let Some(qemu) = self.qemu.as_mut() else {
+            return;
+        };
+        if qemu.arch.is_none() {
+            qemu.arch = self.arch.clone();
+        }
+        let dir = std::path::absolute(log_dir).unwrap_or(PathBuf::from(log_dir));
+        qemu.qmp_sock = Some(dir.join("qmp.sock"));
+        qemu.log_file = Some(dir.join("qemu.log"));
+
+        if qemu.serial.unwrap_or(true) {
+            let sock = dir.join("serial.sock");
+            qemu.serial_sock = Some(sock.clone());
+            // keep login settings from [serial] if any
+            let serial = self.serial.default_or_insert(ConsoleSerial {
+                serial_file: String::new(),
+                bund_rate: None,
+                r#type: None,
+                disable_echo: None,
+                linebreak: None,
+                prompt: None,
+                auto_login: None,
+                username: None,
+                password: None,
+                log_file: None,
+            });
+            serial.serial_file = sock.display().to_string();
+            serial.r#type = Some(ConsoleSerialType::Sock);
+        }
+        if let (Some(display), None) = (qemu.vnc_display, self.vnc.as_ref()) {
+            self.vnc = Some(ConsoleVNC {
+                host: "127.0.0.1".to_string(),
+                port: 5900 + display,
+                password: None,
+                needle_dir: None,
+                screenshot_dir: None,
+            });
+        }
+        // username and auth still come from [ssh]
+        if let (Some(port), Some(ssh)) = (qemu.ssh_forward, self.ssh.get_default_mut()) {
+            ssh.host = "127.0.0.1".to_string();
+            ssh.port = Some(port);
+        }
+    }
+
     pub fn from_toml_file(s: &str) -> Result<Self, toml::de::Error> {
         let mut config: Config = toml::from_str(fs::read_to_string(s).unwrap().as_str()).unwrap();
         config.init();
@@ -46,6 +110,98 @@ impl Config {
     }
 }
 
+/// name of the console declared as a single table, like [ssh]
+pub const DEFAULT_CONSOLE: &str = "default";
+
+// serial.log for the default console, serial-{name}.log for others
+fn log_name(kind: &str, name: &str) -> String {
+    match name {
+        DEFAULT_CONSOLE => format!("{kind}.log"),
+        name => format!("{kind}-{name}.log"),
+    }
+}
+
+/// consoles of one type, a single table like [ssh], or tables by name like [ssh.server].
+/// the default one is used when no name given, it's the one named default, or the first declared
+#[derive(Debug, Clone)]
+pub struct Consoles<T>(Vec<(String, T)>);
+
+impl<T> Default for Consoles<T> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<T> Consoles<T> {
+    pub fn single(c: T) -> Self {
+        Self(vec![(DEFAULT_CONSOLE.to_string(), c)])
+    }
+
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.0.iter().find(|(n, _)| n == name).map(|(_, c)| c)
+    }
+
+    pub fn get_default(&self) -> Option<&T> {
+        self.0.first().map(|(_, c)| c)
+    }
+
+    pub fn get_default_mut(&mut self) -> Option<&mut T> {
+        self.0.first_mut().map(|(_, c)| c)
+    }
+
+    pub fn default_or_insert(&mut self, c: T) -> &mut T {
+        if self.0.is_empty() {
+            self.0.push((DEFAULT_CONSOLE.to_string(), c));
+        }
+        &mut self.0[0].1
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.0.iter().map(|(n, c)| (n.as_str(), c))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut T)> {
+        self.0.iter_mut().map(|(n, c)| (n.as_str(), c))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+impl<T> IntoIterator for Consoles<T> {
+    type Item = (String, T);
+    type IntoIter = std::vec::IntoIter<(String, T)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Consoles<T> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let table = toml::Table::deserialize(d)?;
+        // console fields are never all tables, so this must be consoles by name
+        if table.is_empty() || !table.values().all(|v| v.is_table()) {
+            return T::deserialize(toml::Value::Table(table))
+                .map(Self::single)
+                .map_err(D::Error::custom);
+        }
+        // declaration order is kept, toml is built with preserve_order
+        let mut res = table
+            .into_iter()
+            .map(|(name, v)| Ok((name, T::deserialize(v).map_err(D::Error::custom)?)))
+            .collect::<Result<Vec<_>, D::Error>>()?;
+        res.sort_by_key(|(name, _)| name != DEFAULT_CONSOLE);
+        Ok(Self(res))
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct ConsoleSSH {
     pub host: String,
@@ -56,6 +212,8 @@ pub struct ConsoleSSH {
     pub timeout: Option<Duration>,
     pub enable_echo: Option<bool>,
     pub linebreak: Option<String>,
+    /// bash, zsh, ash or a regex, exec waits for it before writing cmd
+    pub prompt: Option<String>,
 
     #[serde(skip_serializing)]
     pub log_file: Option<PathBuf>,
@@ -63,11 +221,19 @@ pub struct ConsoleSSH {
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct ConsoleSerial {
+    /// can be omitted with [qemu]
+    #[serde(default)]
     pub serial_file: String,
     pub bund_rate: Option<u32>,
     pub r#type: Option<ConsoleSerialType>,
     pub disable_echo: Option<bool>,
     pub linebreak: Option<String>,
+    /// bash, zsh, ash or a regex, exec waits for it before writing cmd
+    pub prompt: Option<String>,
+    /// log in with username and password after connected
+    pub auto_login: Option<bool>,
+    pub username: Option<String>,
+    pub password: Option<String>,
 
     #[serde(skip_serializing)]
     pub log_file: Option<PathBuf>,
@@ -77,6 +243,67 @@ pub struct ConsoleSerial {
 pub enum ConsoleSerialType {
     Pts,
     Sock,
+    // serial_file is host:port, like qemu `-serial tcp::4444,server`
+    Tcp,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConsoleTelnet {
+    pub host: String,
+    pub port: u16,
+    pub disable_echo: Option<bool>,
+    pub linebreak: Option<String>,
+    /// bash, zsh, ash or a regex, exec waits for it before writing cmd
+    pub prompt: Option<String>,
+
+    #[serde(skip_serializing)]
+    pub log_file: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConsoleQemu {
+    /// defaults to qemu-system-{arch}
+    pub bin: Option<String>,
+    /// defaults to top level arch
+    pub arch: Option<String>,
+    pub machine: Option<String>,
+    pub cpu: Option<String>,
+    pub memory: Option<String>,
+    pub smp: Option<u32>,
+    #[serde(default)]
+    pub disks: Vec<String>,
+    /// write to temporary files instead of disk images
+    pub snapshot: Option<bool>,
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+
+    /// expose serial as unix socket and use it as [serial], default true
+    pub serial: Option<bool>,
+    /// expose vnc on 5900 + display and use it as [vnc]
+    pub vnc_display: Option<u16>,
+    /// forward host port to guest 22 and use it for [ssh]
+    pub ssh_forward: Option<u16>,
+
+    #[serde(skip_serializing)]
+    pub serial_sock: Option<PathBuf>,
+    #[serde(skip_serializing)]
+    pub qmp_sock: Option<PathBuf>,
+    #[serde(skip_serializing)]
+    pub log_file: Option<PathBuf>,
+}
+
+/// power control for real boards, qemu is used if no command set
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConsolePower {
+    /// shell commands, like relay or usb hub control tools
+    pub on: Option<String>,
+    pub off: Option<String>,
+    /// power cycle if not set
+    pub reset: Option<String>,
+    /// secs to stay off in power cycle, default 3
+    pub cycle_delay: Option<u64>,
+    /// regex on serial output means boot finished, default "login:"
+    pub boot_pattern: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -91,4 +318,83 @@ pub struct ConsoleVNC {
 }
 
 #[cfg(test)]
-mod test {}
+mod test {
+    use super::{Config, ConsoleSerialType, DEFAULT_CONSOLE};
+
+    #[test]
+    fn test_qemu_wire_consoles() {
+        let c = Config::from_toml_str(
+            r#"
+            arch = "riscv64"
+            log_dir = "/tmp/autotest"
+            [ssh]
+            host = "192.168.1.2"
+            username = "root"
+            [serial]
+            auto_login = true
+            username = "root"
+            [qemu]
+            machine = "virt"
+            vnc_display = 1
+            ssh_forward = 2222
+            "#,
+        )
+        .unwrap();
+        let qemu = c.qemu.as_ref().unwrap();
+        assert_eq!(qemu.arch.as_deref(), Some("riscv64"));
+
+        let serial = c.serial.get_default().unwrap();
+        assert!(matches!(serial.r#type, Some(ConsoleSerialType::Sock)));
+        assert_eq!(serial.serial_file, "/tmp/autotest/serial.sock");
+        assert_eq!(serial.auto_login, Some(true));
+        assert_eq!(c.vnc.as_ref().unwrap().port, 5901);
+        let ssh = c.ssh.get_default().unwrap();
+        assert_eq!((ssh.host.as_str(), ssh.port), ("127.0.0.1", Some(2222)));
+    }
+
+    #[test]
+    fn test_named_consoles() {
+        let c = Config::from_toml_str(
+            r#"
+            log_dir = "/tmp/autotest"
+            [ssh.server]
+            host = "192.168.1.2"
+            username = "root"
+            [ssh.client]
+            host = "192.168.1.3"
+            username = "root"
+            [serial]
+            serial_file = "/dev/ttyUSB0"
+            "#,
+        )
+        .unwrap();
+        let names: Vec<&str> = c.ssh.iter().map(|(n, _)| n).collect();
+        assert_eq!(names, ["server", "client"]);
+        assert_eq!(c.ssh.get("client").unwrap().host, "192.168.1.3");
+        assert_eq!(c.ssh.get_default().unwrap().host, "192.168.1.2");
+        assert_eq!(
+            c.ssh.get("client").unwrap().log_file.as_ref().unwrap(),
+            &std::path::PathBuf::from("/tmp/autotest/ssh-client.log")
+        );
+
+        let (name, serial) = c.serial.iter().next().unwrap();
+        assert_eq!(
+            (name, serial.serial_file.as_str()),
+            (DEFAULT_CONSOLE, "/dev/ttyUSB0")
+        );
+        assert!(c.telnet.is_empty());
+
+        // default console goes first
+        let c = Config::from_toml_str(
+            r#"
+            log_dir = "/tmp/autotest"
+            [serial.uart1]
+            serial_file = "/dev/ttyUSB1"
+            [serial.default]
+            serial_file = "/dev/ttyUSB0"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(c.serial.get_default().unwrap().serial_file, "/dev/ttyUSB0");
+    }
+}
@@ -1,11 +1,14 @@
 mod config;
+mod suite;
 pub use config::*;
 use std::{error::Error, fmt::Display, fs, io, path::Path};
+pub use suite::{Suite, SuiteCase};
 
 #[derive(Debug)]
 pub enum ConfigError {
     ConfigFileNotFound(io::Error),
     DeserializeFailed(toml::de::Error),
+    InvalidSuite(String),
 }
 
 impl Error for ConfigError {}
@@ -15,6 +18,7 @@ impl Display for ConfigError {
         match self {
             ConfigError::ConfigFileNotFound(e) => write!(f, "{}", e),
             ConfigError::DeserializeFailed(e) => write!(f, "{}", e),
+            ConfigError::InvalidSuite(e) => write!(f, "invalid suite: {}", e),
         }
     }
 }
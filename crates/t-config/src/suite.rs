@@ -0,0 +1,142 @@
+use crate::ConfigError;
+use serde::Deserialize;
+use std::{collections::HashSet, fs, path::Path};
+
+/// ordered list of cases, run one by one over the same connections
+///
+/// ```toml
+/// [[case]]
+/// file = "cases/boot.js"
+/// timeout = 600
+/// fatal = true
+///
+/// [[case]]
+/// name = "network"
+/// file = "cases/network.lua"
+/// depends_on = ["boot"]
+/// ignore_failure = true
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct Suite {
+    #[serde(rename = "case", default)]
+    pub cases: Vec<SuiteCase>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SuiteCase {
+    /// defaults to file stem
+    #[serde(default)]
+    pub name: String,
+    pub file: String,
+    /// secs
+    pub timeout: Option<u64>,
+    /// skip this case if any of these cases not passed
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// abort the suite if this case failed
+    #[serde(default)]
+    pub fatal: bool,
+    /// failure of this case doesn't fail the suite
+    #[serde(default)]
+    pub ignore_failure: bool,
+}
+
+impl Suite {
+    pub fn from_toml_str(s: &str) -> Result<Self, ConfigError> {
+        let mut suite: Suite = toml::from_str(s).map_err(ConfigError::DeserializeFailed)?;
+        suite.init()?;
+        Ok(suite)
+    }
+
+    /// case files are relative to the suite file
+    pub fn from_toml_file(f: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let f = f.as_ref();
+        let s = fs::read_to_string(f).map_err(ConfigError::ConfigFileNotFound)?;
+        let mut suite: Suite = toml::from_str(&s).map_err(ConfigError::DeserializeFailed)?;
+        if let Some(base) = f.parent() {
+            for case in suite.cases.iter_mut() {
+                case.file = base.join(&case.file).display().to_string();
+            }
+        }
+        suite.init()?;
+        Ok(suite)
+    }
+
+    fn init(&mut self) -> Result<(), ConfigError> {
+        let mut names = HashSet::new();
+        for case in self.cases.iter_mut() {
+            if case.name.is_empty() {
+                case.name = Path::new(&case.file)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or(case.file.clone());
+            }
+            if case.fatal && case.ignore_failure {
+                return Err(ConfigError::InvalidSuite(format!(
+                    "case {}: fatal and ignore_failure can't be both set",
+                    case.name
+                )));
+            }
+            // cases run in order, so only earlier cases can be depended on
+            if let Some(dep) = case.depends_on.iter().find(|d| !names.contains(*d)) {
+                return Err(ConfigError::InvalidSuite(format!(
+                    "case {}: depends on {}, which is not listed before it",
+                    case.name, dep
+                )));
+            }
+            if !names.insert(case.name.clone()) {
+                return Err(ConfigError::InvalidSuite(format!(
+                    "case {}: duplicate name",
+                    case.name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Suite;
+
+    #[test]
+    fn test_suite() {
+        let suite = Suite::from_toml_str(
+            r#"
+            [[case]]
+            file = "cases/boot.js"
+            timeout = 600
+            fatal = true
+
+            [[case]]
+            name = "net"
+            file = "cases/network.lua"
+            depends_on = ["boot"]
+            ignore_failure = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(suite.cases[0].name, "boot");
+        assert_eq!(suite.cases[0].timeout, Some(600));
+        assert!(suite.cases[1].ignore_failure);
+        assert!(!suite.cases[1].fatal);
+    }
+
+    #[test]
+    fn test_suite_invalid_depends() {
+        let res = Suite::from_toml_str(
+            r#"
+            [[case]]
+            file = "a.js"
+            depends_on = ["b"]
+
+            [[case]]
+            file = "b.js"
+            "#,
+        );
+        assert!(res.is_err());
+        assert!(
+            Suite::from_toml_str("[[case]]\nfile = \"a.js\"\n[[case]]\nfile = \"a.js\"").is_err()
+        );
+    }
+}
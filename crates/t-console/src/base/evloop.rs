@@ -1,14 +1,23 @@
 use std::{
+    fmt::Display,
     fs::{File, OpenOptions},
     io::{self, Read, Write},
     path::PathBuf,
-    sync::mpsc::{self, channel, Receiver, Sender},
+    sync::{
+        mpsc::{self, channel, Receiver, Sender},
+        Arc,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::{ConsoleError, Result};
-use tracing::{debug, error, warn};
+use parking_lot::{Condvar, Mutex};
+use tracing::{debug, error, info, warn};
+
+// reconnect delay doubles on every failed try, up to the max
+const RECONNECT_DELAY: Duration = Duration::from_millis(20);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 pub enum Req {
@@ -22,9 +31,120 @@ pub enum Res {
     Value(Vec<u8>),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnState {
+    Connected,
+    // connection dropped, evloop will try to connect again
+    Lost,
+    Reconnecting,
+}
+
+impl Display for ConnState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnState::Connected => write!(f, "connected"),
+            ConnState::Lost => write!(f, "lost"),
+            ConnState::Reconnecting => write!(f, "reconnecting"),
+        }
+    }
+}
+
+/// one change of connection state
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnEvent {
+    pub state: ConnState,
+    pub time: String,
+    pub reason: Option<String>,
+}
+
+struct Health {
+    state: ConnState,
+    // changes since last take_events
+    events: Vec<ConnEvent>,
+    connects: u64,
+}
+
+/// connection state of a console, shared by evloop and its users
+#[derive(Clone)]
+pub struct ConnHealth {
+    inner: Arc<(Mutex<Health>, Condvar)>,
+}
+
+impl ConnHealth {
+    fn new() -> Self {
+        Self {
+            inner: Arc::new((
+                Mutex::new(Health {
+                    state: ConnState::Connected,
+                    events: Vec::new(),
+                    connects: 1,
+                }),
+                Condvar::new(),
+            )),
+        }
+    }
+
+    pub fn state(&self) -> ConnState {
+        self.inner.0.lock().state
+    }
+
+    /// times connected, changes after every reconnect
+    pub fn connects(&self) -> u64 {
+        self.inner.0.lock().connects
+    }
+
+    fn set(&self, state: ConnState, reason: Option<String>) {
+        let (health, cond) = &*self.inner;
+        let mut health = health.lock();
+        if health.state == state {
+            return;
+        }
+        info!(msg = "console connection state changed", state = %state, reason = ?reason);
+        if state == ConnState::Connected {
+            health.connects += 1;
+        }
+        health.state = state;
+        health.events.push(ConnEvent {
+            state,
+            time: t_util::get_dt(),
+            reason,
+        });
+        cond.notify_all();
+    }
+
+    /// block until connected, Timeout if still not after timeout
+    pub fn wait_connected(&self, timeout: Duration) -> Result<()> {
+        self.wait(timeout, |h| h.state == ConnState::Connected)
+    }
+
+    /// like wait_connected, but the connection has to be newer than connects() returned since
+    pub fn wait_reconnected(&self, since: u64, timeout: Duration) -> Result<()> {
+        self.wait(timeout, |h| {
+            h.state == ConnState::Connected && h.connects > since
+        })
+    }
+
+    fn wait(&self, timeout: Duration, ready: impl Fn(&Health) -> bool) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        let (health, cond) = &*self.inner;
+        let mut health = health.lock();
+        while !ready(&health) {
+            if cond.wait_until(&mut health, deadline).timed_out() {
+                return Err(ConsoleError::Timeout);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn take_events(&self) -> Vec<ConnEvent> {
+        std::mem::take(&mut self.inner.0.lock().events)
+    }
+}
+
 pub struct EvLoopCtl {
     req_tx: Sender<(Req, Sender<Res>)>,
     stop_tx: Sender<Sender<()>>,
+    health: ConnHealth,
 }
 
 impl EvLoopCtl {
@@ -41,6 +161,10 @@ impl EvLoopCtl {
         rx.recv_timeout(timeout)
     }
 
+    pub fn health(&self) -> &ConnHealth {
+        &self.health
+    }
+
     pub fn stop(&self) {
         let (tx, rx) = channel();
         if self.stop_tx.send(tx).is_err() {
@@ -55,6 +179,8 @@ impl EvLoopCtl {
 pub struct EventLoop<T> {
     make_conn: Box<dyn Fn() -> Result<T>>,
     conn: Option<T>,
+    health: ConnHealth,
+    reconnect_delay: Duration,
     req_rx: Receiver<(Req, Sender<Res>)>,
     stop_rx: Receiver<Sender<()>>,
     history: Vec<u8>,
@@ -87,10 +213,14 @@ where
 
         let (req_tx, req_rx) = mpsc::channel();
         let (stop_tx, stop_rx) = mpsc::channel();
+        let health = ConnHealth::new();
 
+        let evloop_health = health.clone();
         thread::spawn(move || {
             Self {
                 conn: Some(conn),
+                health: evloop_health,
+                reconnect_delay: RECONNECT_DELAY,
                 make_conn: Box::new(make_conn),
                 req_rx,
                 stop_rx,
@@ -101,7 +231,11 @@ where
             }
             .pool();
         });
-        Ok(EvLoopCtl { req_tx, stop_tx })
+        Ok(EvLoopCtl {
+            req_tx,
+            stop_tx,
+            health,
+        })
     }
 
     fn pool(&mut self) {
@@ -112,16 +246,13 @@ where
             }
 
             if self.conn.is_none() {
-                if let Ok(conn) = self.make_conn.as_mut()() {
-                    self.conn = Some(conn);
-                } else {
-                    thread::sleep(Duration::from_millis(20));
-                }
+                self.reconnect();
             }
 
             // handle tty output
             if let Err(e) = self.try_read_buffer() {
                 error!(msg="connection lost", reason = ?e);
+                self.health.set(ConnState::Lost, Some(e.to_string()));
                 break 'out;
             }
 
@@ -136,6 +267,7 @@ where
                         Req::Write(msg) => {
                             if let Err(e) = self.write_buffer(&msg) {
                                 error!(msg="connection lost", reason = ?e);
+                                self.health.set(ConnState::Lost, Some(e.to_string()));
                                 break 'out;
                             }
                             debug!(msg = "write done");
@@ -159,81 +291,84 @@ where
         }
     }
 
+    fn reconnect(&mut self) {
+        self.health.set(ConnState::Reconnecting, None);
+        match self.make_conn.as_mut()() {
+            Ok(conn) => {
+                self.conn = Some(conn);
+                self.reconnect_delay = RECONNECT_DELAY;
+                self.health.set(ConnState::Connected, None);
+            }
+            Err(e) => {
+                debug!(msg = "reconnect failed", reason = %e);
+                thread::sleep(self.reconnect_delay);
+                self.reconnect_delay = (self.reconnect_delay * 2).min(RECONNECT_DELAY_MAX);
+            }
+        }
+    }
+
+    // drop conn, relese fd, release /dev/ttyUSB0. evloop connects again later
+    fn lose_conn(&mut self, e: &io::Error) {
+        warn!(msg = "connection lost, reconnecting", reason = ?e);
+        self.conn = None;
+        self.health.set(ConnState::Lost, Some(e.to_string()));
+    }
+
     fn try_read_buffer(&mut self) -> Result<Vec<u8>> {
-        let mut set_none = false;
-        if let Some(conn) = self.conn.as_mut() {
-            match conn.read(&mut self.buffer) {
-                Ok(n) => {
-                    if n == 0 {
-                        return Ok(Vec::new());
-                    }
-                    let received = &self.buffer[0..n];
-                    self.history.extend(received);
+        let Some(conn) = self.conn.as_mut() else {
+            return Ok(Vec::new());
+        };
+        match conn.read(&mut self.buffer) {
+            Ok(n) => {
+                if n == 0 {
+                    return Ok(Vec::new());
+                }
+                let received = &self.buffer[0..n];
+                self.history.extend(received);
 
-                    if let Some(ref mut log_file) = self.log_file {
-                        if let Err(e) = log_file.write_all(received) {
-                            warn!(msg = "unable write to log", reason = ?e);
-                            self.log_file = None;
-                        }
+                if let Some(ref mut log_file) = self.log_file {
+                    if let Err(e) = log_file.write_all(received) {
+                        warn!(msg = "unable write to log", reason = ?e);
+                        self.log_file = None;
                     }
-                    return Ok(received.to_vec());
                 }
-                Err(e) => match e.kind() {
-                    io::ErrorKind::ConnectionRefused
-                    | io::ErrorKind::ConnectionReset
-                    | io::ErrorKind::BrokenPipe => {
-                        // drop conn, relese fd, release /dev/ttyUSB0
-                        set_none = true;
-                    }
-                    io::ErrorKind::TimedOut => return Ok(Vec::new()),
-                    _ => {
-                        error!(msg = "read failed, connection may be broken", reason = ?e);
-                        return Err(ConsoleError::IO(e));
-                    }
-                },
+                Ok(received.to_vec())
             }
+            Err(e) => match e.kind() {
+                io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::BrokenPipe => {
+                    self.lose_conn(&e);
+                    Ok(Vec::new())
+                }
+                io::ErrorKind::TimedOut => Ok(Vec::new()),
+                _ => {
+                    error!(msg = "read failed, connection may be broken", reason = ?e);
+                    Err(ConsoleError::IO(e))
+                }
+            },
         }
-        if set_none {
-            self.conn = None;
-        }
-        Ok(Vec::new())
     }
 
     fn write_buffer(&mut self, bytes: &[u8]) -> Result<()> {
-        let mut set_none = false;
-        if let Some(conn) = self.conn.as_mut() {
-            if let Err(e) = conn.write_all(bytes) {
-                match e.kind() {
-                    io::ErrorKind::ConnectionRefused
-                    | io::ErrorKind::ConnectionReset
-                    | io::ErrorKind::BrokenPipe => {
-                        set_none = true;
-                    }
-                    io::ErrorKind::TimedOut => return Ok(()),
-                    _ => {
-                        error!(msg = "write failed, connection may be broken", reason = ?e);
-                        return Err(ConsoleError::IO(e));
-                    }
+        let Some(conn) = self.conn.as_mut() else {
+            return Ok(());
+        };
+        let res = conn.write_all(bytes).and_then(|_| conn.flush());
+        if let Err(e) = res {
+            match e.kind() {
+                io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::BrokenPipe => {
+                    self.lose_conn(&e);
                 }
-            }
-            if let Err(e) = conn.flush() {
-                match e.kind() {
-                    io::ErrorKind::ConnectionRefused
-                    | io::ErrorKind::ConnectionReset
-                    | io::ErrorKind::BrokenPipe => {
-                        set_none = true;
-                    }
-                    io::ErrorKind::TimedOut => return Ok(()),
-                    _ => {
-                        error!(msg = "flush failed, connection may be broken", reason = ?e);
-                        return Err(ConsoleError::IO(e));
-                    }
+                io::ErrorKind::TimedOut => return Ok(()),
+                _ => {
+                    error!(msg = "write failed, connection may be broken", reason = ?e);
+                    return Err(ConsoleError::IO(e));
                 }
             }
         }
-        if set_none {
-            self.conn = None;
-        }
         Ok(())
     }
 
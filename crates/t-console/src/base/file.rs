@@ -0,0 +1,135 @@
+use super::{tty::Tty, util::shell_quote};
+use crate::{term::Term, ConsoleError, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+// raw bytes sent in one heredoc, small enough to not overflow tty input buffer
+const CHUNK_SIZE: usize = 2048;
+
+// raw bytes read by one exec, exec output is parsed again on every read
+const DOWNLOAD_CHUNK_SIZE: usize = 16384;
+
+// base64 line length, same as coreutils base64
+const LINE_WIDTH: usize = 76;
+
+/// write content to remote file through shell, base64 encoded and checked with md5sum
+pub fn upload<T: Term>(
+    tty: &mut Tty<T>,
+    content: &[u8],
+    remote: &str,
+    mode: Option<i32>,
+    timeout: Duration,
+) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    let remote = shell_quote(remote);
+    info!(msg = "serial upload", remote = remote, size = content.len());
+
+    run(tty, deadline, &format!(": > {remote}"))?;
+    for chunk in content.chunks(CHUNK_SIZE) {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let (code, _) = tty.exec_heredoc(
+            left,
+            &format!("base64 -d >> {remote}"),
+            &encode_lines(chunk),
+        )?;
+        if code != 0 {
+            return Err(ConsoleError::Transfer(format!(
+                "write {remote} failed, code {code}"
+            )));
+        }
+    }
+    if let Some(mode) = mode {
+        run(tty, deadline, &format!("chmod {mode:o} {remote}"))?;
+    }
+
+    let remote_md5 = run(tty, deadline, &format!("md5sum {remote}"))?;
+    check_md5(content, &remote_md5)
+}
+
+/// read remote file through shell chunk by chunk, base64 encoded and checked with md5sum
+pub fn download<T: Term>(tty: &mut Tty<T>, remote: &str, timeout: Duration) -> Result<Vec<u8>> {
+    let deadline = Instant::now() + timeout;
+    let remote = shell_quote(remote);
+    info!(msg = "serial download", remote = remote);
+
+    let size = run(tty, deadline, &format!("wc -c < {remote}"))?;
+    let size: usize = size.trim().parse().map_err(|_| {
+        ConsoleError::Transfer(format!("invalid size of {remote}, {}", size.trim()))
+    })?;
+    let mut content = Vec::with_capacity(size);
+    for i in 0..size.div_ceil(DOWNLOAD_CHUNK_SIZE) {
+        let encoded = run(
+            tty,
+            deadline,
+            &format!(
+                "dd if={remote} bs={DOWNLOAD_CHUNK_SIZE} skip={i} count=1 2>/dev/null | base64"
+            ),
+        )?;
+        content.extend(decode_lines(&encoded)?);
+    }
+    let remote_md5 = run(tty, deadline, &format!("md5sum {remote}"))?;
+    check_md5(&content, &remote_md5)?;
+    Ok(content)
+}
+
+// exec with time left, non zero exit code is an error
+fn run<T: Term>(tty: &mut Tty<T>, deadline: Instant, cmd: &str) -> Result<String> {
+    let left = deadline.saturating_duration_since(Instant::now());
+    match tty.exec(left, cmd)? {
+        (0, output) => Ok(output),
+        (code, output) => Err(ConsoleError::Transfer(format!(
+            "{cmd} failed, code {code}, {output}"
+        ))),
+    }
+}
+
+fn encode_lines(chunk: &[u8]) -> String {
+    STANDARD
+        .encode(chunk)
+        .as_bytes()
+        .chunks(LINE_WIDTH)
+        .map(|l| String::from_utf8_lossy(l) + "\n")
+        .collect()
+}
+
+// output may have \r and other whitespace from console
+fn decode_lines(s: &str) -> Result<Vec<u8>> {
+    let s: String = s.split_whitespace().collect();
+    STANDARD
+        .decode(s)
+        .map_err(|e| ConsoleError::Transfer(format!("invalid base64 output, {e}")))
+}
+
+// md5sum prints "hash  file"
+fn check_md5(content: &[u8], md5sum_output: &str) -> Result<()> {
+    let local = format!("{:x}", md5::compute(content));
+    match md5sum_output.split_whitespace().next() {
+        Some(remote) if remote == local => Ok(()),
+        _ => Err(ConsoleError::Transfer(format!(
+            "checksum mismatch, local {local}, remote {md5sum_output}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let content: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let lines = encode_lines(&content);
+        assert!(lines.lines().all(|l| l.len() <= LINE_WIDTH));
+        assert_eq!(decode_lines(&lines.replace('\n', "\r\n")).unwrap(), content);
+        assert!(decode_lines("not base64!").is_err());
+    }
+
+    #[test]
+    fn test_check_md5() {
+        let output = "5d41402abc4b2a76b9719d911017c592  /tmp/a\n";
+        assert!(check_md5(b"hello", output).is_ok());
+        assert!(check_md5(b"world", output).is_err());
+        assert!(check_md5(b"hello", "").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use super::tty::Tty;
+use crate::{term::Term, ConsoleError, Result};
+use regex::Regex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+// getty gives up after a few wrong attempts too
+const MAX_RETRY: usize = 3;
+
+// same as exec
+const ENTER: &str = "\r";
+
+// index of each pattern passed to wait_match
+const LOGIN: usize = 0;
+const PASSWORD: usize = 1;
+const INCORRECT: usize = 2;
+const SHELL: usize = 3;
+
+/// prompts are the last thing on screen, so anchor them to the end of output
+fn patterns(shell_prompt: Regex) -> Vec<Regex> {
+    let mut res: Vec<Regex> = [
+        r"(?i)login:\s*$",
+        r"(?i)password:\s*$",
+        r"(?i)login incorrect|authentication failure",
+    ]
+    .iter()
+    .map(|p| Regex::new(p).expect("login pattern is valid"))
+    .collect();
+    res.push(shell_prompt);
+    res
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    // nothing sent yet, console may be at any prompt
+    Start,
+    Username,
+    Password,
+    Ready,
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Send(String),
+    Wait,
+    Done,
+    Fail(String),
+}
+
+struct Login<'a> {
+    username: &'a str,
+    password: Option<&'a str>,
+    state: State,
+    retry: usize,
+}
+
+impl<'a> Login<'a> {
+    fn new(username: &'a str, password: Option<&'a str>) -> Self {
+        Self {
+            username,
+            password,
+            state: State::Start,
+            retry: 0,
+        }
+    }
+
+    // decide what to do after pattern at index showed
+    fn next(&mut self, index: usize) -> Action {
+        match index {
+            LOGIN => {
+                self.state = State::Username;
+                Action::Send(self.username.to_string())
+            }
+            PASSWORD => match self.password {
+                Some(p) => {
+                    self.state = State::Password;
+                    Action::Send(p.to_string())
+                }
+                None => Action::Fail("password required, but not configured".to_string()),
+            },
+            INCORRECT => {
+                self.retry += 1;
+                if self.retry >= MAX_RETRY {
+                    return Action::Fail(format!("login incorrect after {} retries", self.retry));
+                }
+                // next login prompt may come with this message, ask for a fresh one
+                self.state = State::Start;
+                Action::Send(String::new())
+            }
+            SHELL => {
+                self.state = State::Ready;
+                Action::Done
+            }
+            _ => Action::Wait,
+        }
+    }
+}
+
+/// log into a getty style console, return once shell prompt shows.
+/// boot and getty output may end with any prompt char, so a shell prompt is required
+pub fn auto_login<T: Term>(
+    tty: &mut Tty<T>,
+    username: &str,
+    password: Option<&str>,
+    timeout: Duration,
+) -> Result<()> {
+    let patterns = patterns(tty.shell_prompt());
+    let mut login = Login::new(username, password);
+    let deadline = Instant::now() + timeout;
+
+    // wake up console, a logged in shell answers with prompt too
+    tty.discard_buffer();
+    tty.write_string(ENTER, timeout)?;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            warn!(msg = "auto login timeout", state = ?login.state);
+            return Err(ConsoleError::Timeout);
+        }
+        let m = tty.wait_match(left, &patterns, 1)?;
+        match login.next(m.index) {
+            Action::Send(s) => {
+                info!(msg = "auto login", state = ?login.state);
+                // matched prompt is waiting for input, nothing after it to lose
+                tty.discard_buffer();
+                tty.write_string(&format!("{}{}", s, ENTER), left)?;
+            }
+            Action::Wait => {}
+            // keep shell prompt for exec
+            Action::Done => {
+                info!(msg = "auto login success", username = username);
+                return Ok(());
+            }
+            Action::Fail(s) => return Err(ConsoleError::NoConnection(s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::base::prompt::Shell;
+
+    #[test]
+    fn test_login_state() {
+        let mut l = Login::new("root", Some("pass"));
+        assert_eq!(l.next(LOGIN), Action::Send("root".to_string()));
+        assert_eq!(l.next(PASSWORD), Action::Send("pass".to_string()));
+        assert_eq!(l.state, State::Password);
+        assert_eq!(l.next(INCORRECT), Action::Send(String::new()));
+        assert_eq!(l.state, State::Start);
+        assert_eq!(l.next(LOGIN), Action::Send("root".to_string()));
+        assert_eq!(l.next(PASSWORD), Action::Send("pass".to_string()));
+        assert_eq!(l.next(SHELL), Action::Done);
+
+        let mut l = Login::new("root", None);
+        assert!(matches!(l.next(PASSWORD), Action::Fail(_)));
+        (0..MAX_RETRY - 1).for_each(|_| assert!(matches!(l.next(INCORRECT), Action::Send(_))));
+        assert!(matches!(l.next(INCORRECT), Action::Fail(_)));
+    }
+
+    #[test]
+    fn test_login_patterns() {
+        let p = patterns(Shell::any_prompt());
+        let matched = |s: &str| p.iter().position(|p| p.is_match(s));
+        assert_eq!(
+            matched("\nUbuntu 22.04 ttyS0\n\nubuntu login: "),
+            Some(LOGIN)
+        );
+        assert_eq!(
+            matched("Last login: Mon Oct 1 on ttyS0\nroot@ubuntu:~# "),
+            Some(SHELL)
+        );
+        assert_eq!(matched("ubuntu login: root\nPassword: "), Some(PASSWORD));
+        assert_eq!(matched("Password: \nLogin incorrect\n"), Some(INCORRECT));
+        // not a shell, though they end with a prompt char
+        assert_eq!(matched("[  OK  ] Reached target Multi-User System >"), None);
+        assert_eq!(matched("Loading initial ramdisk ... 100%"), None);
+        assert_eq!(matched("ubuntu% "), Some(SHELL));
+    }
+}
@@ -1,2 +1,7 @@
 pub mod evloop;
+pub mod file;
+pub mod login;
+pub mod prompt;
+pub mod stream;
 pub mod tty;
+pub mod util;
@@ -0,0 +1,81 @@
+use regex::Regex;
+
+/// any line ending with a common prompt char, used when no prompt configured
+pub const DEFAULT_PROMPT: &str = r"[#$%>]\s*$";
+
+/// shells with their default prompt, all patterns are anchored to the end of output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shell {
+    // \u@\h:\w\$, like root@ubuntu:~#
+    Bash,
+    // %m%#, like ubuntu%
+    Zsh,
+    // busybox \w \$, like ~ #
+    Ash,
+}
+
+impl Shell {
+    // most specific first, ash prompt is a suffix of the others
+    const ALL: [Shell; 3] = [Shell::Bash, Shell::Zsh, Shell::Ash];
+
+    pub fn prompt(&self) -> &'static str {
+        match self {
+            Shell::Bash => r"[\w.-]+@[\w.-]+:[^\n]*[#$] $",
+            Shell::Zsh => r"(^|\n)[\w.-]+[%#] $",
+            Shell::Ash => r"(^|\n)[^\n]* [#$] $",
+        }
+    }
+
+    /// prompt of any known shell, stricter than DEFAULT_PROMPT
+    pub fn any_prompt() -> Regex {
+        let all: Vec<&str> = Self::ALL.iter().map(|s| s.prompt()).collect();
+        Regex::new(&all.join("|")).expect("shell prompt is valid")
+    }
+
+    /// guess shell from the prompt at the end of output
+    pub fn detect(output: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| {
+            Regex::new(s.prompt())
+                .expect("shell prompt is valid")
+                .is_match(output)
+        })
+    }
+}
+
+/// prompt config is a shell name or a regex
+pub fn prompt_regex(s: &str) -> Result<Regex, regex::Error> {
+    Regex::new(match s {
+        "bash" => Shell::Bash.prompt(),
+        "zsh" => Shell::Zsh.prompt(),
+        "ash" | "busybox" => Shell::Ash.prompt(),
+        s => s,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{prompt_regex, Shell};
+
+    #[test]
+    fn test_detect_shell() {
+        for (output, shell) in [
+            ("ls\nroot@ubuntu:~# ", Some(Shell::Bash)),
+            ("pi@raspberrypi:/tmp $ ", Some(Shell::Bash)),
+            ("exit\nubuntu% ", Some(Shell::Zsh)),
+            ("BusyBox v1.36.1\n~ # ", Some(Shell::Ash)),
+            ("/tmp $ ", Some(Shell::Ash)),
+            ("-0abc\n", None),
+        ] {
+            assert_eq!(Shell::detect(output), shell, "{output}");
+        }
+    }
+
+    #[test]
+    fn test_prompt_regex() {
+        assert!(prompt_regex("bash").unwrap().is_match("root@ubuntu:~# "));
+        assert!(prompt_regex(r"\(venv\) \$ $")
+            .unwrap()
+            .is_match("(venv) $ "));
+        assert!(prompt_regex("(").is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+/// stream in the shape evloop expects: reads never block,
+/// and a closed peer is reported as reset so evloop reconnects
+pub struct StreamConn<S>(S);
+
+impl StreamConn<TcpStream> {
+    pub fn connect_tcp(addr: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(Duration::from_millis(10)))?;
+        stream.set_nodelay(true)?;
+        Ok(Self(stream))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl StreamConn<std::os::unix::net::UnixStream> {
+    pub fn connect_unix(path: &str) -> io::Result<Self> {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(Duration::from_millis(10)))?;
+        Ok(Self(stream))
+    }
+}
+
+impl<S: Read> Read for StreamConn<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            Ok(0) => Err(io::ErrorKind::ConnectionReset.into()),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(io::ErrorKind::TimedOut.into()),
+            res => res,
+        }
+    }
+}
+
+impl<S: Write> Write for StreamConn<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
@@ -1,6 +1,8 @@
-use super::evloop::{EvLoopCtl, Req, Res};
+use super::evloop::{ConnHealth, EvLoopCtl, Req, Res};
+use super::prompt::{prompt_regex, Shell, DEFAULT_PROMPT};
 use crate::{term::Term, ConsoleError};
 use parking_lot::Mutex;
+use regex::Regex;
 use std::{
     marker::PhantomData,
     sync::mpsc::Receiver,
@@ -11,6 +13,12 @@ use tracing::{debug, error, info};
 
 type Result<T> = std::result::Result<T, ConsoleError>;
 
+// ends heredoc input of exec_heredoc
+const HEREDOC_END: &str = "AUTOTEST_EOF";
+
+// how long exec waits for a common prompt char when no prompt is configured
+const GUESS_PROMPT_WAIT: Duration = Duration::from_secs(1);
+
 struct State {
     // store all tty output bytes
     history: Vec<u8>,
@@ -18,9 +26,29 @@ struct State {
     last_buffer_start: usize,
 }
 
+/// which pattern matched the console output, and what it matched
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaitMatch {
+    pub index: usize,
+    pub text: String,
+    // capture groups, None if the group didn't participate in the match
+    pub groups: Vec<Option<String>>,
+}
+
 pub struct TtySetting {
     pub disable_echo: bool,
     pub linebreak: String,
+    // exec waits for it before writing cmd
+    pub prompt: Option<Regex>,
+}
+
+impl TtySetting {
+    pub fn prompt_from_config(prompt: Option<&String>) -> Result<Option<Regex>> {
+        prompt
+            .map(|p| prompt_regex(p))
+            .transpose()
+            .map_err(|e| ConsoleError::Config(format!("invalid prompt, {}", e)))
+    }
 }
 
 pub struct Tty<T: Term> {
@@ -61,6 +89,10 @@ where
         self.ctl.stop();
     }
 
+    pub fn health(&self) -> ConnHealth {
+        self.ctl.health().clone()
+    }
+
     fn try_handle_stop_signal(&self) -> bool {
         // stop on receive done signal
         self.stop_rx.lock().try_recv().is_ok()
@@ -80,30 +112,133 @@ where
     }
 
     pub fn wait_string(&mut self, timeout: Duration, pattern: &str) -> Result<String> {
-        info!(msg = "wait_string", pattern = pattern);
+        let pattern = Regex::new(&regex::escape(pattern)).expect("escaped pattern is valid");
+        self.wait_match(timeout, &[pattern], 1).map(|m| m.text)
+    }
+
+    /// drop output received so far, later waits only see what comes after
+    pub fn discard_buffer(&self) {
+        let mut state = self.state.lock();
+        if let Ok(Res::Value(recv)) = self
+            .ctl
+            .send_timeout(Req::Read, Duration::from_millis(1000))
+        {
+            state.history.extend(recv);
+        }
+        state.last_buffer_start = state.history.len();
+    }
+
+    /// wait until any of patterns matched n times, the pattern whose n-th match
+    /// ends first wins
+    pub fn wait_match(
+        &mut self,
+        timeout: Duration,
+        patterns: &[Regex],
+        n: usize,
+    ) -> Result<WaitMatch> {
+        info!(msg = "wait_match", patterns = ?patterns, n = n);
         self.comsume_buffer_and_map(timeout, |buffer, new| {
-            {
-                let buffer_str = Tm::parse_and_strip(buffer);
-                let new_str = Tm::parse_and_strip(new);
-                let res = count_substring(&buffer_str, pattern, 1);
-                info!(
-                    msg = "wait_string",
-                    pattern = pattern,
-                    res = res,
-                    new_buffer = new_str,
-                );
-                res.then_some(buffer_str)
-            }
-            .map_or(ConsumeAction::Continue, ConsumeAction::BreakValue)
+            let buffer_str = Tm::parse_and_strip(buffer);
+            let new_str = Tm::parse_and_strip(new);
+            let res = find_nth_match(&buffer_str, patterns, n);
+            info!(
+                msg = "wait_match",
+                patterns = ?patterns,
+                res = ?res,
+                new_buffer = new_str,
+            );
+            res.map_or(ConsumeAction::Continue, ConsumeAction::BreakValue)
         })
     }
 
+    /// configured prompt, or any line ending with a common prompt char
+    pub fn prompt(&self) -> Regex {
+        self.setting
+            .prompt
+            .clone()
+            .unwrap_or(Regex::new(DEFAULT_PROMPT).expect("default prompt is valid"))
+    }
+
+    /// configured prompt, or prompt of a known shell, what tells a shell from other output
+    pub fn shell_prompt(&self) -> Regex {
+        self.setting
+            .prompt
+            .clone()
+            .unwrap_or_else(Shell::any_prompt)
+    }
+
+    /// wait until shell is idle and showing prompt
+    pub fn wait_prompt(&mut self, timeout: Duration) -> Result<()> {
+        let prompt = self.prompt();
+        // only peek, prompt stays in buffer for the next exec
+        let start = self.state.lock().last_buffer_start;
+        self.wait_match(timeout, &[prompt], 1)?;
+        self.state.lock().last_buffer_start = start;
+        Ok(())
+    }
+
     pub fn exec(&mut self, timeout: Duration, cmd: &str) -> Result<(i32, String)> {
+        self.exec_stream(timeout, cmd, |_| {})
+    }
+
+    /// like exec, but pass each complete output line to on_output as soon as it shows,
+    /// the rest is passed once cmd finished
+    pub fn exec_stream(
+        &mut self,
+        timeout: Duration,
+        cmd: &str,
+        on_output: impl FnMut(&str),
+    ) -> Result<(i32, String)> {
+        self.exec_inner(timeout, cmd, None, on_output)
+    }
+
+    /// like exec, lines of input are passed to cmd's stdin with a heredoc
+    pub fn exec_heredoc(
+        &mut self,
+        timeout: Duration,
+        cmd: &str,
+        input: &str,
+    ) -> Result<(i32, String)> {
+        self.exec_inner(timeout, cmd, Some(input), |_| {})
+    }
+
+    fn exec_inner(
+        &mut self,
+        timeout: Duration,
+        cmd: &str,
+        input: Option<&str>,
+        mut on_output: impl FnMut(&str),
+    ) -> Result<(i32, String)> {
         info!(msg = "exec", cmd = cmd);
         let enter_input: &'static str = "\r";
+        let deadline = Instant::now() + timeout;
 
-        // wait for prompt show, cmd may write too fast before prompt show, which will broken regex
-        std::thread::sleep(Duration::from_millis(70));
+        // cmd <<'END'; echo ...\rline\rEND\r, shell reads the lines before running cmd
+        let (cmd, heredoc) = match input {
+            Some(input) => (
+                format!("{cmd} <<'{HEREDOC_END}'"),
+                input
+                    .lines()
+                    .chain([HEREDOC_END])
+                    .map(|l| format!("{l}{enter_input}"))
+                    .collect(),
+            ),
+            None => (cmd.to_string(), String::new()),
+        };
+
+        // cmd may write too fast before prompt show, which will broken regex
+        match self.setting.prompt.clone() {
+            Some(prompt) => {
+                self.wait_match(timeout, &[prompt], 1)?;
+            }
+            // no idea what prompt looks like, any line ending with a prompt char is likely it.
+            // some consoles never show one, go on once it's not seen in time
+            None => match self.wait_match(timeout.min(GUESS_PROMPT_WAIT), &[self.prompt()], 1) {
+                Ok(_) => {}
+                Err(ConsoleError::Timeout) => debug!(msg = "no prompt seen, exec anyway"),
+                Err(e) => return Err(e),
+            },
+        }
 
         // prepare
         let nanoid = nanoid::nanoid!(6);
@@ -119,8 +254,9 @@ where
         } else {
             // cmd; echo -$?$nanoid\r
             let cmd = format!("{cmd}; echo {}$?{nanoid}{}", res_flag_sep, enter_input);
-            // cmd; echo -$?$nanoid\rresult-0$nanoid\n
-            let match_left = format!("{nanoid}{}{}", &self.setting.linebreak, enter_input);
+            // tty echoes enter as a linebreak, like any output
+            // cmd; echo -$?$nanoid\nresult-0$nanoid\n
+            let match_left = format!("{nanoid}{}", &self.setting.linebreak);
             (cmd, match_left)
         };
 
@@ -128,10 +264,12 @@ where
         let match_right = &format!("{nanoid}{}", &self.setting.linebreak);
 
         // run command
-        self.write_string(&cmd, timeout)?;
+        self.write_string(&format!("{cmd}{heredoc}"), timeout)?;
+
+        // length of output already passed to on_output
+        let mut emitted = 0;
 
         // wait output
-        let deadline = Instant::now() + timeout;
         self.comsume_buffer_and_map(deadline - Instant::now(), |buffer, new| {
             // find target pattern from buffer
             let buffer_str = Tm::parse_and_strip(buffer);
@@ -159,6 +297,9 @@ where
                             res = res
                         );
                         if let Ok(flag) = flag.parse::<i32>() {
+                            if let Some(rest) = res.get(emitted..).filter(|s| !s.is_empty()) {
+                                on_output(rest);
+                            }
                             return ConsumeAction::BreakValue((flag, res.to_string()));
                         }
                     } else {
@@ -170,6 +311,10 @@ where
                     ConsumeAction::BreakValue((1, v))
                 }
                 None => {
+                    if let Some(lines) = new_lines(&buffer_str, &match_left, emitted) {
+                        emitted += lines.len();
+                        on_output(lines);
+                    }
                     debug!(msg = "consume buffer continue");
                     ConsumeAction::Continue
                 }
@@ -180,11 +325,13 @@ where
     fn comsume_buffer_and_map<T>(
         &self,
         timeout: Duration,
-        f: impl Fn(&[u8], &[u8]) -> ConsumeAction<T>,
+        mut f: impl FnMut(&[u8], &[u8]) -> ConsumeAction<T>,
     ) -> Result<T> {
         let deadline = Instant::now() + timeout;
 
         let mut buffer_len = 0;
+        // what we wait for may be in buffer already, like a prompt
+        let mut first = true;
         loop {
             if self.try_handle_stop_signal() {
                 return Err(ConsoleError::Cancel);
@@ -196,7 +343,9 @@ where
                 break;
             }
 
-            thread::sleep(Duration::from_millis(1000));
+            if !first {
+                thread::sleep(Duration::from_millis(1000));
+            }
 
             // read buffer
             let res = self
@@ -204,9 +353,10 @@ where
                 .send_timeout(Req::Read, Duration::from_millis(1000));
             match res {
                 Ok(Res::Value(ref recv)) => {
-                    if recv.is_empty() {
+                    if recv.is_empty() && !first {
                         continue;
                     }
+                    first = false;
 
                     let mut state = self.state.lock();
                     // save to history
@@ -257,17 +407,68 @@ where
     }
 }
 
-fn count_substring(s: &str, substring: &str, n: usize) -> bool {
-    let mut count = 0;
-    let mut start = 0;
+// complete lines of cmd output after left marker, skip first emitted bytes
+fn new_lines<'a>(s: &'a str, left: &str, emitted: usize) -> Option<&'a str> {
+    let (_, output) = s.split_once(left)?;
+    let end = output.rfind('\n')? + 1;
+    output.get(emitted..end).filter(|s| !s.is_empty())
+}
 
-    while let Some(pos) = s[start..].find(substring) {
-        count += 1;
-        if count == n {
-            return true;
-        }
-        start += pos + substring.len();
+fn find_nth_match(s: &str, patterns: &[Regex], n: usize) -> Option<WaitMatch> {
+    patterns
+        .iter()
+        .enumerate()
+        .filter_map(|(index, re)| {
+            let caps = re.captures_iter(s).nth(n.max(1) - 1)?;
+            let m = caps.get(0)?;
+            Some((m.end(), index, caps))
+        })
+        .min_by_key(|(end, index, _)| (*end, *index))
+        .map(|(_, index, caps)| WaitMatch {
+            index,
+            text: caps[0].to_string(),
+            groups: caps
+                .iter()
+                .skip(1)
+                .map(|g| g.map(|g| g.as_str().to_string()))
+                .collect(),
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_nth_match, new_lines};
+    use regex::Regex;
+
+    #[test]
+    fn test_new_lines() {
+        let left = "abc\n";
+        assert_eq!(new_lines("echo abc; ls", left, 0), None);
+        assert_eq!(new_lines("abc\nfoo", left, 0), None);
+        let s = "abc\nfoo\nbar\nba";
+        assert_eq!(new_lines(s, left, 0), Some("foo\nbar\n"));
+        assert_eq!(new_lines(s, left, 4), Some("bar\n"));
+        assert_eq!(new_lines(s, left, 8), None);
     }
 
-    false
+    #[test]
+    fn test_find_nth_match() {
+        let s = "login: \nPassword: \nlogin: root\nversion 6.1.0";
+        let login = Regex::new(&regex::escape("login:")).unwrap();
+        let version = Regex::new(r"version (\d+)\.(\d+)(-rc)?").unwrap();
+
+        let m = find_nth_match(s, &[version.clone(), login.clone()], 1).unwrap();
+        assert_eq!(m.index, 1);
+
+        let m = find_nth_match(s, std::slice::from_ref(&login), 2).unwrap();
+        assert_eq!(m.text, "login:");
+        assert!(find_nth_match(s, &[login], 3).is_none());
+
+        let m = find_nth_match(s, &[version], 1).unwrap();
+        assert_eq!(m.text, "version 6.1");
+        assert_eq!(
+            m.groups,
+            vec![Some("6".to_string()), Some("1".to_string()), None]
+        );
+    }
 }
@@ -0,0 +1,30 @@
+use std::fs;
+
+// single quoted for sh, quotes inside are closed, escaped and reopened
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+// permission bits to keep on remote copy
+#[cfg(unix)]
+pub fn local_mode(meta: &fs::Metadata) -> Option<i32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some((meta.permissions().mode() & 0o777) as i32)
+}
+
+// no unix permission to keep
+#[cfg(not(unix))]
+pub fn local_mode(_: &fs::Metadata) -> Option<i32> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("echo hi"), "'echo hi'");
+        assert_eq!(shell_quote("echo 'a b'"), r"'echo '\''a b'\'''");
+    }
+}
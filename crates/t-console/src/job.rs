@@ -0,0 +1,379 @@
+use crate::{base::util::shell_quote, ConsoleError, Result};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{self, Read},
+    sync::{Arc, Weak},
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+
+// LIBSSH2_ERROR_EAGAIN, returned by every call on a non-blocking session
+const EAGAIN: i32 = -37;
+
+// how long a single libssh2 call may keep retrying
+const SSH_CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+// how long a job has to exit after SIGTERM before SIGKILL
+const KILL_TIMEOUT: Duration = Duration::from_secs(5);
+
+// how often output of running jobs is read in background
+const DRAIN_INTERVAL: Duration = Duration::from_millis(100);
+
+pub type JobId = u32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Running,
+    Exited(i32),
+    // signal name without SIG, like TERM
+    Killed(String),
+}
+
+impl JobStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobStatus::Running => "running",
+            JobStatus::Exited(_) => "exited",
+            JobStatus::Killed(_) => "killed",
+        }
+    }
+
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            JobStatus::Exited(code) => Some(*code),
+            _ => None,
+        }
+    }
+
+    pub fn signal(&self) -> Option<&str> {
+        match self {
+            JobStatus::Killed(signal) => Some(signal),
+            _ => None,
+        }
+    }
+}
+
+impl Display for JobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            JobStatus::Running => write!(f, "running"),
+            JobStatus::Exited(code) => write!(f, "exited with {}", code),
+            JobStatus::Killed(signal) => write!(f, "killed by SIG{}", signal),
+        }
+    }
+}
+
+/// everything a job printed so far
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JobOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+struct Job {
+    channel: ssh2::Channel,
+    // pid of remote shell, also the process group of cmd
+    pid: Option<u32>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    status: JobStatus,
+}
+
+impl Job {
+    // read whatever is available without blocking, collect exit status once done
+    fn poll(&mut self) -> Result<()> {
+        if self.status != JobStatus::Running {
+            return Ok(());
+        }
+        // check eof before reading, so nothing is left behind once it's set
+        let eof = self.channel.eof();
+        read_available(&mut self.channel.stream(0), &mut self.stdout)?;
+        read_available(&mut self.channel.stderr(), &mut self.stderr)?;
+        if self.pid.is_none() {
+            self.pid = take_pid(&mut self.stdout);
+        }
+        if !eof {
+            return Ok(());
+        }
+
+        retry(|| self.channel.close())?;
+        retry(|| self.channel.wait_close())?;
+        let signal = self
+            .channel
+            .exit_signal()
+            .map_err(ConsoleError::SSH2)?
+            .exit_signal;
+        self.status = match signal {
+            Some(signal) => JobStatus::Killed(signal),
+            None => JobStatus::Exited(self.channel.exit_status().map_err(ConsoleError::SSH2)?),
+        };
+        info!(msg = "job done", pid = self.pid, status = %self.status);
+        Ok(())
+    }
+}
+
+/// background commands, each one runs in its own channel of a non-blocking session,
+/// so they never hold the session lock used by the shell console
+pub(crate) struct Jobs {
+    inner: Arc<Mutex<JobsInner>>,
+}
+
+impl Jobs {
+    pub fn new(session: ssh2::Session) -> Self {
+        session.set_blocking(false);
+        let inner = Arc::new(Mutex::new(JobsInner {
+            session,
+            jobs: HashMap::new(),
+            next_id: 1,
+        }));
+        Self::drain(Arc::downgrade(&inner));
+        Self { inner }
+    }
+
+    // read output of running jobs even if nobody asks, a job printing more than
+    // the channel window would block otherwise. stops once Jobs is dropped
+    fn drain(inner: Weak<Mutex<JobsInner>>) {
+        thread::spawn(move || {
+            while let Some(inner) = inner.upgrade() {
+                inner.lock().poll_all();
+                drop(inner);
+                sleep(DRAIN_INTERVAL);
+            }
+        });
+    }
+
+    pub fn spawn(&mut self, cmd: &str) -> Result<JobId> {
+        self.inner.lock().spawn_with(cmd, &[], None)
+    }
+
+    /// like spawn, cmd runs in cwd with extra env
+    pub fn spawn_with(
+        &mut self,
+        cmd: &str,
+        env: &[(String, String)],
+        cwd: Option<&str>,
+    ) -> Result<JobId> {
+        self.inner.lock().spawn_with(cmd, env, cwd)
+    }
+
+    pub fn remove(&mut self, id: JobId) {
+        self.inner.lock().jobs.remove(&id);
+    }
+
+    pub fn status(&mut self, id: JobId) -> Result<JobStatus> {
+        self.inner.lock().status(id)
+    }
+
+    pub fn output(&mut self, id: JobId) -> Result<JobOutput> {
+        self.inner.lock().output(id)
+    }
+
+    pub fn wait(&mut self, id: JobId, timeout: Duration) -> Result<JobStatus> {
+        self.inner.lock().wait(id, timeout)
+    }
+
+    /// SIGTERM the job, SIGKILL if it's still running after a while
+    pub fn kill(&mut self, id: JobId) -> Result<JobStatus> {
+        self.inner.lock().kill(id)
+    }
+}
+
+struct JobsInner {
+    session: ssh2::Session,
+    jobs: HashMap<JobId, Job>,
+    next_id: JobId,
+}
+
+impl JobsInner {
+    fn poll_all(&mut self) {
+        for (id, job) in self.jobs.iter_mut() {
+            if let Err(e) = job.poll() {
+                debug!(msg = "poll job failed", id = id, reason = ?e);
+            }
+        }
+    }
+
+    fn spawn_with(
+        &mut self,
+        cmd: &str,
+        env: &[(String, String)],
+        cwd: Option<&str>,
+    ) -> Result<JobId> {
+        let mut channel = retry(|| self.session.channel_session())?;
+        let wrapped = wrap_cmd(cmd, env, cwd);
+        retry(|| channel.exec(&wrapped))?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        info!(msg = "job spawned", id = id, cmd = cmd);
+        self.jobs.insert(
+            id,
+            Job {
+                channel,
+                pid: None,
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+                status: JobStatus::Running,
+            },
+        );
+        Ok(id)
+    }
+
+    fn job(&mut self, id: JobId) -> Result<&mut Job> {
+        let job = self
+            .jobs
+            .get_mut(&id)
+            .ok_or_else(|| ConsoleError::Job(format!("no job {}", id)))?;
+        job.poll()?;
+        Ok(job)
+    }
+
+    fn status(&mut self, id: JobId) -> Result<JobStatus> {
+        Ok(self.job(id)?.status.clone())
+    }
+
+    fn output(&mut self, id: JobId) -> Result<JobOutput> {
+        let job = self.job(id)?;
+        Ok(JobOutput {
+            stdout: String::from_utf8_lossy(&job.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&job.stderr).to_string(),
+        })
+    }
+
+    fn wait(&mut self, id: JobId, timeout: Duration) -> Result<JobStatus> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let status = self.status(id)?;
+            if status != JobStatus::Running {
+                return Ok(status);
+            }
+            if Instant::now() > deadline {
+                return Err(ConsoleError::Timeout);
+            }
+            sleep(Duration::from_millis(100));
+        }
+    }
+
+    fn kill(&mut self, id: JobId) -> Result<JobStatus> {
+        // pid is the first line, it may not be read yet
+        let deadline = Instant::now() + KILL_TIMEOUT;
+        let pid = loop {
+            let job = self.job(id)?;
+            if job.status != JobStatus::Running {
+                return Ok(job.status.clone());
+            }
+            if let Some(pid) = job.pid {
+                break pid;
+            }
+            if Instant::now() > deadline {
+                return Err(ConsoleError::Job(format!("pid of job {} unknown", id)));
+            }
+            sleep(Duration::from_millis(100));
+        };
+
+        for signal in ["TERM", "KILL"] {
+            info!(msg = "kill job", id = id, pid = pid, signal = signal);
+            self.run(&format!("kill -{} -- -{}", signal, pid))?;
+            match self.wait(id, KILL_TIMEOUT) {
+                Err(ConsoleError::Timeout) => warn!(msg = "job still running", id = id),
+                res => return res,
+            }
+        }
+        Err(ConsoleError::Timeout)
+    }
+
+    // run a short cmd and ignore its output
+    fn run(&self, cmd: &str) -> Result<()> {
+        let mut channel = retry(|| self.session.channel_session())?;
+        retry(|| channel.exec(cmd))?;
+        retry(|| channel.send_eof())?;
+        retry(|| channel.wait_close())?;
+        Ok(())
+    }
+}
+
+// session is non-blocking, retry until libssh2 finished the call
+fn retry<T>(mut f: impl FnMut() -> std::result::Result<T, ssh2::Error>) -> Result<T> {
+    let deadline = Instant::now() + SSH_CALL_TIMEOUT;
+    loop {
+        match f() {
+            Ok(v) => return Ok(v),
+            Err(e) if e.code() == ssh2::ErrorCode::Session(EAGAIN) => {}
+            Err(e) => return Err(ConsoleError::SSH2(e)),
+        }
+        if Instant::now() > deadline {
+            return Err(ConsoleError::Timeout);
+        }
+        sleep(Duration::from_millis(10));
+    }
+}
+
+fn read_available(r: &mut impl Read, buf: &mut Vec<u8>) -> Result<()> {
+    let mut chunk = [0; 4096];
+    loop {
+        match r.read(&mut chunk) {
+            Ok(0) => return Ok(()),
+            Ok(n) => buf.extend(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(ConsoleError::IO(e)),
+        }
+    }
+}
+
+// remove the pid line printed by spawn
+fn take_pid(stdout: &mut Vec<u8>) -> Option<u32> {
+    let end = stdout.iter().position(|&b| b == b'\n')?;
+    let pid = String::from_utf8_lossy(&stdout[..end])
+        .trim()
+        .parse()
+        .ok()?;
+    stdout.drain(..=end);
+    Some(pid)
+}
+
+// print pid first, exec keeps it for cmd so kill can find the whole group
+fn wrap_cmd(cmd: &str, env: &[(String, String)], cwd: Option<&str>) -> String {
+    let cd = cwd
+        .map(|d| format!("cd {} && ", shell_quote(d)))
+        .unwrap_or_default();
+    let env: String = env
+        .iter()
+        .map(|(k, v)| format!("{} ", shell_quote(&format!("{k}={v}"))))
+        .collect();
+    let env = if env.is_empty() {
+        env
+    } else {
+        format!("env {env}")
+    };
+    format!("echo $$; {cd}exec {env}sh -c {}", shell_quote(cmd))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_take_pid() {
+        let mut stdout = b"12".to_vec();
+        assert_eq!(take_pid(&mut stdout), None);
+        stdout.extend(b"34\nstress: info\n");
+        assert_eq!(take_pid(&mut stdout), Some(1234));
+        assert_eq!(stdout, b"stress: info\n");
+    }
+
+    #[test]
+    fn test_wrap_cmd() {
+        assert_eq!(wrap_cmd("ls", &[], None), "echo $$; exec sh -c 'ls'");
+        assert_eq!(
+            wrap_cmd(
+                "make",
+                &[("CC".to_string(), "gcc -O2".to_string())],
+                Some("/tmp/a b")
+            ),
+            "echo $$; cd '/tmp/a b' && exec env 'CC=gcc -O2' sh -c 'make'"
+        );
+    }
+}
@@ -1,13 +1,25 @@
 mod base;
+mod job;
+mod power;
+mod qemu;
 mod serial;
 mod ssh;
+mod telnet;
 mod term;
+mod transfer;
 mod vnc;
 
 use std::fmt::Display;
 
+pub use base::evloop::{ConnEvent, ConnHealth, ConnState};
+pub use base::prompt::Shell;
+pub use base::tty::WaitMatch;
+pub use job::{JobId, JobOutput, JobStatus};
+pub use power::{PowerControl, ShellPower};
+pub use qemu::Qemu;
 pub use serial::Serial;
-pub use ssh::SSH;
+pub use ssh::{ExecResult, SSH};
+pub use telnet::Telnet;
 pub use term::*;
 pub use vnc::{key, Rect, VNCError, VNCEventReq, VNCEventRes, PNG, VNC};
 
@@ -24,6 +36,11 @@ pub enum ConsoleError {
     IO(std::io::Error),
     Serial(serialport::Error),
     SSH2(ssh2::Error),
+    QMP(String),
+    Power(String),
+    Config(String),
+    Job(String),
+    Transfer(String),
 }
 
 impl Display for ConsoleError {
@@ -36,6 +53,11 @@ impl Display for ConsoleError {
             ConsoleError::IO(e) => write!(f, "io error, {}", e),
             ConsoleError::SSH2(e) => write!(f, "ssh error, {}", e),
             ConsoleError::Serial(e) => write!(f, "serial error, {}", e),
+            ConsoleError::QMP(s) => write!(f, "qmp error, {}", s),
+            ConsoleError::Power(s) => write!(f, "power control error, {}", s),
+            ConsoleError::Config(s) => write!(f, "invalid config, {}", s),
+            ConsoleError::Job(s) => write!(f, "job error, {}", s),
+            ConsoleError::Transfer(s) => write!(f, "file transfer error, {}", s),
         }
     }
 }
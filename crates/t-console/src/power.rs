@@ -0,0 +1,120 @@
+use crate::{ConsoleError, Qemu, Result};
+use std::{thread::sleep, time::Duration};
+use t_config::ConsolePower;
+use tracing::info;
+
+/// switch the machine under test on and off
+pub trait PowerControl: Send + Sync {
+    fn power_on(&self) -> Result<()>;
+
+    fn power_off(&self) -> Result<()>;
+
+    fn reset(&self) -> Result<()> {
+        self.cycle(Duration::ZERO)
+    }
+
+    /// off, wait for delay, then on again
+    fn cycle(&self, delay: Duration) -> Result<()> {
+        self.power_off()?;
+        sleep(delay);
+        self.power_on()
+    }
+}
+
+/// run user provided commands, like relay or usb hub control tools
+pub struct ShellPower {
+    on: Option<String>,
+    off: Option<String>,
+    reset: Option<String>,
+}
+
+impl ShellPower {
+    pub fn new(c: ConsolePower) -> Self {
+        Self {
+            on: c.on,
+            off: c.off,
+            reset: c.reset,
+        }
+    }
+
+    fn run(action: &str, cmd: Option<&String>) -> Result<()> {
+        let Some(cmd) = cmd else {
+            return Err(ConsoleError::Power(format!("no {} command", action)));
+        };
+        info!(msg = "power control", action = action, cmd = cmd);
+        t_util::execute_shell(cmd).map_err(|e| ConsoleError::Power(format!("{}: {}", action, e)))
+    }
+}
+
+impl PowerControl for ShellPower {
+    fn power_on(&self) -> Result<()> {
+        Self::run("on", self.on.as_ref())
+    }
+
+    fn power_off(&self) -> Result<()> {
+        Self::run("off", self.off.as_ref())
+    }
+
+    fn reset(&self) -> Result<()> {
+        match self.reset.as_ref() {
+            Some(cmd) => Self::run("reset", Some(cmd)),
+            None => self.cycle(Duration::ZERO),
+        }
+    }
+}
+
+impl PowerControl for Qemu {
+    fn power_on(&self) -> Result<()> {
+        if self.is_running() {
+            return Ok(());
+        }
+        self.start()
+    }
+
+    fn power_off(&self) -> Result<()> {
+        self.stop();
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<()> {
+        if !self.is_running() {
+            return self.start();
+        }
+        self.system_reset()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PowerControl, ShellPower};
+    use std::{fs, time::Duration};
+    use t_config::ConsolePower;
+
+    #[test]
+    fn test_shell_power() {
+        let state = std::env::temp_dir().join("autotest-power-state");
+        let state = state.display();
+        let p = ShellPower::new(ConsolePower {
+            on: Some(format!("printf on >> {state}")),
+            off: Some(format!("printf off >> {state}")),
+            reset: None,
+            cycle_delay: None,
+            boot_pattern: None,
+        });
+        let _ = fs::remove_file(state.to_string());
+
+        p.reset().unwrap();
+        p.cycle(Duration::from_millis(10)).unwrap();
+        assert_eq!(fs::read_to_string(state.to_string()).unwrap(), "offonoffon");
+
+        let p = ShellPower::new(ConsolePower {
+            on: Some("exit 1".to_string()),
+            off: None,
+            reset: None,
+            cycle_delay: None,
+            boot_pattern: None,
+        });
+        assert!(p.power_on().is_err());
+        assert!(p.power_off().is_err());
+    }
+}
@@ -0,0 +1,372 @@
+use crate::ConsoleError;
+use crate::Result;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use t_config::ConsoleQemu;
+use tracing::{debug, info, warn};
+
+/// qemu process started from config, controlled by QMP
+pub struct Qemu {
+    config: ConsoleQemu,
+    child: Mutex<Option<Child>>,
+    qmp: Mutex<Option<QmpClient<Box<dyn Stream>>>>,
+}
+
+trait Stream: Read + Write + Send {}
+impl<T: Read + Write + Send> Stream for T {}
+
+impl Qemu {
+    pub fn spawn(c: ConsoleQemu) -> Result<Self> {
+        let res = Self {
+            config: c,
+            child: Mutex::new(None),
+            qmp: Mutex::new(None),
+        };
+        res.start()?;
+        Ok(res)
+    }
+
+    /// start qemu again with the same config, used after stop
+    pub fn start(&self) -> Result<()> {
+        let c = &self.config;
+        let args = build_args(c);
+        let bin = c.bin.clone().unwrap_or(format!(
+            "qemu-system-{}",
+            c.arch.as_deref().unwrap_or("riscv64")
+        ));
+        info!(msg = "start qemu", bin = bin, args = ?args);
+
+        // remove sockets left by last run, qemu refuses to bind them
+        for sock in [c.qmp_sock.as_ref(), c.serial_sock.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            let _ = std::fs::remove_file(sock);
+        }
+
+        let (stdout, stderr) = match c.log_file.as_ref() {
+            Some(f) => {
+                let f = File::create(f).map_err(ConsoleError::IO)?;
+                (
+                    Stdio::from(f.try_clone().map_err(ConsoleError::IO)?),
+                    Stdio::from(f),
+                )
+            }
+            None => (Stdio::null(), Stdio::null()),
+        };
+        let child = Command::new(&bin)
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()
+            .map_err(ConsoleError::IO)?;
+        *self.child.lock() = Some(child);
+
+        // consoles connect right after this, wait until qemu is listening
+        let deadline = Instant::now() + Duration::from_secs(10);
+        for sock in [c.qmp_sock.as_ref(), c.serial_sock.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            while !sock.exists() {
+                if !self.is_running() {
+                    return Err(ConsoleError::NoConnection(
+                        "qemu exited unexpectedly, see qemu.log".to_string(),
+                    ));
+                }
+                if Instant::now() > deadline {
+                    self.kill();
+                    return Err(ConsoleError::Timeout);
+                }
+                sleep(Duration::from_millis(50));
+            }
+        }
+        if let Some(sock) = c.qmp_sock.as_ref() {
+            match connect_qmp(sock) {
+                Ok(qmp) => *self.qmp.lock() = Some(qmp),
+                Err(e) => {
+                    self.kill();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_running(&self) -> bool {
+        match self.child.lock().as_mut() {
+            Some(child) => matches!(child.try_wait(), Ok(None)),
+            None => false,
+        }
+    }
+
+    pub fn qmp(&self, execute: &str, arguments: Option<Value>) -> Result<Value> {
+        match self.qmp.lock().as_mut() {
+            Some(qmp) => qmp.execute(execute, arguments),
+            None => Err(ConsoleError::QMP("qmp not connected".to_string())),
+        }
+    }
+
+    // savevm and loadvm only exist as monitor commands
+    fn hmp(&self, command_line: String) -> Result<()> {
+        let res = self.qmp(
+            "human-monitor-command",
+            Some(json!({ "command-line": command_line })),
+        )?;
+        // hmp reports errors as output text
+        match res.as_str() {
+            Some(out) if out.contains("Error") => Err(ConsoleError::QMP(out.trim().to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn system_reset(&self) -> Result<()> {
+        self.qmp("system_reset", None).map(|_| ())
+    }
+
+    pub fn savevm(&self, tag: &str) -> Result<()> {
+        self.hmp(format!("savevm {}", tag))
+    }
+
+    pub fn loadvm(&self, tag: &str) -> Result<()> {
+        self.hmp(format!("loadvm {}", tag))
+    }
+
+    pub fn quit(&self) -> Result<()> {
+        self.qmp("quit", None).map(|_| ())
+    }
+
+    /// quit gracefully, kill if qemu doesn't exit in time
+    pub fn stop(&self) {
+        if self.is_running() {
+            if let Err(e) = self.quit() {
+                warn!(msg = "qemu quit failed", reason = ?e);
+            }
+            let deadline = Instant::now() + Duration::from_secs(3);
+            while self.is_running() && Instant::now() < deadline {
+                sleep(Duration::from_millis(50));
+            }
+        }
+        self.kill();
+        info!(msg = "qemu stopped");
+    }
+
+    fn kill(&self) {
+        *self.qmp.lock() = None;
+        let Some(mut child) = self.child.lock().take() else {
+            return;
+        };
+        if let Ok(None) = child.try_wait() {
+            if let Err(e) = child.kill() {
+                warn!(msg = "kill qemu failed", reason = ?e);
+            }
+        }
+        let _ = child.wait();
+    }
+}
+
+fn build_args(c: &ConsoleQemu) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+    let mut push = |k: &str, v: String| {
+        args.push(k.to_string());
+        args.push(v);
+    };
+    if let Some(machine) = c.machine.as_ref() {
+        push("-machine", machine.clone());
+    }
+    if let Some(cpu) = c.cpu.as_ref() {
+        push("-cpu", cpu.clone());
+    }
+    if let Some(memory) = c.memory.as_ref() {
+        push("-m", memory.clone());
+    }
+    if let Some(smp) = c.smp {
+        push("-smp", smp.to_string());
+    }
+    for disk in c.disks.iter() {
+        push("-drive", format!("file={},if=virtio", disk));
+    }
+    push("-display", "none".to_string());
+    match c.serial_sock.as_ref() {
+        Some(sock) => push("-serial", format!("unix:{},server,nowait", sock.display())),
+        None => push("-serial", "none".to_string()),
+    }
+    if let Some(sock) = c.qmp_sock.as_ref() {
+        push("-qmp", format!("unix:{},server,nowait", sock.display()));
+    }
+    if let Some(display) = c.vnc_display {
+        push("-vnc", format!(":{}", display));
+    }
+    if let Some(port) = c.ssh_forward {
+        push("-nic", format!("user,hostfwd=tcp:127.0.0.1:{}-:22", port));
+    }
+    if c.snapshot.unwrap_or(false) {
+        args.push("-snapshot".to_string());
+    }
+    args.extend(c.extra_args.iter().cloned());
+    args
+}
+
+#[cfg(unix)]
+fn connect_qmp(sock: &Path) -> Result<QmpClient<Box<dyn Stream>>> {
+    let stream = std::os::unix::net::UnixStream::connect(sock).map_err(ConsoleError::IO)?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(30)))
+        .map_err(ConsoleError::IO)?;
+    QmpClient::handshake(Box::new(stream) as Box<dyn Stream>)
+}
+
+#[cfg(not(unix))]
+fn connect_qmp(_sock: &Path) -> Result<QmpClient<Box<dyn Stream>>> {
+    Err(ConsoleError::QMP(
+        "qmp over unix socket is not supported on this platform".to_string(),
+    ))
+}
+
+/// json lines protocol, see qemu docs/interop/qmp-spec
+struct QmpClient<S: Read + Write> {
+    reader: BufReader<S>,
+}
+
+impl<S: Read + Write> QmpClient<S> {
+    fn handshake(stream: S) -> Result<Self> {
+        let mut res = Self {
+            reader: BufReader::new(stream),
+        };
+        let greeting = res.read_msg()?;
+        if greeting.get("QMP").is_none() {
+            return Err(ConsoleError::QMP(format!(
+                "unexpected greeting: {}",
+                greeting
+            )));
+        }
+        res.execute("qmp_capabilities", None)?;
+        Ok(res)
+    }
+
+    fn read_msg(&mut self) -> Result<Value> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Err(ConsoleError::IO(io::ErrorKind::UnexpectedEof.into())),
+            Ok(_) => serde_json::from_str(&line).map_err(|e| ConsoleError::QMP(e.to_string())),
+            Err(e) => Err(ConsoleError::IO(e)),
+        }
+    }
+
+    fn execute(&mut self, execute: &str, arguments: Option<Value>) -> Result<Value> {
+        let mut cmd = json!({ "execute": execute });
+        if let Some(arguments) = arguments {
+            cmd["arguments"] = arguments;
+        }
+        debug!(msg = "qmp execute", cmd = %cmd);
+        let stream = self.reader.get_mut();
+        writeln!(stream, "{}", cmd).map_err(ConsoleError::IO)?;
+        stream.flush().map_err(ConsoleError::IO)?;
+
+        loop {
+            let mut msg = self.read_msg()?;
+            if let Some(res) = msg.get_mut("return") {
+                return Ok(res.take());
+            }
+            if let Some(e) = msg.get("error") {
+                return Err(ConsoleError::QMP(
+                    e.get("desc")
+                        .and_then(|d| d.as_str())
+                        .unwrap_or("unknown error")
+                        .to_string(),
+                ));
+            }
+            // async events like RESET and STOP
+            debug!(msg = "qmp event", event = %msg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    // replays server output, ignores what client writes
+    struct Replay {
+        output: Cursor<Vec<u8>>,
+        input: Vec<u8>,
+    }
+
+    impl Read for Replay {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.output.read(buf)
+        }
+    }
+
+    impl Write for Replay {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.input.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_qmp_client() {
+        let output = [
+            r#"{"QMP": {"version": {}, "capabilities": []}}"#,
+            r#"{"return": {}}"#,
+            r#"{"event": "RESET", "data": {"guest": false}}"#,
+            r#"{"return": {}}"#,
+            r#"{"error": {"class": "GenericError", "desc": "no such snapshot"}}"#,
+        ]
+        .join("\n");
+        let mut qmp = QmpClient::handshake(Replay {
+            output: Cursor::new(output.into_bytes()),
+            input: Vec::new(),
+        })
+        .unwrap();
+
+        assert!(qmp.execute("system_reset", None).is_ok());
+        let e = qmp.execute("loadvm", None).unwrap_err();
+        assert!(matches!(e, ConsoleError::QMP(ref s) if s == "no such snapshot"));
+
+        let input = String::from_utf8(qmp.reader.get_ref().input.clone()).unwrap();
+        assert_eq!(
+            input.lines().collect::<Vec<_>>(),
+            vec![
+                r#"{"execute":"qmp_capabilities"}"#,
+                r#"{"execute":"system_reset"}"#,
+                r#"{"execute":"loadvm"}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_args() {
+        let c: ConsoleQemu = toml::from_str(
+            r#"
+            machine = "virt"
+            memory = "2G"
+            smp = 4
+            disks = ["disk.qcow2"]
+            snapshot = true
+            ssh_forward = 2222
+            vnc_display = 1
+            extra_args = ["-bios", "fw.bin"]
+            "#,
+        )
+        .unwrap();
+        let args = build_args(&c).join(" ");
+        assert_eq!(
+            args,
+            "-machine virt -m 2G -smp 4 -drive file=disk.qcow2,if=virtio -display none \
+             -serial none -vnc :1 -nic user,hostfwd=tcp:127.0.0.1:2222-:22 -snapshot -bios fw.bin"
+        );
+    }
+}
@@ -1,22 +1,33 @@
 use crate::base::evloop::EventLoop;
+use crate::base::file;
+use crate::base::login::auto_login;
+use crate::base::stream::StreamConn;
 use crate::base::tty::Tty;
 use crate::base::tty::TtySetting;
+use crate::base::util::local_mode;
 use crate::term::Term;
 use crate::ConsoleError;
 use crate::Result;
+use std::fs;
 use std::ops::Deref;
 use std::ops::DerefMut;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::sync::mpsc::Receiver;
+use std::time::Duration;
 use t_config::ConsoleSerialType;
-use tracing::{error, info};
+use tracing::{debug, error, info};
 
 pub struct Serial {
     stop_tx: mpsc::Sender<()>,
     inner: Box<dyn SerialClient<crate::VT102> + Send + Sync>,
+    // username and password if auto_login enabled
+    login: Option<(String, Option<String>)>,
 }
 
+// machine may be still booting when serial connected
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(300);
+
 impl Deref for Serial {
     type Target = Tty<crate::VT102>;
 
@@ -38,6 +49,7 @@ impl Serial {
         let setting = TtySetting {
             disable_echo: c.disable_echo.unwrap_or(false),
             linebreak: c.linebreak.clone().unwrap_or("\n".to_string()),
+            prompt: TtySetting::prompt_from_config(c.prompt.as_ref())?,
         };
 
         #[cfg(never)]
@@ -57,6 +69,12 @@ impl Serial {
                 stop_rx,
                 setting,
             )?),
+            Some(ConsoleSerialType::Tcp) => Box::new(TcpClient::connect(
+                &c.serial_file,
+                c.log_file.clone(),
+                stop_rx,
+                setting,
+            )?),
             _ => {
                 let ssh_client = PtyClient::connect(
                     &c.serial_file,
@@ -68,7 +86,64 @@ impl Serial {
                 Box::new(ssh_client)
             }
         };
-        Ok(Self { stop_tx, inner })
+        let login = match (c.auto_login.unwrap_or(false), c.username) {
+            (true, Some(username)) => Some((username, c.password)),
+            (true, None) => {
+                return Err(ConsoleError::NoConnection(
+                    "auto_login enabled, but no username".to_string(),
+                ))
+            }
+            (false, _) => None,
+        };
+        let mut res = Self {
+            stop_tx,
+            inner,
+            login,
+        };
+        // exec injects markers into whatever reads input, make sure it's a shell
+        if res.login.is_some() {
+            if let Err(e) = res.login(LOGIN_TIMEOUT) {
+                res.stop();
+                return Err(e);
+            }
+        }
+        Ok(res)
+    }
+
+    /// log in again, like after reboot. do nothing if auto_login not enabled
+    pub fn login(&mut self, timeout: Duration) -> Result<()> {
+        let Some((username, password)) = self.login.clone() else {
+            return Ok(());
+        };
+        auto_login(
+            self.inner.get_tty_mut(),
+            &username,
+            password.as_deref(),
+            timeout,
+        )
+    }
+
+    /// write local file to remote through shell, mode defaults to mode of local file
+    pub fn upload(
+        &mut self,
+        local: &Path,
+        remote: &str,
+        mode: Option<i32>,
+        timeout: Duration,
+    ) -> Result<()> {
+        let content = fs::read(local).map_err(ConsoleError::IO)?;
+        // no mode to keep leaves it to umask
+        let mode = mode.or_else(|| local_mode(&fs::metadata(local).ok()?));
+        file::upload(self.inner.get_tty_mut(), &content, remote, mode, timeout)
+    }
+
+    /// read remote file through shell, parent of local is created if missing
+    pub fn download(&mut self, remote: &str, local: &Path, timeout: Duration) -> Result<()> {
+        let content = file::download(self.inner.get_tty_mut(), remote, timeout)?;
+        if let Some(parent) = local.parent() {
+            fs::create_dir_all(parent).map_err(ConsoleError::IO)?;
+        }
+        fs::write(local, content).map_err(ConsoleError::IO)
     }
 
     pub fn stop(&self) {
@@ -107,6 +182,16 @@ impl<T: Term> SerialClient<T> for SockClient<T> {
     }
 }
 
+impl<T: Term> SerialClient<T> for TcpClient<T> {
+    fn get_tty(&self) -> &Tty<T> {
+        &self.tty
+    }
+
+    fn get_tty_mut(&mut self) -> &mut Tty<T> {
+        &mut self.tty
+    }
+}
+
 struct PtyClient<T: Term> {
     pub tty: Tty<T>,
     pub path: String,
@@ -175,14 +260,15 @@ where
     ) -> Result<Self> {
         let file = file.to_string();
 
+        // qemu closes the socket when it exits, reconnect once it's started again
         let evloop = EventLoop::spawn(
-            move || match std::os::unix::net::UnixStream::connect(std::path::Path::new(&file)) {
+            move || match StreamConn::connect_unix(&file) {
                 Ok(res) => {
                     info!(msg = "serial(unix sock) conn success");
                     Ok(res)
                 }
                 Err(e) => {
-                    error!("serial(unix sock) conn failed: {} {}", e, file);
+                    debug!(msg = "serial(unix sock) conn failed", file = file, reason = ?e);
                     Err(ConsoleError::IO(e))
                 }
             },
@@ -201,17 +287,55 @@ where
     }
 }
 
+struct TcpClient<T: Term> {
+    pub tty: Tty<T>,
+}
+
+impl<T> TcpClient<T>
+where
+    T: Term,
+{
+    pub fn connect(
+        addr: &str,
+        log_file: Option<PathBuf>,
+        stop_rx: Receiver<()>,
+        setting: TtySetting,
+    ) -> Result<Self> {
+        let addr = addr.to_string();
+
+        // also used by evloop to reconnect after guest reboot, keep it quiet
+        let evloop = EventLoop::spawn(
+            move || match StreamConn::connect_tcp(&addr) {
+                Ok(res) => {
+                    info!(msg = "serial(tcp) conn success", addr = addr);
+                    Ok(res)
+                }
+                Err(e) => {
+                    debug!(msg = "serial(tcp) conn failed", addr = addr, reason = ?e);
+                    Err(ConsoleError::IO(e))
+                }
+            },
+            log_file,
+        );
+
+        Ok(Self {
+            tty: Tty::new(evloop?, stop_rx, setting),
+        })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use t_config::{Config, ConsoleSerial};
 
     use crate::{
-        base::tty::TtySetting,
+        base::{evloop::ConnState, tty::TtySetting},
         term::{Term, VT102},
     };
     use std::{
         env,
-        io::{ErrorKind, Read},
+        io::{BufRead, BufReader, ErrorKind, Read, Write},
+        net::{TcpListener, TcpStream},
         sync::mpsc::channel,
         thread::sleep,
         time::Duration,
@@ -226,7 +350,7 @@ mod test {
             return;
         }
         let c = c.unwrap();
-        let Some(serial) = c.serial else {
+        let Some(serial) = c.serial.get_default().cloned() else {
             return;
         };
 
@@ -271,6 +395,7 @@ mod test {
             TtySetting {
                 disable_echo: serial.disable_echo.unwrap_or(false),
                 linebreak: serial.linebreak.clone().unwrap_or("\n".to_string()),
+                prompt: None,
             },
         )
         .unwrap()
@@ -281,7 +406,7 @@ mod test {
         let Some(c) = get_config_from_file() else {
             return;
         };
-        let Some(c) = c.serial else {
+        let Some(c) = c.serial.get_default().cloned() else {
             return;
         };
         let mut serial = get_client(&c);
@@ -301,4 +426,241 @@ mod test {
             }
         })
     }
+
+    // serial console over tcp, every connection is passed to handler
+    fn tcp_serial(handler: impl Fn(TcpStream) + Send + 'static) -> super::Serial {
+        tcp_serial_with(handler, |_| {})
+    }
+
+    fn tcp_serial_with(
+        handler: impl Fn(TcpStream) + Send + 'static,
+        setup: impl FnOnce(&mut ConsoleSerial),
+    ) -> super::Serial {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut c = ConsoleSerial {
+            serial_file: listener.local_addr().unwrap().to_string(),
+            bund_rate: None,
+            r#type: Some(t_config::ConsoleSerialType::Tcp),
+            disable_echo: Some(true),
+            linebreak: None,
+            prompt: None,
+            auto_login: None,
+            username: None,
+            password: None,
+            log_file: None,
+        };
+        setup(&mut c);
+        std::thread::spawn(move || {
+            for s in listener.incoming().flatten() {
+                handler(s);
+            }
+        });
+        super::Serial::new(c).unwrap()
+    }
+
+    // pass each command with its heredoc to a real sh, prompt shows after output,
+    // "reboot" is not run but closes the connection. with echo, input lines are sent back
+    // like a tty does, heredoc lines after "> ", and line breaks become \r\n
+    fn fake_shell(s: TcpStream, prompt: &str, echo: bool) {
+        let mut reader = BufReader::new(s.try_clone().unwrap());
+        let mut s = s;
+        let mut script = String::new();
+        let mut heredoc = false;
+        loop {
+            let mut line = Vec::new();
+            match reader.read_until(b'\r', &mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            let line = String::from_utf8_lossy(&line).trim_end().to_string();
+            script += &format!("{line}\n");
+            if echo {
+                let more = if heredoc { "> " } else { "" };
+                if s.write_all(format!("{more}{line}\r\n").as_bytes()).is_err() {
+                    return;
+                }
+            }
+            heredoc = match heredoc {
+                true => line != "AUTOTEST_EOF",
+                false => line.contains("<<'AUTOTEST_EOF'"),
+            };
+            if heredoc {
+                continue;
+            }
+            let reboot = script.contains("; reboot; ");
+            let out = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(script.replace("; reboot; ", "; true; "))
+                .output()
+                .unwrap();
+            script.clear();
+            let mut out = String::from_utf8_lossy(&out.stdout).to_string();
+            if echo {
+                out = out.replace('\n', "\r\n");
+            }
+            if s.write_all(out.as_bytes()).is_err() || s.write_all(prompt.as_bytes()).is_err() {
+                return;
+            }
+            if reboot {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn test_tcp_reconnect() {
+        // the second connection comes after "reboot"
+        let mut serial = tcp_serial(|s| fake_shell(s, "~ # ", false));
+
+        let res = serial.exec(Duration::from_secs(5), "echo hello").unwrap();
+        assert_eq!(res, (0, "hello\n".to_string()));
+
+        let res = serial.exec(Duration::from_secs(5), "reboot").unwrap();
+        assert_eq!(res.0, 0);
+
+        // wait evloop notice the closed connection and reconnect
+        sleep(Duration::from_millis(500));
+        let health = serial.health();
+        health.wait_connected(Duration::from_secs(5)).unwrap();
+        let states: Vec<_> = health.take_events().into_iter().map(|e| e.state).collect();
+        assert_eq!(
+            states,
+            [
+                ConnState::Lost,
+                ConnState::Reconnecting,
+                ConnState::Connected
+            ]
+        );
+        assert_eq!(health.connects(), 2);
+        let res = serial.exec(Duration::from_secs(5), "echo back").unwrap();
+        assert_eq!(res, (0, "back\n".to_string()));
+        serial.stop();
+    }
+
+    #[test]
+    fn test_upload_download() {
+        // echoed heredoc lines show up between the markers of exec
+        for echo in [false, true] {
+            let mut serial = tcp_serial_with(
+                move |s| fake_shell(s, "~ # ", echo),
+                |c| {
+                    c.disable_echo = Some(!echo);
+                    c.linebreak = echo.then(|| "\r\n".to_string());
+                },
+            );
+
+            let dir = env::temp_dir().join(nanoid::nanoid!(6));
+            std::fs::create_dir_all(&dir).unwrap();
+            // more than one chunk each way
+            let content: Vec<u8> = (0..=255).cycle().take(20000).collect();
+            std::fs::write(dir.join("a"), &content).unwrap();
+
+            let remote = dir.join("remote a").to_string_lossy().to_string();
+            serial
+                .upload(
+                    &dir.join("a"),
+                    &remote,
+                    Some(0o700),
+                    Duration::from_secs(60),
+                )
+                .unwrap();
+            assert_eq!(std::fs::read(&remote).unwrap(), content);
+
+            serial
+                .download(&remote, &dir.join("b/c"), Duration::from_secs(30))
+                .unwrap();
+            assert_eq!(std::fs::read(dir.join("b/c")).unwrap(), content);
+
+            assert!(serial
+                .download("/no/such/file", &dir.join("d"), Duration::from_secs(10))
+                .is_err());
+            serial.stop();
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_exec_stream() {
+        let mut serial = tcp_serial(|mut s| {
+            let mut line = Vec::new();
+            BufReader::new(s.try_clone().unwrap())
+                .read_until(b'\r', &mut line)
+                .unwrap();
+            let line = String::from_utf8_lossy(&line).to_string();
+            let id = line.split("; ").next().unwrap().trim_start_matches("echo ");
+            // a slow build, first line shows long before the result
+            s.write_all(format!("{id}\nstep 1\nstep").as_bytes())
+                .unwrap();
+            sleep(Duration::from_millis(2500));
+            s.write_all(format!(" 2\ndone-0{id}\n").as_bytes()).unwrap();
+        });
+
+        let start = std::time::Instant::now();
+        let mut chunks = Vec::new();
+        let res = serial
+            .exec_stream(Duration::from_secs(10), "make", |s| {
+                chunks.push((s.to_string(), start.elapsed()))
+            })
+            .unwrap();
+        assert_eq!(res, (0, "step 1\nstep 2\ndone".to_string()));
+        let (text, at): (Vec<_>, Vec<_>) = chunks.into_iter().unzip();
+        assert_eq!(text, vec!["step 1\n", "step 2\ndone"]);
+        assert!(at[0] < Duration::from_millis(2500));
+        serial.stop();
+    }
+
+    // getty that rejects the first password, then hands over to fake_shell
+    fn fake_getty(mut s: TcpStream) {
+        let mut reader = BufReader::new(s.try_clone().unwrap());
+        let mut read_line = || {
+            let mut line = Vec::new();
+            reader.read_until(b'\r', &mut line).ok()?;
+            Some(String::from_utf8_lossy(&line).trim().to_string())
+        };
+        let mut attempt = 0;
+        // console wakes up with an empty line
+        read_line();
+        loop {
+            s.write_all(b"\nUbuntu 22.04 ttyS0\n\nubuntu login: ")
+                .unwrap();
+            let Some(username) = read_line() else { return };
+            if username.is_empty() {
+                continue;
+            }
+            s.write_all(b"Password: ").unwrap();
+            let Some(password) = read_line() else { return };
+            attempt += 1;
+            if username == "root" && password == "pass" && attempt > 1 {
+                break;
+            }
+            s.write_all(b"\nLogin incorrect\n").unwrap();
+            // the next prompt is only shown for the linebreak from client
+            read_line();
+        }
+        s.write_all(b"Last login: Mon Oct 1 on ttyS0\nroot@ubuntu:~# ")
+            .unwrap();
+        fake_shell(s, "root@ubuntu:~# ", false);
+    }
+
+    #[test]
+    fn test_auto_login() {
+        let mut serial = tcp_serial_with(fake_getty, |c| {
+            c.prompt = Some("bash".to_string());
+            c.auto_login = Some(true);
+            c.username = Some("root".to_string());
+            c.password = Some("pass".to_string());
+        });
+
+        // exec waits for prompt of the last command
+        for s in ["hello", "world"] {
+            let res = serial
+                .exec(Duration::from_secs(5), &format!("echo {s}"))
+                .unwrap();
+            assert_eq!(res, (0, format!("{s}\n")));
+        }
+        serial.wait_prompt(Duration::from_secs(1)).unwrap();
+        let res = serial.exec(Duration::from_secs(5), "echo again").unwrap();
+        assert_eq!(res, (0, "again\n".to_string()));
+        serial.stop();
+    }
 }
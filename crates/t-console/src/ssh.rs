@@ -1,8 +1,12 @@
 use crate::base::evloop::EventLoop;
 use crate::base::tty::Tty;
 use crate::base::tty::TtySetting;
+use crate::job::{JobId, JobOutput, JobStatus, Jobs};
 use crate::term::Term;
+use crate::transfer::Transfer;
 use crate::ConsoleError;
+use parking_lot::Mutex;
+use std::io::{self, Read, Write};
 use std::net::TcpStream;
 use std::net::ToSocketAddrs;
 use std::ops::Deref;
@@ -14,7 +18,7 @@ use std::sync::mpsc::Receiver;
 use std::thread::sleep;
 use std::time::Duration;
 use tracing::error;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 type Result<T> = std::result::Result<T, ConsoleError>;
 
@@ -24,9 +28,26 @@ pub enum SSHAuthAuth<P: AsRef<Path>> {
     Password(String),
 }
 
+/// result of a command run in its own channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecResult {
+    // -1 if killed by signal
+    pub code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
 pub struct SSH {
     stop_tx: mpsc::Sender<()>,
     inner: SSHClient<crate::Xterm>,
+    // used to open more sessions
+    config: t_config::ConsoleSSH,
+    // background jobs, connected on first spawn
+    jobs: Option<Jobs>,
+    // blocking session for file transfer, connected on first use
+    transfer: Option<Transfer>,
+    // health().connects() when jobs and transfer were opened, they are gone after reconnect
+    opened_at: u64,
 }
 
 impl Deref for SSH {
@@ -44,9 +65,8 @@ impl DerefMut for SSH {
 }
 
 impl SSH {
-    pub fn new(c: t_config::ConsoleSSH) -> Result<Self> {
-        info!(msg = "init ssh...");
-        let auth = if let Some(password) = c.password.as_ref() {
+    fn auth(c: &t_config::ConsoleSSH) -> SSHAuthAuth<String> {
+        if let Some(password) = c.password.as_ref() {
             SSHAuthAuth::Password(password.clone())
         } else {
             SSHAuthAuth::PrivateKey(
@@ -59,25 +79,42 @@ impl SSH {
                         .unwrap(),
                 ),
             )
-        };
+        }
+    }
+
+    fn addr(c: &t_config::ConsoleSSH) -> String {
+        format!("{}:{}", c.host, c.port.unwrap_or(22))
+    }
+
+    pub fn new(c: t_config::ConsoleSSH) -> Result<Self> {
+        info!(msg = "init ssh...");
+        let auth = Self::auth(&c);
 
         let (stop_tx, stop_rx) = mpsc::channel();
 
         let setting = TtySetting {
             disable_echo: c.enable_echo.unwrap_or(false),
             linebreak: c.linebreak.clone().unwrap_or("\n".to_string()),
+            prompt: TtySetting::prompt_from_config(c.prompt.as_ref())?,
         };
 
         let inner = SSHClient::connect(
             c.timeout,
-            &auth,
+            auth,
             c.username.clone(),
-            format!("{}:{}", c.host, c.port.unwrap_or(22)),
+            Self::addr(&c),
             c.log_file.clone(),
             stop_rx,
             setting,
         )?;
-        Ok(Self { stop_tx, inner })
+        Ok(Self {
+            stop_tx,
+            inner,
+            config: c,
+            jobs: None,
+            transfer: None,
+            opened_at: 1,
+        })
     }
 
     pub fn stop(&self) {
@@ -92,38 +129,139 @@ impl SSH {
         self.inner.pts_file.clone()
     }
 
-    // FIXME: may blocking
+    // sessions of last connection are dead after reconnect, like after reboot
+    fn drop_stale_sessions(&mut self) {
+        let connects = self.health().connects();
+        if connects != self.opened_at {
+            info!(msg = "ssh reconnected, drop old sessions");
+            self.jobs = None;
+            self.transfer = None;
+            self.opened_at = connects;
+        }
+    }
+
+    fn jobs(&mut self) -> Result<&mut Jobs> {
+        self.drop_stale_sessions();
+        if self.jobs.is_none() {
+            self.jobs = Some(Jobs::new(self.new_session()?));
+        }
+        Ok(self.jobs.as_mut().expect("jobs connected"))
+    }
+
+    /// run cmd in background, the shell console stays usable
+    pub fn spawn(&mut self, cmd: &str) -> Result<JobId> {
+        self.jobs()?.spawn(cmd)
+    }
+
+    pub fn job_status(&mut self, id: JobId) -> Result<JobStatus> {
+        self.jobs()?.status(id)
+    }
+
+    /// wait until job exited, Timeout if it's still running
+    pub fn job_wait(&mut self, id: JobId, timeout: Duration) -> Result<JobStatus> {
+        self.jobs()?.wait(id, timeout)
+    }
+
+    pub fn job_kill(&mut self, id: JobId) -> Result<JobStatus> {
+        self.jobs()?.kill(id)
+    }
+
+    pub fn job_output(&mut self, id: JobId) -> Result<JobOutput> {
+        self.jobs()?.output(id)
+    }
+
+    /// run command in its own channel, it's killed if still running after timeout
     pub fn exec_seperate(
         &mut self,
         command: &str,
-    ) -> std::result::Result<(i32, String), std::io::Error> {
-        use std::io::Read;
-        let mut exec_ch = self.inner.session.channel_session().unwrap();
+        env: &[(String, String)],
+        cwd: Option<&str>,
+        timeout: Duration,
+    ) -> Result<ExecResult> {
+        let jobs = self.jobs()?;
+        let id = jobs.spawn_with(command, env, cwd)?;
+        let status = jobs.wait(id, timeout);
+        if let Err(ConsoleError::Timeout) = status {
+            if let Err(e) = jobs.kill(id) {
+                warn!(msg = "kill timeout command failed", reason = %e);
+            }
+        }
+        let output = jobs.output(id);
+        jobs.remove(id);
+
+        let (status, output) = (status?, output?);
+        Ok(ExecResult {
+            code: status.code().unwrap_or(-1),
+            stdout: output.stdout,
+            stderr: output.stderr,
+        })
+    }
 
-        exec_ch.exec(command)?;
-        let mut buffer = String::new();
-        exec_ch.read_to_string(&mut buffer)?;
+    fn new_session(&self) -> Result<ssh2::Session> {
+        let c = &self.config;
+        open_session(c.timeout, &Self::auth(c), c.username.clone(), Self::addr(c))
+    }
 
-        exec_ch.exec("echo $?\n")?;
-        let mut code = String::new();
-        exec_ch.read_to_string(&mut code)?;
+    fn transfer(&mut self) -> Result<&Transfer> {
+        self.drop_stale_sessions();
+        if self.transfer.is_none() {
+            self.transfer = Some(Transfer::new(self.new_session()?));
+        }
+        Ok(self.transfer.as_ref().expect("transfer connected"))
+    }
 
-        Ok((code.parse::<i32>().unwrap(), buffer))
+    /// copy local file or directory to remote, mode defaults to mode of local file
+    pub fn upload(&mut self, local: &Path, remote: &str, mode: Option<i32>) -> Result<()> {
+        self.transfer()?.upload(local, remote, mode)
     }
 
-    pub fn upload_file(&mut self, remote_path: impl AsRef<Path>) {
-        let p: &Path = remote_path.as_ref();
-        assert!(p.exists());
-        let stat = std::fs::metadata(p).unwrap();
-        self.inner
-            .session
-            .scp_send(p, 644, stat.len(), None)
-            .unwrap();
+    /// copy remote file or directory to local
+    pub fn download(&mut self, remote: &str, local: &Path) -> Result<()> {
+        self.transfer()?.download(remote, local)
+    }
+
+    pub fn remote_exists(&mut self, remote: &str) -> Result<bool> {
+        self.transfer()?.exists(remote)
+    }
+}
+
+fn open_session<P: AsRef<Path>, A: ToSocketAddrs>(
+    timeout: Option<Duration>,
+    auth: &SSHAuthAuth<P>,
+    user: impl Into<String>,
+    addrs: A,
+) -> Result<ssh2::Session> {
+    // host may be down while rebooting, don't wait for os connect timeout
+    let connect_timeout = timeout.unwrap_or(Duration::from_secs(5));
+    let tcp = addrs
+        .to_socket_addrs()
+        .map_err(ConsoleError::IO)?
+        .map(|addr| TcpStream::connect_timeout(&addr, connect_timeout))
+        .find_map(|res| res.ok())
+        .ok_or_else(|| ConsoleError::NoConnection("ssh host unreachable".to_string()))?;
+    let mut sess = ssh2::Session::new().map_err(ConsoleError::SSH2)?;
+    sess.set_tcp_stream(tcp);
+    sess.handshake().map_err(ConsoleError::SSH2)?;
+
+    // never disconnect auto
+    sess.set_timeout(timeout.map(|x| x.as_millis() as u32).unwrap_or(5000));
+
+    match auth {
+        SSHAuthAuth::PrivateKey(private_key) => {
+            sess.userauth_pubkey_file(&user.into(), None, private_key.as_ref(), None)
+                .map_err(ConsoleError::SSH2)?;
+        }
+        SSHAuthAuth::Password(password) => {
+            sess.userauth_password(&user.into(), password.as_str())
+                .map_err(ConsoleError::SSH2)?;
+        }
     }
+    assert!(sess.authenticated());
+    debug!(msg = "ssh auth success");
+    Ok(sess)
 }
 
 struct SSHClient<T: Term> {
-    session: ssh2::Session,
     pub pts: Tty<T>,
     pts_file: String,
 }
@@ -132,50 +270,30 @@ impl<Tm> SSHClient<Tm>
 where
     Tm: Term,
 {
-    pub fn connect<P: AsRef<Path>, A: ToSocketAddrs>(
+    pub fn connect(
         timeout: Option<Duration>,
-        auth: &SSHAuthAuth<P>,
-        user: impl Into<String>,
-        addrs: A,
+        auth: SSHAuthAuth<String>,
+        user: String,
+        addr: String,
         log_file: Option<PathBuf>,
         stop_rx: Receiver<()>,
         setting: TtySetting,
     ) -> std::result::Result<Self, ConsoleError> {
-        let tcp = TcpStream::connect(addrs).map_err(ConsoleError::IO)?;
-        let mut sess = ssh2::Session::new().map_err(ConsoleError::SSH2)?;
-        sess.set_tcp_stream(tcp);
-        sess.handshake().map_err(ConsoleError::SSH2)?;
-
-        // never disconnect auto
-        sess.set_timeout(timeout.map(|x| x.as_millis() as u32).unwrap_or(5000));
-
-        match auth {
-            SSHAuthAuth::PrivateKey(private_key) => {
-                sess.userauth_pubkey_file(&user.into(), None, private_key.as_ref(), None)
-                    .map_err(ConsoleError::SSH2)?;
-            }
-            SSHAuthAuth::Password(password) => {
-                sess.userauth_password(&user.into(), password.as_str())
-                    .map_err(ConsoleError::SSH2)?;
-            }
-        }
-        assert!(sess.authenticated());
-        debug!(msg = "ssh auth success");
+        let sess = open_session(timeout, &auth, user.clone(), addr.as_str())?;
 
         sleep(Duration::from_secs(3));
 
+        // first conn uses the session above, later ones log in again after connection lost
+        let first = Mutex::new(Some(sess));
         let res = Self {
-            session: sess.clone(),
             pts: Tty::new(
                 EventLoop::spawn(
                     move || {
-                        // build shell channel
-                        let mut channel = sess.channel_session().map_err(ConsoleError::SSH2)?;
-                        channel
-                            .request_pty("xterm", None, Some((80, 24, 0, 0)))
-                            .map_err(ConsoleError::SSH2)?;
-                        channel.shell().map_err(ConsoleError::SSH2)?;
-                        Ok(channel)
+                        let sess = match first.lock().take() {
+                            Some(sess) => sess,
+                            None => open_session(timeout, &auth, user.clone(), addr.as_str())?,
+                        };
+                        ShellConn::open(&sess)
                     },
                     log_file,
                 )?,
@@ -189,6 +307,48 @@ where
     }
 }
 
+/// shell channel in the shape evloop expects, a closed or broken session
+/// is reported as reset so evloop reconnects
+struct ShellConn(ssh2::Channel);
+
+impl ShellConn {
+    fn open(sess: &ssh2::Session) -> Result<Self> {
+        let mut channel = sess.channel_session().map_err(ConsoleError::SSH2)?;
+        channel
+            .request_pty("xterm", None, Some((80, 24, 0, 0)))
+            .map_err(ConsoleError::SSH2)?;
+        channel.shell().map_err(ConsoleError::SSH2)?;
+        Ok(Self(channel))
+    }
+}
+
+// libssh2 errors other than timeout come as Other, the session is not usable after them
+fn as_reset(e: io::Error) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::Other => io::Error::new(io::ErrorKind::ConnectionReset, e),
+        _ => e,
+    }
+}
+
+impl Read for ShellConn {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            Ok(0) if self.0.eof() => Err(io::ErrorKind::ConnectionReset.into()),
+            res => res.map_err(as_reset),
+        }
+    }
+}
+
+impl Write for ShellConn {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf).map_err(as_reset)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush().map_err(as_reset)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -201,7 +361,7 @@ mod test {
 
     fn get_ssh_client() -> Option<SSH> {
         if let Some(c) = get_config_from_file() {
-            return SSH::new(c.ssh?).ok();
+            return SSH::new(c.ssh.get_default()?.clone()).ok();
         }
         None
     }
@@ -219,9 +379,111 @@ mod test {
         }
         let mut ssh = ssh.unwrap();
         for cmd in cmds {
-            let res = ssh.exec_seperate(cmd.0).unwrap();
-            assert_eq!(res.1, cmd.1);
+            let res = ssh
+                .exec_seperate(cmd.0, &[], None, Duration::from_secs(10))
+                .unwrap();
+            assert_eq!(res.stdout, cmd.1);
         }
+
+        let res = ssh
+            .exec_seperate(
+                r#"pwd; echo "A=$A"; echo err >&2; exit 3"#,
+                &[("A".to_string(), "2".to_string())],
+                Some("/tmp"),
+                Duration::from_secs(10),
+            )
+            .unwrap();
+        assert_eq!(
+            res,
+            ExecResult {
+                code: 3,
+                stdout: "/tmp\nA=2\n".to_string(),
+                stderr: "err\n".to_string(),
+            }
+        );
+
+        assert!(matches!(
+            ssh.exec_seperate("sleep 100", &[], None, Duration::from_secs(1)),
+            Err(ConsoleError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn test_job() {
+        let Some(mut ssh) = get_ssh_client() else {
+            return;
+        };
+        let id = ssh.spawn("echo out; echo err >&2; exit 3").unwrap();
+        assert_eq!(
+            ssh.job_wait(id, Duration::from_secs(10)).unwrap(),
+            JobStatus::Exited(3)
+        );
+        let output = ssh.job_output(id).unwrap();
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+
+        let id = ssh.spawn("sleep 100").unwrap();
+        assert_eq!(ssh.job_status(id).unwrap(), JobStatus::Running);
+        // main console is not blocked by job
+        assert_eq!(
+            ssh.exec(Duration::from_secs(5), "echo hi").unwrap().1,
+            "hi\n"
+        );
+        assert_eq!(
+            ssh.job_kill(id).unwrap(),
+            JobStatus::Killed("TERM".to_string())
+        );
+    }
+
+    #[test]
+    fn test_transfer() {
+        let Some(mut ssh) = get_ssh_client() else {
+            return;
+        };
+        let local = env::temp_dir().join("autotest-transfer");
+        let _ = std::fs::remove_dir_all(&local);
+        std::fs::create_dir_all(local.join("sub")).unwrap();
+        std::fs::write(local.join("sub").join("a.sh"), "echo a").unwrap();
+
+        let remote = "/tmp/autotest-transfer";
+        ssh.exec_seperate(
+            &format!("rm -rf {remote}"),
+            &[],
+            None,
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert!(!ssh.remote_exists(remote).unwrap());
+        ssh.upload(&local, remote, Some(0o755)).unwrap();
+        let res = ssh
+            .exec_seperate(
+                &format!("{remote}/sub/a.sh"),
+                &[],
+                None,
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        assert_eq!(res.stdout, "a\n");
+
+        let back = local.join("back");
+        ssh.download(remote, &back).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(back.join("sub").join("a.sh")).unwrap(),
+            "echo a"
+        );
+
+        // scp fallback copies files only
+        let scp = Transfer::scp_only(ssh.new_session().unwrap());
+        scp.download(&format!("{remote}/sub/a.sh"), &local.join("scp/a.sh"))
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(local.join("scp/a.sh")).unwrap(),
+            "echo a"
+        );
+        assert!(matches!(
+            scp.download(remote, &local.join("scp")),
+            Err(ConsoleError::Transfer(_))
+        ));
     }
 
     #[test]
@@ -237,7 +499,12 @@ mod test {
         let tty = ssh.tty();
 
         thread::spawn(move || {
-            ssh2.exec_seperate(format!(r#"sleep 5 && echo "asdfg" > {}"#, tty).as_str())
+            ssh2.exec_seperate(
+                &format!(r#"sleep 5 && echo "asdfg" > {}"#, tty),
+                &[],
+                None,
+                Duration::from_secs(10),
+            )
         });
 
         ssh.wait_string(Duration::from_secs(1), "asdfg").unwrap();
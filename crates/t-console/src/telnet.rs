@@ -0,0 +1,295 @@
+use crate::base::evloop::EventLoop;
+use crate::base::stream::StreamConn;
+use crate::base::tty::Tty;
+use crate::base::tty::TtySetting;
+use crate::ConsoleError;
+use crate::Result;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::sync::mpsc;
+use tracing::{debug, error, info};
+
+// rfc854 commands
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+// options we agree to
+const OPT_ECHO: u8 = 1;
+const OPT_SGA: u8 = 3;
+
+pub struct Telnet {
+    stop_tx: mpsc::Sender<()>,
+    tty: Tty<crate::VT102>,
+}
+
+impl Deref for Telnet {
+    type Target = Tty<crate::VT102>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tty
+    }
+}
+
+impl DerefMut for Telnet {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.tty
+    }
+}
+
+impl Telnet {
+    pub fn new(c: t_config::ConsoleTelnet) -> Result<Self> {
+        let (stop_tx, stop_rx) = mpsc::channel();
+
+        let setting = TtySetting {
+            disable_echo: c.disable_echo.unwrap_or(false),
+            // telnet NVT sends CRLF
+            linebreak: c.linebreak.clone().unwrap_or("\r\n".to_string()),
+            prompt: TtySetting::prompt_from_config(c.prompt.as_ref())?,
+        };
+
+        let addr = format!("{}:{}", c.host, c.port);
+        let evloop = EventLoop::spawn(
+            move || match TelnetStream::connect(&addr) {
+                Ok(res) => {
+                    info!(msg = "telnet conn success", addr = addr);
+                    Ok(res)
+                }
+                Err(e) => {
+                    error!("telnet conn failed: {} {}", e, addr);
+                    Err(ConsoleError::IO(e))
+                }
+            },
+            c.log_file.clone(),
+        )?;
+
+        Ok(Self {
+            stop_tx,
+            tty: Tty::new(evloop, stop_rx, setting),
+        })
+    }
+
+    pub fn stop(&self) {
+        if self.stop_tx.send(()).is_err() {
+            error!("stop telnet failed, telnet may stopped already");
+            return;
+        }
+
+        self.tty.stop_evloop();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Data,
+    Iac,
+    // got IAC and one of WILL, WONT, DO, DONT
+    Negotiate(u8),
+    Sub,
+    SubIac,
+}
+
+/// strip telnet commands from server output and build replies for option negotiation
+struct Parser {
+    state: State,
+    // options already answered, avoid negotiation loops
+    answered: Vec<(u8, u8)>,
+}
+
+impl Parser {
+    fn new() -> Self {
+        Self {
+            state: State::Data,
+            answered: Vec::new(),
+        }
+    }
+
+    /// returns (data, reply)
+    fn feed(&mut self, input: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut data = Vec::with_capacity(input.len());
+        let mut reply = Vec::new();
+        for &b in input {
+            self.state = match (self.state, b) {
+                (State::Data, IAC) => State::Iac,
+                (State::Data, b) => {
+                    data.push(b);
+                    State::Data
+                }
+                // escaped 0xff
+                (State::Iac, IAC) => {
+                    data.push(IAC);
+                    State::Data
+                }
+                (State::Iac, WILL | WONT | DO | DONT) => State::Negotiate(b),
+                (State::Iac, SB) => State::Sub,
+                // NOP, GA and the like carry no data
+                (State::Iac, _) => State::Data,
+                (State::Negotiate(cmd), opt) => {
+                    if let Some(answer) = Self::answer(cmd, opt) {
+                        if !self.answered.contains(&(cmd, opt)) {
+                            self.answered.push((cmd, opt));
+                            debug!(
+                                msg = "telnet negotiate",
+                                cmd = cmd,
+                                opt = opt,
+                                answer = answer
+                            );
+                            reply.extend([IAC, answer, opt]);
+                        }
+                    }
+                    State::Data
+                }
+                (State::Sub, IAC) => State::SubIac,
+                (State::Sub, _) => State::Sub,
+                (State::SubIac, SE) => State::Data,
+                (State::SubIac, _) => State::Sub,
+            };
+        }
+        (data, reply)
+    }
+
+    // let server echo and suppress go ahead, refuse everything else
+    fn answer(cmd: u8, opt: u8) -> Option<u8> {
+        match (cmd, opt) {
+            (WILL, OPT_ECHO | OPT_SGA) => Some(DO),
+            (WILL, _) => Some(DONT),
+            (DO, OPT_SGA) => Some(WILL),
+            (DO, _) => Some(WONT),
+            // WONT and DONT need no reply if we never enabled the option
+            _ => None,
+        }
+    }
+}
+
+struct TelnetStream {
+    conn: StreamConn<TcpStream>,
+    parser: Parser,
+}
+
+impl TelnetStream {
+    fn connect(addr: &str) -> io::Result<Self> {
+        Ok(Self {
+            conn: StreamConn::connect_tcp(addr)?,
+            parser: Parser::new(),
+        })
+    }
+}
+
+impl Read for TelnetStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.conn.read(buf)?;
+        let (data, reply) = self.parser.feed(&buf[..n]);
+        if !reply.is_empty() {
+            self.conn.write_all(&reply)?;
+        }
+        // data is never longer than input
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+}
+
+impl Write for TelnetStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // 0xff in data must be escaped
+        let mut escaped = Vec::with_capacity(buf.len());
+        for &b in buf {
+            if b == IAC {
+                escaped.push(IAC);
+            }
+            escaped.push(b);
+        }
+        self.conn.write_all(&escaped)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.conn.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{net::TcpListener, thread, time::Duration};
+
+    #[test]
+    fn test_parser() {
+        let mut p = Parser::new();
+        // split in the middle of a command
+        let (data, reply) = p.feed(&[b'a', IAC, WILL]);
+        assert_eq!(data, b"a");
+        assert!(reply.is_empty());
+
+        let (data, reply) = p.feed(&[OPT_ECHO, IAC, DO, 24, IAC, IAC, b'b']);
+        assert_eq!(data, vec![IAC, b'b']);
+        assert_eq!(reply, vec![IAC, DO, OPT_ECHO, IAC, WONT, 24]);
+
+        // subnegotiation is dropped, repeated request is not answered again
+        let (data, reply) = p.feed(&[IAC, SB, 24, 1, IAC, SE, b'c', IAC, WILL, OPT_ECHO]);
+        assert_eq!(data, b"c");
+        assert!(reply.is_empty());
+    }
+
+    #[test]
+    fn test_telnet_exec() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // fake shell: negotiate, then answer every line like a shell with echo off
+        thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            s.write_all(&[IAC, WILL, OPT_ECHO, IAC, WILL, OPT_SGA])
+                .unwrap();
+            let mut buf = [0; 1024];
+            let mut line = Vec::new();
+            loop {
+                let n = match s.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+                let mut bytes = buf[..n].iter();
+                while let Some(&b) = bytes.next() {
+                    // skip negotiation replies
+                    if b == IAC {
+                        bytes.nth(1);
+                        continue;
+                    }
+                    if b != b'\r' {
+                        line.push(b);
+                        continue;
+                    }
+                    let cmd = String::from_utf8_lossy(&line).to_string();
+                    line.clear();
+                    // echo {id}; whoami; echo -$?{id}
+                    let parts: Vec<&str> = cmd.split("; ").collect();
+                    if parts.len() != 3 {
+                        continue;
+                    }
+                    let id = parts[0].trim_start_matches("echo ");
+                    let out = format!("{id}\r\nroot\r\n-0{id}\r\n");
+                    s.write_all(out.as_bytes()).unwrap();
+                }
+            }
+        });
+
+        let mut telnet = Telnet::new(t_config::ConsoleTelnet {
+            host: "127.0.0.1".to_string(),
+            port,
+            disable_echo: Some(true),
+            linebreak: None,
+            prompt: None,
+            log_file: None,
+        })
+        .unwrap();
+        let (code, output) = telnet.exec(Duration::from_secs(5), "whoami").unwrap();
+        assert_eq!(code, 0);
+        assert_eq!(output.trim(), "root");
+        telnet.stop();
+    }
+}
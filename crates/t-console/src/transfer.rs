@@ -0,0 +1,220 @@
+use crate::{
+    base::util::{local_mode, shell_quote},
+    ConsoleError, Result,
+};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+use tracing::{info, warn};
+
+// LIBSSH2_FX_NO_SUCH_FILE
+const FX_NO_SUCH_FILE: i32 = 2;
+
+const DIR_MODE: i32 = 0o755;
+
+/// copy files over sftp, fallback to scp if sftp subsystem is not available
+pub(crate) struct Transfer {
+    session: ssh2::Session,
+    // opened once with the session, None if server has no sftp
+    sftp: Option<ssh2::Sftp>,
+}
+
+impl Transfer {
+    pub fn new(session: ssh2::Session) -> Self {
+        let sftp = session
+            .sftp()
+            .map_err(|e| warn!(msg = "sftp not available, fallback to scp", reason = %e))
+            .ok();
+        Self { session, sftp }
+    }
+
+    #[cfg(test)]
+    pub fn scp_only(session: ssh2::Session) -> Self {
+        Self {
+            session,
+            sftp: None,
+        }
+    }
+
+    /// upload file or directory, mode is only used for files, default to mode of local file
+    pub fn upload(&self, local: &Path, remote: &str, mode: Option<i32>) -> Result<()> {
+        info!(msg = "upload", local = ?local, remote = remote);
+        let meta = fs::metadata(local).map_err(ConsoleError::IO)?;
+        if !meta.is_dir() {
+            let content = fs::read(local).map_err(ConsoleError::IO)?;
+            return self.write_file(
+                remote,
+                &content,
+                mode.or(local_mode(&meta)).unwrap_or(0o644),
+            );
+        }
+
+        self.mkdir(remote)?;
+        for entry in fs::read_dir(local).map_err(ConsoleError::IO)? {
+            let entry = entry.map_err(ConsoleError::IO)?;
+            let name = entry.file_name();
+            self.upload(
+                &entry.path(),
+                &remote_join(remote, &name.to_string_lossy()),
+                mode,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// download file or directory, parent of local is created if missing
+    pub fn download(&self, remote: &str, local: &Path) -> Result<()> {
+        info!(msg = "download", remote = remote, local = ?local);
+        if let Some(parent) = local.parent() {
+            fs::create_dir_all(parent).map_err(ConsoleError::IO)?;
+        }
+        let Some(sftp) = self.sftp.as_ref() else {
+            // scp can't list directories
+            if self.exec_ok(&format!("test -d {}", shell_quote(remote)))? {
+                return Err(ConsoleError::Transfer(format!(
+                    "download directory {} needs sftp, server only has scp",
+                    remote
+                )));
+            }
+            let content = self.scp_read(remote)?;
+            return fs::write(local, content).map_err(ConsoleError::IO);
+        };
+
+        let stat = sftp.stat(Path::new(remote)).map_err(ConsoleError::SSH2)?;
+        if !stat.is_dir() {
+            let mut content = Vec::new();
+            sftp.open(Path::new(remote))
+                .map_err(ConsoleError::SSH2)?
+                .read_to_end(&mut content)
+                .map_err(ConsoleError::IO)?;
+            return fs::write(local, content).map_err(ConsoleError::IO);
+        }
+
+        fs::create_dir_all(local).map_err(ConsoleError::IO)?;
+        for (path, _) in sftp
+            .readdir(Path::new(remote))
+            .map_err(ConsoleError::SSH2)?
+        {
+            let Some(name) = path.file_name() else {
+                continue;
+            };
+            self.download(
+                &remote_join(remote, &name.to_string_lossy()),
+                &local.join(name),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn exists(&self, remote: &str) -> Result<bool> {
+        let Some(sftp) = self.sftp.as_ref() else {
+            return self.exec_ok(&format!("test -e {}", shell_quote(remote)));
+        };
+        match sftp.stat(Path::new(remote)) {
+            Ok(_) => Ok(true),
+            Err(e) if e.code() == ssh2::ErrorCode::SFTP(FX_NO_SUCH_FILE) => Ok(false),
+            Err(e) => Err(ConsoleError::SSH2(e)),
+        }
+    }
+
+    fn write_file(&self, remote: &str, content: &[u8], mode: i32) -> Result<()> {
+        let Some(sftp) = self.sftp.as_ref() else {
+            return self.scp_write(remote, content, mode);
+        };
+        let mut f = sftp
+            .open_mode(
+                Path::new(remote),
+                ssh2::OpenFlags::WRITE | ssh2::OpenFlags::CREATE | ssh2::OpenFlags::TRUNCATE,
+                mode,
+                ssh2::OpenType::File,
+            )
+            .map_err(ConsoleError::SSH2)?;
+        f.write_all(content).map_err(ConsoleError::IO)?;
+        // open_mode only applies to new files
+        sftp.setstat(
+            Path::new(remote),
+            ssh2::FileStat {
+                size: None,
+                uid: None,
+                gid: None,
+                perm: Some(mode as u32),
+                atime: None,
+                mtime: None,
+            },
+        )
+        .map_err(ConsoleError::SSH2)
+    }
+
+    fn mkdir(&self, remote: &str) -> Result<()> {
+        let Some(sftp) = self.sftp.as_ref() else {
+            let cmd = format!("mkdir -p {}", shell_quote(remote));
+            return match self.exec_ok(&cmd)? {
+                true => Ok(()),
+                false => Err(ConsoleError::Transfer(format!(
+                    "create remote dir {} failed",
+                    remote
+                ))),
+            };
+        };
+        match sftp.stat(Path::new(remote)) {
+            Ok(stat) if stat.is_dir() => Ok(()),
+            _ => sftp
+                .mkdir(Path::new(remote), DIR_MODE)
+                .map_err(ConsoleError::SSH2),
+        }
+    }
+
+    fn scp_write(&self, remote: &str, content: &[u8], mode: i32) -> Result<()> {
+        let mut ch = self
+            .session
+            .scp_send(Path::new(remote), mode, content.len() as u64, None)
+            .map_err(ConsoleError::SSH2)?;
+        ch.write_all(content).map_err(ConsoleError::IO)?;
+        ch.send_eof().map_err(ConsoleError::SSH2)?;
+        ch.wait_eof().map_err(ConsoleError::SSH2)?;
+        ch.close().map_err(ConsoleError::SSH2)?;
+        ch.wait_close().map_err(ConsoleError::SSH2)
+    }
+
+    fn scp_read(&self, remote: &str) -> Result<Vec<u8>> {
+        let (mut ch, stat) = self
+            .session
+            .scp_recv(Path::new(remote))
+            .map_err(ConsoleError::SSH2)?;
+        let mut content = Vec::with_capacity(stat.size() as usize);
+        Read::by_ref(&mut ch)
+            .take(stat.size())
+            .read_to_end(&mut content)
+            .map_err(ConsoleError::IO)?;
+        ch.close().map_err(ConsoleError::SSH2)?;
+        ch.wait_close().map_err(ConsoleError::SSH2)?;
+        Ok(content)
+    }
+
+    // run a short cmd, true if it exits with 0
+    fn exec_ok(&self, cmd: &str) -> Result<bool> {
+        let mut ch = self.session.channel_session().map_err(ConsoleError::SSH2)?;
+        ch.exec(cmd).map_err(ConsoleError::SSH2)?;
+        ch.read_to_end(&mut Vec::new()).map_err(ConsoleError::IO)?;
+        ch.wait_close().map_err(ConsoleError::SSH2)?;
+        Ok(ch.exit_status().map_err(ConsoleError::SSH2)? == 0)
+    }
+}
+
+// remote is always unix
+fn remote_join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::remote_join;
+
+    #[test]
+    fn test_remote_join() {
+        assert_eq!(remote_join("/tmp", "a"), "/tmp/a");
+        assert_eq!(remote_join("/tmp/", "a"), "/tmp/a");
+    }
+}
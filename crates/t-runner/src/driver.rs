@@ -3,7 +3,10 @@ use std::sync::{
     Arc,
 };
 
-use t_binding::api::ApiTx;
+use t_binding::{
+    api::ApiTx,
+    report::{ConsoleEvent, Metric},
+};
 use t_config::Config;
 use t_console::SSH;
 use tracing::warn;
@@ -12,13 +15,16 @@ use crate::{
     error::DriverError,
     server::{Server, Service},
 };
-use t_util::AMOption;
+use parking_lot::{Mutex, RwLock};
+use t_util::{AMMap, AMOption};
 
 pub struct Driver {
     pub config: Option<Config>,
     pub stop_tx: mpsc::Sender<Sender<()>>,
     pub msg_tx: ApiTx,
     server: Option<Server>,
+    // shared with server, still usable after it started
+    repo: Arc<Service>,
 }
 
 impl Driver {
@@ -50,8 +56,22 @@ impl Driver {
         }
     }
 
+    /// connection state changes of text consoles since last call
+    pub fn take_console_events(&self) -> Vec<ConsoleEvent> {
+        self.repo.take_console_events()
+    }
+
+    /// values measured by runner since last call, like boot time
+    pub fn take_metrics(&self) -> Vec<Metric> {
+        self.repo.take_metrics()
+    }
+
     pub fn new_ssh(&mut self) -> StdResult<SSH, DriverError> {
-        if let Some(ssh) = self.config.as_ref().and_then(|c| c.ssh.clone()) {
+        if let Some(ssh) = self
+            .config
+            .as_ref()
+            .and_then(|c| c.ssh.get_default().cloned())
+        {
             SSH::new(ssh).map_err(DriverError::ConsoleError)
         } else {
             Err(DriverError::ConsoleError(
@@ -95,9 +115,15 @@ impl DriverBuilder {
             repo: Arc::new(Service {
                 enable_screenshot: true,
                 config: AMOption::new(self.config.clone()),
-                ssh: AMOption::new(None),
-                serial: AMOption::new(None),
+                ssh: AMMap::default(),
+                serial: AMMap::default(),
+                telnet: AMMap::default(),
+                conns: RwLock::new(Vec::new()),
+                metrics: Mutex::new(Vec::new()),
                 vnc: AMOption::new(None),
+                qemu: AMOption::new(None),
+                power: AMOption::new(None),
+                needles: Mutex::new(None),
             }),
         };
 
@@ -113,6 +139,7 @@ impl DriverBuilder {
             config: self.config,
             stop_tx,
             msg_tx,
+            repo: server.repo.clone(),
             server: Some(server),
         };
         Ok(driver)
@@ -1,16 +1,23 @@
 use crate::engine::Engine;
 use crate::engine::EngineClient;
 use crate::error::DriverError;
+use crate::report::Report;
 use crate::Driver;
 use crate::DriverBuilder;
+use std::collections::HashMap;
+use std::sync::mpsc::RecvTimeoutError;
 use std::thread;
-use t_config::Config;
+use std::time::Duration;
+use t_binding::{report::Status, CaseReport};
+use t_config::{Config, Suite};
 use t_console::SSH;
+use tracing::{info, warn};
 
 pub struct DriverForScript {
     driver: Driver,
     engine: Option<Engine>,
     engine_client: Option<EngineClient>,
+    report: Report,
 }
 
 type Result<T> = std::result::Result<T, DriverError>;
@@ -23,6 +30,7 @@ impl DriverForScript {
             driver,
             engine: None,
             engine_client: None,
+            report: Report::default(),
         })
     }
 
@@ -61,14 +69,79 @@ impl DriverForScript {
     }
 
     pub fn run_file(&mut self, script: String) -> &mut Self {
-        if let Some(c) = self.engine_client.as_mut() {
-            c.run_file(script.as_str());
+        if self.engine_client.is_some() {
+            let case = self.run_case(&script, None);
+            self.report.cases.push(case);
+        }
+        self
+    }
+
+    fn run_case(&mut self, script: &str, timeout: Option<Duration>) -> CaseReport {
+        let Some(c) = self.engine_client.as_mut() else {
+            return CaseReport::new(script).fail("no script engine");
+        };
+        let mut case = match timeout {
+            Some(timeout) => match c.run_file_timeout(script, timeout) {
+                Ok(case) => case,
+                Err(RecvTimeoutError::Timeout) => {
+                    CaseReport::new(script).fail(format!("timeout after {}s", timeout.as_secs()))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    CaseReport::new(script).fail("script engine stopped")
+                }
+            },
+            None => c
+                .run_file(script)
+                .unwrap_or_else(|| CaseReport::new(script).fail("script engine stopped")),
+        };
+        // like reboot in the case, and reconnect after it
+        case.events = self.driver.take_console_events();
+        case.metrics = self.driver.take_metrics();
+        case
+    }
+
+    /// run suite cases in order, skip cases whose dependencies not passed,
+    /// skip all the rest after a fatal case failed
+    pub fn run_suite(&mut self, suite: &Suite) -> &mut Self {
+        let mut passed: HashMap<&str, bool> = HashMap::new();
+        let mut aborted_by: Option<&str> = None;
+        for case in suite.cases.iter() {
+            let mut report = if let Some(fatal) = aborted_by {
+                CaseReport::new(&case.file).skip(format!("aborted by fatal case {}", fatal))
+            } else if let Some(dep) = case
+                .depends_on
+                .iter()
+                .find(|d| !passed.get(d.as_str()).copied().unwrap_or(false))
+            {
+                CaseReport::new(&case.file).skip(format!("dependency {} not passed", dep))
+            } else {
+                info!(msg = "run case", case = case.name);
+                self.run_case(&case.file, case.timeout.map(Duration::from_secs))
+            };
+            report.name = case.name.clone();
+            report.ignore_failure = case.ignore_failure;
+
+            passed.insert(&case.name, report.status == Status::Passed);
+            if case.fatal && !report.is_success() {
+                warn!(msg = "fatal case failed, abort suite", case = case.name);
+                aborted_by = Some(&case.name);
+            }
+            self.report.cases.push(report);
         }
         self
     }
 
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+
     pub fn new_ssh(&mut self) -> Result<SSH> {
-        if let Some(ssh) = self.driver.config.as_ref().and_then(|c| c.ssh.clone()) {
+        if let Some(ssh) = self
+            .driver
+            .config
+            .as_ref()
+            .and_then(|c| c.ssh.get_default().cloned())
+        {
             SSH::new(ssh.clone()).map_err(DriverError::ConsoleError)
         } else {
             Err(DriverError::ConsoleError(t_console::ConsoleError::Timeout))
@@ -1,10 +1,20 @@
-use std::sync::mpsc;
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
 
-use t_binding::{JSEngine, MsgReq, MsgRes, ScriptEngine};
+use t_binding::{new_engine, CaseReport, MsgReq, MsgRes};
+use tracing::warn;
 
 pub enum Msg {
     Stop(mpsc::Sender<()>),
-    ScriptFile(String),
+    // None is sent back if the case is still running after timeout
+    ScriptFile(String, Option<Duration>, mpsc::Sender<Option<CaseReport>>),
 }
 
 pub struct EngineClient {
@@ -17,10 +27,31 @@ impl EngineClient {
         rx.recv().unwrap();
     }
 
-    pub fn run_file(&self, script: &str) {
+    // block until the case finished, return None if engine stopped unexpectedly
+    pub fn run_file(&self, script: &str) -> Option<CaseReport> {
+        let (tx, rx) = mpsc::channel();
         self.msg_tx
-            .send(Msg::ScriptFile(script.to_string()))
-            .unwrap();
+            .send(Msg::ScriptFile(script.to_string(), None, tx))
+            .ok()?;
+        rx.recv().ok().flatten()
+    }
+
+    // like run_file, but give up after timeout. the running case is cut off from server
+    // and left behind on its own thread, engine moves on to the next case at once
+    pub fn run_file_timeout(
+        &self,
+        script: &str,
+        timeout: Duration,
+    ) -> Result<CaseReport, mpsc::RecvTimeoutError> {
+        let (tx, rx) = mpsc::channel();
+        self.msg_tx
+            .send(Msg::ScriptFile(script.to_string(), Some(timeout), tx))
+            .map_err(|_| mpsc::RecvTimeoutError::Disconnected)?;
+        match rx.recv() {
+            Ok(Some(report)) => Ok(report),
+            Ok(None) => Err(mpsc::RecvTimeoutError::Timeout),
+            Err(_) => Err(mpsc::RecvTimeoutError::Disconnected),
+        }
     }
 }
 
@@ -53,18 +84,120 @@ impl Engine {
                     tx.send(()).unwrap();
                     break;
                 }
-                Msg::ScriptFile(file) => {
-                    self.run_file(&file);
+                Msg::ScriptFile(file, timeout, tx) => {
+                    let report = self.run_file(&file, timeout);
+                    if tx.send(report).is_err() {
+                        warn!(msg = "case report receiver closed", file = file);
+                    }
                 }
             }
         }
     }
 
-    fn run_file(&mut self, file: &str) {
-        let mut e: Box<dyn ScriptEngine> = match self.ext.as_str() {
-            "js" => Box::new(JSEngine::new(self.msg_tx.clone())),
-            _ => unimplemented!(),
+    // each case runs on a thread of its own with a fresh engine, a case stuck without
+    // api calls can't be stopped, so it's cut off from server and abandoned on timeout
+    fn run_file(&mut self, file: &str, timeout: Option<Duration>) -> Option<CaseReport> {
+        // every case may use a different language, fallback to the default one
+        let ext = Path::new(file)
+            .extension()
+            .map(|e| e.to_string_lossy().to_string())
+            .unwrap_or(self.ext.clone());
+
+        // forward requests to server until the case is cancelled
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel::<(MsgReq, mpsc::Sender<MsgRes>)>();
+        let msg_tx = self.msg_tx.clone();
+        let forward_cancel = cancel.clone();
+        thread::spawn(move || {
+            while let Ok(req) = rx.recv() {
+                if forward_cancel.load(Ordering::SeqCst) {
+                    warn!(msg = "case cancelled, drop request", req = ?req.0);
+                    continue;
+                }
+                if msg_tx.send(req).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (report_tx, report_rx) = mpsc::channel();
+        let case = file.to_string();
+        thread::spawn(move || {
+            let report = match new_engine(&ext, tx) {
+                Some(mut e) => e.run_file(&case),
+                None => CaseReport::new(&case).fail(format!("unsupported script type: {}", ext)),
+            };
+            let _ = report_tx.send(report);
+        });
+
+        let res = match timeout {
+            Some(timeout) => report_rx.recv_timeout(timeout).ok(),
+            None => report_rx.recv().ok(),
         };
-        e.run_file(file);
+        if res.is_none() {
+            warn!(
+                msg = "case timeout, left running without server",
+                file = file
+            );
+            cancel.store(true, Ordering::SeqCst);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Engine;
+    use std::{
+        fs,
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+    use t_binding::{MsgReq, MsgRes};
+
+    #[test]
+    fn test_timeout_cancel_case() {
+        let (tx, rx) = mpsc::channel::<(MsgReq, mpsc::Sender<MsgRes>)>();
+        thread::spawn(move || {
+            while let Ok((_, tx)) = rx.recv() {
+                thread::sleep(Duration::from_millis(20));
+                let _ = tx.send(MsgRes::ScriptRun {
+                    code: 0,
+                    value: String::new(),
+                });
+            }
+        });
+
+        let dir = std::env::temp_dir().join("autotest-engine-timeout");
+        fs::create_dir_all(&dir).unwrap();
+        let endless = dir.join("endless.lua");
+        fs::write(
+            &endless,
+            "function run() while true do script_run('ls', 1) end end",
+        )
+        .unwrap();
+        // never calls api, only a timeout can get rid of it
+        let busy = dir.join("busy.lua");
+        fs::write(&busy, "function run() while true do end end").unwrap();
+        let quick = dir.join("quick.lua");
+        fs::write(&quick, "function run() script_run('ls', 1) end").unwrap();
+
+        let (mut engine, client) = Engine::new("lua", tx);
+        thread::spawn(move || engine.start());
+
+        for case in [endless, busy] {
+            let res = client.run_file_timeout(case.to_str().unwrap(), Duration::from_millis(200));
+            assert_eq!(res.err(), Some(mpsc::RecvTimeoutError::Timeout));
+
+            // stuck case is left behind, engine is free for the next one
+            let start = Instant::now();
+            let report = client
+                .run_file_timeout(quick.to_str().unwrap(), Duration::from_secs(5))
+                .unwrap();
+            assert!(report.is_success());
+            assert!(start.elapsed() < Duration::from_secs(1));
+        }
+        client.stop();
     }
 }
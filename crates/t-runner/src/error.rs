@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{fmt::Display, path::PathBuf};
 
 use t_binding::ApiError;
 use t_console::ConsoleError;
@@ -18,3 +18,26 @@ impl Display for DriverError {
         }
     }
 }
+
+#[derive(Debug, Clone)]
+pub enum NeedleError {
+    // no such file, or no needle carries the tags
+    NotFound(String),
+    // file is there but can't be used, like a broken json or png
+    Unusable { path: PathBuf, reason: String },
+}
+
+impl std::error::Error for NeedleError {}
+impl Display for NeedleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NeedleError::NotFound(s) => write!(f, "needle file not found, {}", s),
+            NeedleError::Unusable { path, reason } => write!(
+                f,
+                "needle file {} exists but can't be used, {}",
+                path.display(),
+                reason
+            ),
+        }
+    }
+}
@@ -2,6 +2,7 @@ mod driver;
 mod driver_for_script;
 mod engine;
 pub mod needle;
+pub mod report;
 mod server;
 pub use driver_for_script::DriverForScript;
 pub mod error;
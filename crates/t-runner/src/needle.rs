@@ -1,87 +1,614 @@
 use std::{
-    fs::File,
-    io::{BufReader, Read},
+    collections::HashMap,
+    env,
+    fs::{self, File},
+    io::{self, BufReader},
     path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
 };
 
+use notify::Watcher;
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use t_console::{Rect, PNG};
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
+
+use crate::error::NeedleError;
+
+// pixels searched around an area for a shifted match, if the area has no margin
+const DEFAULT_MARGIN: u16 = 10;
+// pixels compared at most when searching an area without margin, big areas search less around
+const SEARCH_PIXELS: usize = 1 << 23;
+// gray level difference still counted as same pixel in flat areas
+const FLAT_TOLERANCE: f32 = 16.;
+// std of gray levels below it means area has no pattern to correlate
+const FLAT_STD: f32 = 2.;
+// reads text of ocr areas, must be in PATH
+const OCR_CMD: &str = "tesseract";
 
 pub struct Needle {
+    // path of json in needle_dir without extension, like dark/login
+    pub name: String,
     pub config: NeedleConfig,
     pub data: PNG,
+    // opaque pixels of png row by row, None if png has no alpha
+    pub mask: Option<Vec<bool>>,
+    // gray pixels of each area compared with screen, same order as config areas
+    areas: Vec<Option<AreaPixels>>,
+}
+
+/// where an area is found on screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AreaMatch {
+    pub similarity: f32,
+    // (x, y) from area position in needle
+    pub offset: (i32, i32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NeedleMatch {
+    // of the worst area
+    pub similarity: f32,
+    pub matched: bool,
+    // same order as needle areas
+    pub areas: Vec<AreaMatch>,
 }
 
 impl Needle {
-    pub fn cmp(s: &PNG, needle: &Needle, min_same: Option<f32>) -> (f32, bool) {
+    /// gray pixels of match areas are prepared here, build again if config changes
+    pub fn new(name: String, config: NeedleConfig, data: PNG, mask: Option<Vec<bool>>) -> Self {
+        let gray = Gray::new(&data);
+        let excludes: Vec<&Area> = config
+            .areas
+            .iter()
+            .filter(|a| a.type_field == AreaType::Exclude)
+            .collect();
+        let areas = config
+            .areas
+            .iter()
+            .map(|area| match area.type_field {
+                AreaType::Match => AreaPixels::new(&gray, area, &excludes, mask.as_deref()),
+                AreaType::Exclude | AreaType::Ocr => None,
+            })
+            .collect();
+        Self {
+            name,
+            config,
+            data,
+            mask,
+            areas,
+        }
+    }
+
+    /// search each match area within its margin, pixels in exclude areas are ignored.
+    /// every match area must reach min_same, then ocr areas must show their text
+    pub fn cmp(s: &PNG, needle: &Needle, min_same: Option<f32>) -> NeedleMatch {
+        Self::cmp_gray(s, &Gray::new(s), needle, min_same)
+    }
+
+    /// like cmp, with gray of the screen built once for all needles
+    pub fn cmp_gray(s: &PNG, screen: &Gray, needle: &Needle, min_same: Option<f32>) -> NeedleMatch {
         if needle.config.areas.is_empty() {
             warn!("this needle has no match ares");
-            return (1.0, true);
+            return NeedleMatch {
+                similarity: 1.,
+                matched: true,
+                areas: Vec::new(),
+            };
+        }
+
+        let min_same = min_same.unwrap_or(0.95);
+        let mut areas: Vec<AreaMatch> = needle
+            .config
+            .areas
+            .iter()
+            .zip(needle.areas.iter())
+            .map(|(area, pixels)| match area.type_field {
+                AreaType::Match => match_area(screen, area, pixels.as_ref()),
+                AreaType::Exclude | AreaType::Ocr => AreaMatch {
+                    similarity: 1.,
+                    offset: (0, 0),
+                },
+            })
+            .collect();
+
+        // ocr is slow, only read text when the picture already matched
+        let worst = |areas: &[AreaMatch]| areas.iter().map(|m| m.similarity).fold(1., f32::min);
+        if worst(&areas) >= min_same {
+            // text moves with the window, like match areas do
+            let offset = needle
+                .config
+                .areas
+                .iter()
+                .zip(areas.iter())
+                .find(|(a, _)| a.type_field == AreaType::Match)
+                .map(|(_, m)| m.offset)
+                .unwrap_or_default();
+            for (area, found) in needle.config.areas.iter().zip(areas.iter_mut()) {
+                if area.type_field == AreaType::Ocr {
+                    *found = match_ocr(s, area, offset);
+                }
+            }
+        }
+
+        let similarity = worst(&areas);
+        info!(res = similarity, areas = ?areas);
+        NeedleMatch {
+            similarity,
+            matched: similarity >= min_same,
+            areas,
+        }
+    }
+
+    /// click point of the first area with one, moved with the area
+    pub fn click_point(&self, m: &NeedleMatch) -> Option<(u16, u16)> {
+        self.config
+            .areas
+            .iter()
+            .zip(m.areas.iter())
+            .find_map(|(area, found)| {
+                let click = area.click.as_ref()?;
+                let x = (area.left + click.left) as i32 + found.offset.0;
+                let y = (area.top + click.top) as i32 + found.offset.1;
+                Some((x.max(0) as u16, y.max(0) as u16))
+            })
+    }
+}
+
+// gray levels of an image, what areas are compared with
+pub struct Gray {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl Gray {
+    pub fn new(png: &PNG) -> Self {
+        let mut data = Vec::with_capacity(png.width as usize * png.height as usize);
+        for row in 0..png.height {
+            for col in 0..png.width {
+                let p = png.get(row, col);
+                data.push(match p {
+                    [r, g, b, ..] => 0.299 * *r as f32 + 0.587 * *g as f32 + 0.114 * *b as f32,
+                    [v, ..] => *v as f32,
+                    [] => 0.,
+                });
+            }
+        }
+        Self {
+            width: png.width as usize,
+            height: png.height as usize,
+            data,
         }
+    }
 
-        let mut not_same = 0;
-        let mut all = 0;
-        for area in needle.config.areas.iter() {
-            all += area.width * area.height;
-            let count = s.cmp_rect_and_count(&needle.data, &area.into());
-            not_same += count;
+    // pixels of rect row by row, None if rect is not inside the image
+    fn rect(
+        &self,
+        left: i32,
+        top: i32,
+        width: usize,
+        height: usize,
+    ) -> Option<impl Iterator<Item = f32> + '_> {
+        if left < 0 || top < 0 {
+            return None;
+        }
+        let (left, top) = (left as usize, top as usize);
+        if left + width > self.width || top + height > self.height {
+            return None;
         }
+        Some((top..top + height).flat_map(move |row| {
+            let start = row * self.width + left;
+            self.data[start..start + width].iter().copied()
+        }))
+    }
+}
+
+// pixels of a match area in needle not covered by any exclude area nor transparent
+struct AreaPixels {
+    keep: Vec<bool>,
+    // kept gray levels minus their mean
+    centered: Vec<f32>,
+    mean: f32,
+    std: f32,
+}
 
-        let res = 1. - (not_same as f32 / all as f32);
-        info!(res = res, all = all, not_same = not_same);
-        (res, res >= min_same.unwrap_or(0.95))
+impl AreaPixels {
+    // None if area is out of needle image
+    fn new(needle: &Gray, area: &Area, excludes: &[&Area], mask: Option<&[bool]>) -> Option<Self> {
+        let Some(pixels) = needle
+            .rect(
+                area.left as i32,
+                area.top as i32,
+                area.width as usize,
+                area.height as usize,
+            )
+            .filter(|_| area.width > 0 && area.height > 0)
+        else {
+            warn!(msg = "needle area out of needle image", area = ?area);
+            return None;
+        };
+        let keep: Vec<bool> = (0..area.height)
+            .flat_map(|y| (0..area.width).map(move |x| (area.left + x, area.top + y)))
+            .map(|(x, y)| {
+                let transparent = mask.is_some_and(|m| !m[y as usize * needle.width + x as usize]);
+                !transparent && !excludes.iter().any(|e| e.contains(x, y))
+            })
+            .collect();
+        let kept: Vec<f32> = pixels
+            .zip(keep.iter())
+            .filter_map(|(v, k)| k.then_some(v))
+            .collect();
+        let (mean, std) = mean_std(kept.iter().copied());
+        Some(Self {
+            keep,
+            centered: kept.into_iter().map(|v| v - mean).collect(),
+            mean,
+            std,
+        })
+    }
+
+    // zero mean normalized cross correlation, it doesn't care about small brightness changes
+    // like antialiasing. flat needle area has no pattern, count pixels close enough instead
+    fn similarity(&self, screen: impl Iterator<Item = f32>) -> f32 {
+        let screen = screen
+            .zip(self.keep.iter())
+            .filter_map(|(v, k)| k.then_some(v));
+        let n = self.centered.len() as f32;
+        if self.std < FLAT_STD {
+            let same = screen
+                .filter(|x| (x - self.mean).abs() <= FLAT_TOLERANCE)
+                .count();
+            return same as f32 / n;
+        }
+        // centered needle sums to 0, so screen mean drops out of the covariance
+        let (mut sum, mut sum_sq, mut cov) = (0., 0., 0.);
+        for (s, c) in screen.zip(self.centered.iter()) {
+            sum += s;
+            sum_sq += s * s;
+            cov += s * c;
+        }
+        let mean = sum / n;
+        let ss = (sum_sq / n - mean * mean).max(0.).sqrt();
+        if ss < FLAT_STD {
+            return 0.;
+        }
+        (cov / n / (self.std * ss)).clamp(0., 1.)
     }
 }
 
+fn mean_std(v: impl Iterator<Item = f32> + Clone) -> (f32, f32) {
+    let n = v.clone().count() as f32;
+    let mean = v.clone().sum::<f32>() / n;
+    let var = v.map(|x| (x - mean) * (x - mean)).sum::<f32>() / n;
+    (mean, var.sqrt())
+}
+
+fn match_area(screen: &Gray, area: &Area, pixels: Option<&AreaPixels>) -> AreaMatch {
+    let not_found = AreaMatch {
+        similarity: 0.,
+        offset: (0, 0),
+    };
+    let Some(pixels) = pixels else {
+        return not_found;
+    };
+    if pixels.centered.is_empty() {
+        warn!(msg = "match area is fully excluded or transparent", area = ?area);
+        return AreaMatch {
+            similarity: 1.,
+            offset: (0, 0),
+        };
+    }
+
+    // nearest first, the smallest shift wins if several are as good
+    let margin = area
+        .margin
+        .unwrap_or_else(|| default_margin(area.width, area.height)) as i32;
+    let mut offsets: Vec<(i32, i32)> = (-margin..=margin)
+        .flat_map(|y| (-margin..=margin).map(move |x| (x, y)))
+        .collect();
+    offsets.sort_by_key(|(x, y)| x * x + y * y);
+
+    let (width, height) = (area.width as usize, area.height as usize);
+    let mut best = not_found;
+    for (x, y) in offsets {
+        let Some(found) = screen.rect(area.left as i32 + x, area.top as i32 + y, width, height)
+        else {
+            continue;
+        };
+        let similarity = pixels.similarity(found);
+        if similarity > best.similarity {
+            best = AreaMatch {
+                similarity,
+                offset: (x, y),
+            };
+            if similarity >= 0.999 {
+                break;
+            }
+        }
+    }
+    best
+}
+
+// a full screen area would compare millions of pixels at each of the 441 offsets
+fn default_margin(width: u16, height: u16) -> u16 {
+    let offsets = SEARCH_PIXELS / (width as usize * height as usize).max(1);
+    let side = (offsets as f64).sqrt() as usize;
+    (side.saturating_sub(1) / 2).min(DEFAULT_MARGIN as usize) as u16
+}
+
+// ocr area without text only logs what it reads
+fn match_ocr(s: &PNG, area: &Area, offset: (i32, i32)) -> AreaMatch {
+    let left = area.left as i32 + offset.0;
+    let top = area.top as i32 + offset.1;
+    let similarity = match ocr(s, left, top, area.width, area.height) {
+        Ok(text) => {
+            let text = normalize_text(&text);
+            info!(msg = "ocr area", text = text, expect = area.text);
+            match &area.text {
+                Some(expect) if normalize_text(expect) != text => 0.,
+                _ => 1.,
+            }
+        }
+        Err(e) => {
+            warn!(msg = "ocr failed", area = ?area, reason = e);
+            match area.text {
+                Some(_) => 0.,
+                None => 1.,
+            }
+        }
+    };
+    AreaMatch { similarity, offset }
+}
+
+// crop rect of screen to a temp file and read it with OCR_CMD
+fn ocr(s: &PNG, left: i32, top: i32, width: u16, height: u16) -> Result<String, String> {
+    if left < 0
+        || top < 0
+        || left + width as i32 > s.width as i32
+        || top + height as i32 > s.height as i32
+    {
+        return Err("ocr area out of screen".to_string());
+    }
+    let (left, top) = (left as u16, top as u16);
+    let img = image::RgbImage::from_fn(width as u32, height as u32, |x, y| {
+        match s.get(top + y as u16, left + x as u16) {
+            [r, g, b, ..] => image::Rgb([*r, *g, *b]),
+            [v, ..] => image::Rgb([*v, *v, *v]),
+            [] => image::Rgb([0, 0, 0]),
+        }
+    });
+    let path = env::temp_dir().join(format!("t-ocr-{}.png", nanoid::nanoid!()));
+    img.save_with_format(&path, image::ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    let output = Command::new(OCR_CMD).arg(&path).arg("stdout").output();
+    let _ = fs::remove_file(&path);
+
+    let output = output.map_err(|e| format!("run {} failed, {}", OCR_CMD, e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// ocr output has line breaks and spaces of its own
+fn normalize_text(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// what was read from needle_dir, the watcher drops what changed on disk
+#[derive(Default)]
+struct NeedleCache {
+    // None until dir is read again
+    index: Option<Vec<(String, Result<NeedleConfig, NeedleError>)>>,
+    // decoded needles by name
+    needles: HashMap<String, Arc<Needle>>,
+}
+
 pub struct NeedleManager {
     dir: PathBuf,
+    cache: Arc<Mutex<NeedleCache>>,
+    // None if dir can't be watched, nothing is cached then
+    watcher: Option<notify::RecommendedWatcher>,
 }
 
 impl NeedleManager {
     pub fn new(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        let cache = Arc::new(Mutex::new(NeedleCache::default()));
+        let watcher = match Self::watch(&dir, cache.clone()) {
+            Ok(w) => Some(w),
+            Err(e) => {
+                warn!(msg = "watch needle dir failed, needles are read on every check", dir = ?dir, reason = ?e);
+                None
+            }
+        };
         Self {
-            dir: dir.as_ref().to_path_buf(),
+            dir,
+            cache,
+            watcher,
         }
     }
 
-    pub fn load(&self, tag: &str) -> Option<Needle> {
-        let needle_png = self.load_image(self.dir.join(format!("{}.png", tag)))?;
-        let json: NeedleConfig = self.load_json(self.dir.join(format!("{}.json", tag)))?;
-        Some(Needle {
-            config: json,
-            data: needle_png,
-        })
+    fn watch(
+        dir: &Path,
+        cache: Arc<Mutex<NeedleCache>>,
+    ) -> notify::Result<notify::RecommendedWatcher> {
+        let root = dir.to_path_buf();
+        let mut watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+                Ok(event) if !event.kind.is_access() => {
+                    let mut cache = cache.lock();
+                    // a json may be added, removed or retagged
+                    cache.index = None;
+                    for path in event.paths {
+                        match path.strip_prefix(&root) {
+                            Ok(p) => {
+                                cache.needles.remove(&needle_name(p));
+                            }
+                            Err(_) => cache.needles.clear(),
+                        }
+                    }
+                    debug!(msg = "needle cache invalidated");
+                }
+                Ok(_) => {}
+                Err(e) => warn!(msg = "needle watcher failed", reason = ?e),
+            })?;
+        watcher.watch(dir, notify::RecursiveMode::Recursive)?;
+        Ok(watcher)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn load(&self, tag: &str) -> Result<Needle, NeedleError> {
+        let config = self.load_json(self.dir.join(format!("{}.json", tag)))?;
+        let (data, mask) = read_png(&self.dir.join(format!("{}.png", tag)))?;
+        Ok(Needle::new(tag.to_string(), config, data, mask))
+    }
+
+    /// every needle carrying any of tags, or named as one of them, never empty if Ok.
+    /// read from cache, only files changed since last call are loaded again
+    pub fn load_tags(&self, tags: &[String]) -> Result<Vec<Arc<Needle>>, NeedleError> {
+        let mut cache = self.cache.lock();
+        if self.watcher.is_none() {
+            *cache = NeedleCache::default();
+        }
+        let cache = &mut *cache;
+        let index = cache.index.get_or_insert_with(|| self.index());
+
+        let mut res = Vec::new();
+        let mut err = None;
+        for (name, config) in index.iter() {
+            let config = match config {
+                Ok(config) => config,
+                // tags of a broken json are unknown, only its name can match
+                Err(e) => {
+                    if tags.contains(name) {
+                        err.get_or_insert(e.clone());
+                    }
+                    continue;
+                }
+            };
+            if !tags.iter().any(|t| t == name || config.tags.contains(t)) {
+                continue;
+            }
+            if let Some(needle) = cache.needles.get(name) {
+                res.push(needle.clone());
+                continue;
+            }
+            match read_png(&self.dir.join(format!("{}.png", name))) {
+                Ok((data, mask)) => {
+                    let needle = Arc::new(Needle::new(name.clone(), config.clone(), data, mask));
+                    cache.needles.insert(name.clone(), needle.clone());
+                    res.push(needle);
+                }
+                Err(e) => {
+                    warn!(msg = "needle skipped", needle = name, reason = %e);
+                    err.get_or_insert(e);
+                }
+            }
+        }
+        if res.is_empty() {
+            return Err(err.unwrap_or(NeedleError::NotFound(format!("tags {:?}", tags))));
+        }
+        Ok(res)
     }
 
-    pub fn load_image(&self, tag: impl AsRef<Path>) -> Option<PNG> {
-        let needle_file = File::open(tag).ok()?;
-        let needle_png = image::load(BufReader::new(needle_file), image::ImageFormat::Png).ok()?;
-        match needle_png {
-            image::DynamicImage::ImageRgb8(img) => {
-                let data = img.bytes();
-                let data = data.map(|x| x.unwrap()).collect::<Vec<u8>>();
-                Some(PNG::new_with_data(
-                    img.width() as u16,
-                    img.height() as u16,
-                    data,
-                    3,
-                ))
+    // name and config of every json in dir and its subdirs, sorted by name
+    fn index(&self) -> Vec<(String, Result<NeedleConfig, NeedleError>)> {
+        let mut res = Vec::new();
+        let mut dirs = vec![self.dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for path in entries.flatten().map(|e| e.path()) {
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                if path.extension() != Some("json".as_ref()) {
+                    continue;
+                }
+                let config = self.load_json(&path);
+                if let Err(ref e) = config {
+                    warn!(msg = "invalid needle json", reason = %e);
+                }
+                let name = needle_name(path.strip_prefix(&self.dir).unwrap_or(&path));
+                res.push((name, config));
             }
-            _ => None,
         }
+        res.sort_by(|a, b| a.0.cmp(&b.0));
+        res
     }
 
-    pub fn load_json(&self, tag: impl AsRef<Path>) -> Option<NeedleConfig> {
-        let json_file = File::open(tag).ok()?;
-        let json: NeedleConfig = serde_json::from_reader(BufReader::new(json_file)).ok()?;
-        Some(json)
+    /// any png format converted to rgb, alpha is dropped
+    pub fn load_image(&self, tag: impl AsRef<Path>) -> Result<PNG, NeedleError> {
+        read_png(tag.as_ref()).map(|(png, _)| png)
     }
 
-    pub fn cmp(&self, s: &PNG, filename: &str, min_same: Option<f32>) -> Option<(f32, bool)> {
+    pub fn load_json(&self, tag: impl AsRef<Path>) -> Result<NeedleConfig, NeedleError> {
+        let path = tag.as_ref();
+        let json_file = open(path)?;
+        serde_json::from_reader(BufReader::new(json_file)).map_err(|e| NeedleError::Unusable {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })
+    }
+
+    pub fn cmp(
+        &self,
+        s: &PNG,
+        filename: &str,
+        min_same: Option<f32>,
+    ) -> Result<NeedleMatch, NeedleError> {
         let needle = self.load(filename)?;
-        Some(Needle::cmp(s, &needle, min_same))
+        Ok(Needle::cmp(s, &needle, min_same))
+    }
+}
+
+fn open(path: &Path) -> Result<File, NeedleError> {
+    File::open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => NeedleError::NotFound(path.display().to_string()),
+        _ => NeedleError::Unusable {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        },
+    })
+}
+
+// rgba, gray, 16 bit and palette pngs all end up as rgb pixels of PNG,
+// with alpha below half as transparent pixels which are not compared
+fn read_png(path: &Path) -> Result<(PNG, Option<Vec<bool>>), NeedleError> {
+    let unusable = |reason: String| NeedleError::Unusable {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let file = open(path)?;
+    let img = image::load(BufReader::new(file), image::ImageFormat::Png)
+        .map_err(|e| unusable(e.to_string()))?;
+    if img.width() > u16::MAX as u32 || img.height() > u16::MAX as u32 {
+        return Err(unusable(format!(
+            "{}x{} is too large",
+            img.width(),
+            img.height()
+        )));
     }
+    let mask = img
+        .color()
+        .has_alpha()
+        .then(|| img.to_rgba8().pixels().map(|p| p[3] >= 128).collect());
+    let rgb = img.to_rgb8();
+    let png = PNG::new_with_data(rgb.width() as u16, rgb.height() as u16, rgb.into_raw(), 3);
+    Ok((png, mask))
+}
+
+// path in needle_dir without extension, png and json of a needle share it
+fn needle_name(path: &Path) -> String {
+    path.with_extension("").to_string_lossy().replace('\\', "/")
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -92,16 +619,44 @@ pub struct NeedleConfig {
     pub tags: Vec<String>,
 }
 
+/// how an area is used, like openQA
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AreaType {
+    /// compared with the screen
+    #[default]
+    Match,
+    /// ignored by match areas, like clocks and cursors
+    Exclude,
+    /// text on screen must be the text of the area
+    Ocr,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Area {
     #[serde(rename = "type")]
-    pub type_field: String,
+    pub type_field: AreaType,
     pub left: u16,
     pub top: u16,
     pub width: u16,
     pub height: u16,
     pub click: Option<AreaClick>,
+    // pixels to search around for a shifted match, default 10
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub margin: Option<u16>,
+    // expected text of ocr area, compared ignoring extra whitespace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl Area {
+    fn contains(&self, x: u16, y: u16) -> bool {
+        // u16 sum overflows for areas at the far end of a large screen
+        let (x, y) = (x as u32, y as u32);
+        let (left, top) = (self.left as u32, self.top as u32);
+        x >= left && x < left + self.width as u32 && y >= top && y < top + self.height as u32
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -123,12 +678,53 @@ impl From<&Area> for Rect {
 
 #[cfg(test)]
 mod test {
-    use std::fs;
+    use std::{
+        fs,
+        sync::Arc,
+        thread,
+        time::{Duration, Instant},
+    };
 
-    use super::NeedleManager;
-    use crate::needle::{Area, NeedleConfig};
+    use super::{normalize_text, Needle, NeedleManager};
+    use crate::error::NeedleError;
+    use crate::needle::{Area, AreaClick, AreaType, NeedleConfig};
     use image::{ImageBuffer, Rgb};
-    use t_console::Rect;
+    use t_console::{Rect, PNG};
+
+    // white screen with an 8x6 colorful block at left, top
+    fn screen_with_block(left: u16, top: u16) -> PNG {
+        let mut png = PNG::new(40, 30, 3);
+        png.data.iter_mut().for_each(|p| *p = 255);
+        for y in 0..6 {
+            for x in 0..8 {
+                let v = ((x * 37 + y * 91) % 200) as u8;
+                png.set(top + y, left + x, &[v, v / 2, 255 - v]);
+            }
+        }
+        png
+    }
+
+    fn block_needle(margin: Option<u16>) -> Needle {
+        block_needle_with(margin, |_| {})
+    }
+
+    fn block_needle_with(margin: Option<u16>, setup: impl FnOnce(&mut NeedleConfig)) -> Needle {
+        let mut config = NeedleConfig {
+            areas: vec![Area {
+                type_field: AreaType::Match,
+                left: 10,
+                top: 10,
+                width: 8,
+                height: 6,
+                click: Some(AreaClick { left: 2, top: 3 }),
+                margin,
+                text: None,
+            }],
+            ..Default::default()
+        };
+        setup(&mut config);
+        Needle::new("block".to_string(), config, screen_with_block(10, 10), None)
+    }
 
     fn init_needle_manager() -> NeedleManager {
         // 创建临时文件夹
@@ -205,10 +801,290 @@ mod test {
         NeedleManager::new(tmp_needle_folder)
     }
 
+    #[test]
+    fn test_shifted_match() {
+        let needle = block_needle(None);
+        let found = Needle::cmp(&screen_with_block(10, 10), &needle, None);
+        assert!(found.matched);
+        assert_eq!(found.areas[0].offset, (0, 0));
+
+        // window moved 2 left and 1 down, click follows it
+        let found = Needle::cmp(&screen_with_block(8, 11), &needle, None);
+        assert!(found.matched);
+        assert_eq!(found.areas[0].offset, (-2, 1));
+        assert_eq!(needle.click_point(&found), Some((10, 14)));
+
+        // antialiasing changes pixels a little
+        let mut screen = screen_with_block(10, 10);
+        for x in [10, 13, 16] {
+            let p: Vec<u8> = screen
+                .get(12, x)
+                .iter()
+                .map(|v| v.saturating_add(12))
+                .collect();
+            screen.set(12, x, &p);
+        }
+        let found = Needle::cmp(&screen, &needle, None);
+        assert!(found.matched && found.similarity < 1.);
+
+        // too far away
+        let found = Needle::cmp(&screen_with_block(15, 10), &block_needle(Some(2)), None);
+        assert!(!found.matched);
+    }
+
+    #[test]
+    fn test_full_screen_area() {
+        let screen = |f: fn(u16, u16) -> u8| {
+            let mut png = PNG::new(800, 600, 3);
+            for y in 0..600 {
+                for x in 0..800 {
+                    let v = f(x, y);
+                    png.set(y, x, &[v, v / 2, 255 - v]);
+                }
+            }
+            png
+        };
+        let desktop = screen(|x, y| ((x as u32 * 37 + y as u32 * 91) % 200) as u8);
+        let config = |left, top, width, height| NeedleConfig {
+            areas: vec![Area {
+                type_field: AreaType::Match,
+                left,
+                top,
+                width,
+                height,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let full = Needle::new(
+            "full".to_string(),
+            config(0, 0, 800, 600),
+            desktop.clone(),
+            None,
+        );
+        let found = Needle::cmp(&desktop, &full, None);
+        assert!(found.matched);
+        assert_eq!(found.areas[0].offset, (0, 0));
+
+        // nothing matches, so every offset is tried
+        let other = screen(|x, y| ((x as u32 * 53 + y as u32 * 17) % 200) as u8);
+        let almost = Needle::new("almost".to_string(), config(5, 5, 790, 590), desktop, None);
+        let start = Instant::now();
+        assert!(!Needle::cmp(&other, &almost, None).matched);
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "{:?}",
+            start.elapsed()
+        );
+
+        assert_eq!(super::default_margin(8, 6), 10);
+        assert_eq!(super::default_margin(800, 600), 1);
+    }
+
+    #[test]
+    fn test_flat_area() {
+        let needle = block_needle_with(Some(0), |c| c.areas[0].left = 25);
+        let white = screen_with_block(0, 0);
+        assert_eq!(Needle::cmp(&white, &needle, None).similarity, 1.);
+
+        let black = PNG::new(40, 30, 3);
+        assert_eq!(Needle::cmp(&black, &needle, None).similarity, 0.);
+    }
+
+    #[test]
+    fn test_exclude_area() {
+        let needle = block_needle(Some(0));
+        // a clock in the middle of the block changes all the time
+        let mut screen = screen_with_block(10, 10);
+        for y in 12..14 {
+            for x in 13..16 {
+                screen.set(y, x, &[0, 255, 0]);
+            }
+        }
+        assert!(!Needle::cmp(&screen, &needle, None).matched);
+
+        let needle = block_needle_with(Some(0), |c| {
+            c.areas.push(Area {
+                type_field: AreaType::Exclude,
+                left: 13,
+                top: 12,
+                width: 3,
+                height: 2,
+                ..Default::default()
+            })
+        });
+        let found = Needle::cmp(&screen, &needle, None);
+        assert!(found.matched);
+        assert_eq!(found.areas.len(), 2);
+        assert_eq!(needle.click_point(&found), Some((12, 13)));
+    }
+
+    #[test]
+    fn test_ocr_area() {
+        let config: NeedleConfig = serde_json::from_str(
+            r#"{"areas": [{"type": "ocr", "left": 0, "top": 0, "width": 5, "height": 5,
+                "text": "login:"}], "properties": [], "tags": []}"#,
+        )
+        .unwrap();
+        assert_eq!(config.areas[0].type_field, AreaType::Ocr);
+        assert_eq!(config.areas[0].text.as_deref(), Some("login:"));
+
+        // no text on the screen, or no ocr to read it, fails an area expecting text
+        let screen = screen_with_block(10, 10);
+        let found = super::match_ocr(&screen, &config.areas[0], (-10, 0));
+        assert_eq!(found.similarity, 0.);
+
+        // ocr area without text never fails the needle, even if ocr can't run
+        let mut area = config.areas[0].clone();
+        area.text = None;
+        assert_eq!(super::match_ocr(&screen, &area, (-10, 0)).similarity, 1.);
+
+        // spaces and line breaks in what ocr reads count as one space
+        assert_eq!(
+            normalize_text(" Debian GNU/Linux 12\n\nlogin: \n"),
+            "Debian GNU/Linux 12 login:"
+        );
+    }
+
+    #[test]
+    fn test_area_contains() {
+        // right and bottom edges at the end of u16 don't overflow
+        let far = Area {
+            left: u16::MAX - 1,
+            top: u16::MAX - 1,
+            width: 2,
+            height: 2,
+            ..Default::default()
+        };
+        assert!(far.contains(u16::MAX, u16::MAX));
+        assert!(!far.contains(0, 0));
+    }
+
+    #[test]
+    fn test_load_tags() {
+        let dir = std::env::temp_dir().join(format!("needle-tags-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("dark")).unwrap();
+        let save = |name: &str, tags: &[&str]| {
+            let needle = block_needle(None);
+            image::RgbImage::from_raw(40, 30, needle.data.data.clone())
+                .unwrap()
+                .save_with_format(dir.join(format!("{name}.png")), image::ImageFormat::Png)
+                .unwrap();
+            let config = NeedleConfig {
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                ..needle.config
+            };
+            fs::write(
+                dir.join(format!("{name}.json")),
+                serde_json::to_string(&config).unwrap(),
+            )
+            .unwrap();
+        };
+        save("login", &["login-prompt"]);
+        save("dark/login", &["login-prompt", "dark"]);
+        save("desktop", &["desktop"]);
+
+        let nmg = NeedleManager::new(&dir);
+        let names = |tags: &[&str]| -> Vec<String> {
+            let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+            match nmg.load_tags(&tags) {
+                Ok(needles) => needles.iter().map(|n| n.name.clone()).collect(),
+                Err(_) => Vec::new(),
+            }
+        };
+        assert_eq!(names(&["login-prompt"]), ["dark/login", "login"]);
+        assert_eq!(names(&["dark", "desktop"]), ["dark/login", "desktop"]);
+        // file name still works without tag
+        assert_eq!(names(&["login"]), ["login"]);
+        assert!(names(&["missing"]).is_empty());
+
+        // cached until the files change
+        let tags = ["desktop".to_string()];
+        let first = nmg.load_tags(&tags).unwrap();
+        assert!(Arc::ptr_eq(&first[0], &nmg.load_tags(&tags).unwrap()[0]));
+        save("desktop", &["desktop", "kde"]);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        // json may be read while half written, it's skipped until the next event
+        while nmg.load_tags(&tags).map(|n| n[0].config.tags.len()).ok() != Some(2) {
+            assert!(Instant::now() < deadline, "needle not reloaded");
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!Arc::ptr_eq(&first[0], &nmg.load_tags(&tags).unwrap()[0]));
+        assert_eq!(names(&["kde"]), ["desktop"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_png_formats() {
+        let dir = std::env::temp_dir().join(format!("needle-formats-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let block = block_needle(None);
+        let save_json = |name: &str| {
+            fs::write(
+                dir.join(format!("{name}.json")),
+                serde_json::to_string(&block.config).unwrap(),
+            )
+            .unwrap();
+        };
+
+        // block with a transparent hole, anything may show through it
+        let rgba = image::RgbaImage::from_fn(40, 30, |x, y| {
+            let p = block.data.get(y as u16, x as u16);
+            let a = if (13..16).contains(&x) && (12..14).contains(&y) {
+                0
+            } else {
+                255
+            };
+            image::Rgba([p[0], p[1], p[2], a])
+        });
+        rgba.save_with_format(dir.join("rgba.png"), image::ImageFormat::Png)
+            .unwrap();
+        save_json("rgba");
+        // 16 bit gray of the block
+        let gray = image::DynamicImage::ImageRgb8(
+            image::RgbImage::from_raw(40, 30, block.data.data.clone()).unwrap(),
+        )
+        .into_luma16();
+        gray.save_with_format(dir.join("gray.png"), image::ImageFormat::Png)
+            .unwrap();
+        save_json("gray");
+        fs::write(dir.join("broken.png"), "not a png").unwrap();
+        save_json("broken");
+
+        let nmg = NeedleManager::new(&dir);
+        let mut screen = screen_with_block(10, 10);
+        for y in 12..14 {
+            for x in 13..16 {
+                screen.set(y, x, &[0, 255, 0]);
+            }
+        }
+        let rgba = nmg.load("rgba").unwrap();
+        assert_eq!(rgba.data.pixel_size, 3);
+        assert!(rgba.mask.is_some());
+        assert!(Needle::cmp(&screen, &rgba, None).matched);
+        assert!(!Needle::cmp(&screen, &block, None).matched);
+
+        let gray = nmg.load("gray").unwrap();
+        assert!(gray.mask.is_none());
+        assert!(Needle::cmp(&screen_with_block(10, 10), &gray, None).matched);
+
+        assert!(matches!(
+            nmg.load_tags(&["broken".to_string()]),
+            Err(NeedleError::Unusable { .. })
+        ));
+        assert!(matches!(
+            nmg.load_tags(&["missing".to_string()]),
+            Err(NeedleError::NotFound(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn get_needle() {
         let needle_mg = init_needle_manager();
-        let Some(png) = needle_mg.load("output") else {
+        let Ok(png) = needle_mg.load("output") else {
             return;
         };
 
@@ -216,12 +1092,14 @@ mod test {
             png.config,
             NeedleConfig {
                 areas: vec![Area {
-                    type_field: "match".to_string(),
+                    type_field: AreaType::Match,
                     left: 0,
                     top: 0,
                     width: 5,
                     height: 5,
                     click: None,
+                    margin: None,
+                    text: None,
                 }],
                 properties: Vec::new(),
                 tags: vec!["output".to_string()]
@@ -0,0 +1,306 @@
+use serde::Serialize;
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use t_binding::{report::Status, CaseReport};
+use tracing::info;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub cases: Vec<CaseReport>,
+}
+
+impl Report {
+    pub fn is_success(&self) -> bool {
+        self.cases
+            .iter()
+            .all(|c| c.is_success() || c.ignore_failure)
+    }
+
+    /// one line per case, for printing after run
+    pub fn summary(&self) -> String {
+        let width = self.cases.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        let mut s = String::new();
+        for case in self.cases.iter() {
+            let status = match case.status {
+                Status::Passed => "passed",
+                Status::Failed if case.ignore_failure => "failed (ignored)",
+                Status::Failed => "failed",
+                Status::Skipped => "skipped",
+            };
+            let _ = writeln!(
+                s,
+                "{:<width$}  {:<16}  {:>8.3}s  {}",
+                case.name,
+                status,
+                case.duration.as_secs_f64(),
+                case.failure.as_deref().unwrap_or_default(),
+                width = width,
+            );
+        }
+        let count = |status| self.cases.iter().filter(|c| c.status == status).count();
+        let _ = write!(
+            s,
+            "{} passed, {} failed, {} skipped",
+            count(Status::Passed),
+            count(Status::Failed),
+            count(Status::Skipped)
+        );
+        s
+    }
+
+    /// write report.xml (junit) and report.json into dir
+    pub fn save(&self, dir: impl AsRef<Path>) -> io::Result<(PathBuf, PathBuf)> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let xml = dir.join("report.xml");
+        fs::write(&xml, self.to_junit())?;
+
+        let json = dir.join("report.json");
+        let content = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(&json, content)?;
+
+        info!(msg = "report saved", xml = ?xml, json = ?json);
+        Ok((xml, json))
+    }
+
+    /// every case is a testsuite, every phase of the case is a testcase
+    pub fn to_junit(&self) -> String {
+        let count = |status| {
+            self.cases
+                .iter()
+                .flat_map(|c| c.phases.iter())
+                .filter(|p| p.status == status)
+                .count()
+        };
+        let tests: usize = self.cases.iter().map(|c| c.phases.len().max(1)).sum();
+        // cases failed before any phase ran are counted as an error
+        let errors = self
+            .cases
+            .iter()
+            .filter(|c| c.phases.is_empty() && c.status == Status::Failed)
+            .count();
+        let skipped_cases = self
+            .cases
+            .iter()
+            .filter(|c| c.phases.is_empty() && c.status == Status::Skipped)
+            .count();
+        let time: f64 = self.cases.iter().map(|c| c.duration.as_secs_f64()).sum();
+
+        let mut s = String::new();
+        s.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            s,
+            r#"<testsuites name="autotest" tests="{}" failures="{}" errors="{}" skipped="{}" time="{:.3}">"#,
+            tests,
+            count(Status::Failed),
+            errors,
+            count(Status::Skipped) + skipped_cases,
+            time
+        );
+        for case in self.cases.iter() {
+            let failures = case
+                .phases
+                .iter()
+                .filter(|p| p.status == Status::Failed)
+                .count();
+            let skipped = case
+                .phases
+                .iter()
+                .filter(|p| p.status == Status::Skipped)
+                .count()
+                + usize::from(case.phases.is_empty() && case.status == Status::Skipped);
+            let _ = writeln!(
+                s,
+                r#"  <testsuite name="{}" file="{}" tests="{}" failures="{}" errors="{}" skipped="{}" time="{:.3}">"#,
+                escape(&case.name),
+                escape(&case.file),
+                case.phases.len().max(1),
+                failures,
+                usize::from(case.phases.is_empty() && case.status == Status::Failed),
+                skipped,
+                case.duration.as_secs_f64(),
+            );
+            // unit is only in report.json, values stay plain numbers here
+            if !case.metrics.is_empty() {
+                s.push_str("    <properties>\n");
+                for m in case.metrics.iter() {
+                    let _ = writeln!(
+                        s,
+                        r#"      <property name="{}" value="{}"/>"#,
+                        escape(&m.name),
+                        m.value
+                    );
+                }
+                s.push_str("    </properties>\n");
+            }
+            if case.phases.is_empty() {
+                let _ = writeln!(
+                    s,
+                    r#"    <testcase classname="{}" name="load" time="0.000">"#,
+                    escape(&case.name)
+                );
+                match (case.status, case.failure.as_ref()) {
+                    (Status::Failed, Some(failure)) => {
+                        let _ = writeln!(s, r#"      <error message="{}"/>"#, escape(failure));
+                    }
+                    (Status::Skipped, reason) => {
+                        let _ = writeln!(
+                            s,
+                            r#"      <skipped message="{}"/>"#,
+                            escape(reason.map(|r| r.as_str()).unwrap_or_default())
+                        );
+                    }
+                    _ => {}
+                }
+                s.push_str("    </testcase>\n");
+            }
+            for phase in case.phases.iter() {
+                let _ = writeln!(
+                    s,
+                    r#"    <testcase classname="{}" name="{}" time="{:.3}">"#,
+                    escape(&case.name),
+                    escape(&phase.name),
+                    phase.duration.as_secs_f64(),
+                );
+                match phase.status {
+                    Status::Failed => {
+                        let _ = writeln!(
+                            s,
+                            r#"      <failure message="{}"/>"#,
+                            escape(phase.failure.as_deref().unwrap_or("failed"))
+                        );
+                    }
+                    Status::Skipped => s.push_str("      <skipped/>\n"),
+                    Status::Passed => {}
+                }
+                if !phase.calls.is_empty() {
+                    s.push_str("      <system-out>");
+                    for call in phase.calls.iter() {
+                        let _ = writeln!(
+                            s,
+                            "[{:?}] {:.3}s {}{}",
+                            call.status,
+                            call.duration.as_secs_f64(),
+                            escape(&call.req),
+                            call.failure
+                                .as_ref()
+                                .map(|f| format!(", {}", escape(f)))
+                                .unwrap_or_default(),
+                        );
+                    }
+                    s.push_str("</system-out>\n");
+                }
+                s.push_str("    </testcase>\n");
+            }
+            if !case.events.is_empty() {
+                s.push_str("    <system-out>");
+                for event in case.events.iter() {
+                    let _ = writeln!(
+                        s,
+                        "[{}] {} {}{}",
+                        event.time,
+                        escape(&event.console),
+                        event.state,
+                        event
+                            .reason
+                            .as_ref()
+                            .map(|r| format!(", {}", escape(r)))
+                            .unwrap_or_default(),
+                    );
+                }
+                s.push_str("</system-out>\n");
+            }
+            s.push_str("  </testsuite>\n");
+        }
+        s.push_str("</testsuites>\n");
+        s
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            // not allowed in xml 1.0, mostly from raw console output
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use t_binding::report::{ConsoleEvent, Metric, Recorder};
+
+    #[test]
+    fn test_junit() {
+        let recorder = Recorder::default();
+        let mut case = CaseReport::new("cases/login.js");
+        case.push_phase(recorder.phase("prehook", || Ok(())));
+        case.push_phase(recorder.phase("main", || Err("\"whoami\" <timeout>".to_string())));
+
+        case.events.push(ConsoleEvent {
+            console: "ssh.default".to_string(),
+            state: "lost".to_string(),
+            time: "2024-01-01 10:00:00".to_string(),
+            reason: Some("connection reset".to_string()),
+        });
+
+        case.metrics.push(Metric {
+            name: "boot_time".to_string(),
+            value: 12.5,
+            unit: "s".to_string(),
+        });
+
+        let report = Report {
+            cases: vec![
+                case,
+                CaseReport::new("cases/missing.js").fail("read failed"),
+            ],
+        };
+        assert!(!report.is_success());
+
+        let xml = report.to_junit();
+        assert!(xml.contains(r#"tests="3" failures="1" errors="1" skipped="0""#));
+        assert!(xml.contains(r#"<failure message="&quot;whoami&quot; &lt;timeout&gt;"/>"#));
+        assert!(xml.contains(r#"<error message="read failed"/>"#));
+        assert!(xml.contains("[2024-01-01 10:00:00] ssh.default lost, connection reset\n"));
+        assert!(xml.contains(r#"<property name="boot_time" value="12.5"/>"#));
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut ignored = CaseReport::new("cases/net.lua").fail("ping failed");
+        ignored.ignore_failure = true;
+        let report = Report {
+            cases: vec![
+                ignored,
+                CaseReport::new("cases/desktop.js").skip("dependency net not passed"),
+            ],
+        };
+        assert!(report.is_success());
+
+        let summary = report.summary();
+        assert!(summary.contains("failed (ignored)"));
+        assert!(summary.ends_with("0 passed, 1 failed, 1 skipped"));
+        assert!(report
+            .to_junit()
+            .contains(r#"<skipped message="dependency net not passed"/>"#));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a&b\u{1b}[0m\n"), "a&amp;b[0m\n");
+    }
+}
@@ -1,7 +1,8 @@
-use crate::needle::{Needle, NeedleManager};
+use crate::needle::{Gray, Needle, NeedleManager};
+use parking_lot::{Mutex, RwLock};
 use std::{
     env::current_dir,
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::{
         mpsc::{self, Receiver, Sender},
@@ -10,10 +11,16 @@ use std::{
     thread,
     time::{self, Duration, Instant},
 };
-use t_binding::{MsgReq, MsgRes, MsgResError};
-use t_config::{Config, ConsoleVNC};
-use t_console::{key, ConsoleError, Serial, VNCEventReq, VNCEventRes, PNG, SSH, VNC};
-use t_util::{get_time, AMOption};
+use t_binding::{
+    report::{ConsoleEvent, Metric},
+    MsgReq, MsgRes, MsgResError, TextConsole,
+};
+use t_config::{Config, ConsoleVNC, Consoles};
+use t_console::{
+    key, ConnHealth, ConsoleError, PowerControl, Qemu, Serial, ShellPower, Telnet, VNCEventReq,
+    VNCEventRes, PNG, SSH, VNC,
+};
+use t_util::{get_time, AMMap, AMOption};
 use tracing::{debug, error, info, warn};
 
 pub(crate) struct Server {
@@ -36,12 +43,16 @@ impl Server {
         if let Ok(tx) = self.stop_rx.try_recv() {
             info!(msg = "runner handler thread stopped");
 
-            self.repo.ssh.map_ref(|c| c.stop());
+            self.repo.ssh.for_each(|_, c| c.stop());
             info!(msg = "ssh stopped");
-            self.repo.serial.map_ref(|s| s.stop());
+            self.repo.serial.for_each(|_, s| s.stop());
             info!(msg = "serial stopped");
+            self.repo.telnet.for_each(|_, s| s.stop());
+            info!(msg = "telnet stopped");
             self.repo.vnc.map_ref(|s| s.stop());
             info!(msg = "vnc stopped");
+            // consoles are gone, safe to shutdown the machine
+            self.repo.qemu.map_ref(|q| q.stop());
 
             if let Err(e) = tx.send(()) {
                 warn!(msg = "runner handler thread stopped", reason = ?e);
@@ -101,13 +112,51 @@ impl Server {
     }
 }
 
+// text console picked by TextConsole, named after console types
+#[allow(clippy::upper_case_acronyms)]
+enum TextTarget {
+    SSH(AMOption<SSH>),
+    Serial(AMOption<Serial>),
+    Telnet(AMOption<Telnet>),
+}
+
+// connect consoles of one type, stop at the first failed one
+fn connect_all<C, T>(
+    kind: &str,
+    consoles: Consoles<C>,
+    connect: impl Fn(C) -> Result<T, ConsoleError>,
+) -> Result<Vec<(String, T)>, ConsoleError> {
+    consoles
+        .into_iter()
+        .map(|(name, c)| match connect(c) {
+            Ok(res) => {
+                info!(msg = "console connect success", kind = kind, name = name);
+                Ok((name, res))
+            }
+            Err(e) => {
+                error!(msg = "console connect failed", kind = kind, name = name, reason = ?e);
+                Err(e)
+            }
+        })
+        .collect()
+}
+
 pub(crate) struct Service {
     pub(crate) enable_screenshot: bool,
 
     pub(crate) config: AMOption<Config>,
-    pub(crate) ssh: AMOption<SSH>,
-    pub(crate) serial: AMOption<Serial>,
+    // consoles by name, the default one is used if no name given
+    pub(crate) ssh: AMMap<SSH>,
+    pub(crate) serial: AMMap<Serial>,
+    pub(crate) telnet: AMMap<Telnet>,
+    // connection state of every text console, like ssh.default, kept out of console locks
+    pub(crate) conns: RwLock<Vec<(String, ConnHealth)>>,
+    pub(crate) metrics: Mutex<Vec<Metric>>,
     pub(crate) vnc: AMOption<VNC>,
+    pub(crate) qemu: AMOption<Arc<Qemu>>,
+    pub(crate) power: AMOption<Arc<dyn PowerControl>>,
+    // kept between checks for its cache, replaced if needle_dir changes
+    pub(crate) needles: Mutex<Option<Arc<NeedleManager>>>,
 }
 
 impl Service {
@@ -160,39 +209,50 @@ impl Service {
     }
 
     pub fn connect_with_config(&self, c: Config) -> Result<(), ConsoleError> {
-        // init serial
-        if let Some(c) = c.serial.clone() {
-            self.serial.map_ref(|c| c.stop());
-            match Serial::new(c) {
-                Ok(s) => {
-                    self.serial.set(Some(s));
-                    info!(msg = "serial connect success");
+        // start qemu first, consoles connect to it
+        if let Some(c) = c.qemu.clone() {
+            self.qemu.map_ref(|q| q.stop());
+            match Qemu::spawn(c) {
+                Ok(q) => {
+                    self.qemu.set(Some(Arc::new(q)));
+                    info!(msg = "qemu start success");
                 }
                 Err(e) => {
-                    error!(msg="serial connect failed", reason = ?e);
+                    error!(msg="qemu start failed", reason = ?e);
                     return Err(e);
                 }
             }
         } else {
-            self.serial.set(None);
+            self.qemu.set(None);
         }
 
-        // init ssh
-        if let Some(c) = c.ssh.clone() {
-            self.ssh.map_ref(|s| s.stop());
-            match SSH::new(c) {
-                Ok(s) => {
-                    self.ssh.set(Some(s));
-                    info!("ssh connect success");
-                }
-                Err(e) => {
-                    error!(msg="ssh connect failed", reason = ?e);
-                    return Err(e);
-                }
+        // commands in [power] take precedence over qemu
+        let power: Option<Arc<dyn PowerControl>> = match c.power.clone() {
+            Some(p) if p.on.is_some() || p.off.is_some() || p.reset.is_some() => {
+                Some(Arc::new(ShellPower::new(p)))
             }
-        } else {
-            self.ssh.set(None);
-        }
+            _ => self.qemu.map_ref(|q| q.clone() as Arc<dyn PowerControl>),
+        };
+        self.power.set(power);
+
+        // init text consoles, each type may have several by name
+        self.serial.for_each(|_, c| c.stop());
+        self.serial
+            .set(connect_all("serial", c.serial.clone(), Serial::new)?);
+        self.ssh.for_each(|_, c| c.stop());
+        self.ssh.set(connect_all("ssh", c.ssh.clone(), SSH::new)?);
+        self.telnet.for_each(|_, c| c.stop());
+        self.telnet
+            .set(connect_all("telnet", c.telnet.clone(), Telnet::new)?);
+        let mut conns = Vec::new();
+        let mut push =
+            |kind: &str, name: &str, health| conns.push((format!("{kind}.{name}"), health));
+        self.serial
+            .for_each(|name, c| push("serial", name, c.health()));
+        self.ssh.for_each(|name, c| push("ssh", name, c.health()));
+        self.telnet
+            .for_each(|name, c| push("telnet", name, c.health()));
+        *self.conns.write() = conns;
 
         // init vnc
         let build_vnc = move |vnc: ConsoleVNC| {
@@ -227,6 +287,110 @@ impl Service {
         Ok(())
     }
 
+    /// connection state changes of all text consoles since last call, oldest first
+    pub fn take_console_events(&self) -> Vec<ConsoleEvent> {
+        let mut events: Vec<ConsoleEvent> = self
+            .conns
+            .read()
+            .iter()
+            .flat_map(|(console, health)| {
+                health.take_events().into_iter().map(|e| ConsoleEvent {
+                    console: console.clone(),
+                    state: e.state.to_string(),
+                    time: e.time,
+                    reason: e.reason,
+                })
+            })
+            .collect();
+        events.sort_by(|a, b| a.time.cmp(&b.time));
+        events
+    }
+
+    /// values measured by runner since last call, like boot time of reboot
+    pub fn take_metrics(&self) -> Vec<Metric> {
+        std::mem::take(&mut *self.metrics.lock())
+    }
+
+    // no console given means default serial, then ssh, then telnet.
+    // a name is looked up in all types, unless it's prefixed with type like ssh.client,
+    // a name found in more than one type needs the prefix
+    fn text_console(&self, console: Option<TextConsole>) -> Result<TextTarget, MsgResError> {
+        let name = match console {
+            Some(TextConsole::Named(name)) => name,
+            console => {
+                return match (
+                    console,
+                    self.ssh.get_default(),
+                    self.serial.get_default(),
+                    self.telnet.get_default(),
+                ) {
+                    (None | Some(TextConsole::Serial), _, Some(c), _) => Ok(TextTarget::Serial(c)),
+                    (None | Some(TextConsole::SSH), Some(c), _, _) => Ok(TextTarget::SSH(c)),
+                    (None | Some(TextConsole::Telnet), _, _, Some(c)) => Ok(TextTarget::Telnet(c)),
+                    _ => Err(MsgResError::String("no console supported".to_string())),
+                };
+            }
+        };
+        let (kind, short) = match name.split_once('.') {
+            Some((kind, short)) if ["serial", "ssh", "telnet"].contains(&kind) => {
+                (Some(kind), short)
+            }
+            _ => (None, name.as_str()),
+        };
+        let serial = || self.serial.get(short).map(TextTarget::Serial);
+        let ssh = || self.ssh.get(short).map(TextTarget::SSH);
+        let telnet = || self.telnet.get(short).map(TextTarget::Telnet);
+        match kind {
+            Some("serial") => serial(),
+            Some("ssh") => ssh(),
+            Some(_) => telnet(),
+            None => {
+                let mut found: Vec<_> =
+                    [("serial", serial()), ("ssh", ssh()), ("telnet", telnet())]
+                        .into_iter()
+                        .filter_map(|(kind, c)| c.map(|c| (kind, c)))
+                        .collect();
+                if found.len() > 1 {
+                    let kinds: Vec<_> = found.iter().map(|(kind, _)| *kind).collect();
+                    return Err(MsgResError::String(format!(
+                        "console {name} is ambiguous, found in {}, use a prefix like {}.{name}",
+                        kinds.join(", "),
+                        kinds[0],
+                    )));
+                }
+                found.pop().map(|(_, c)| c)
+            }
+        }
+        .ok_or_else(|| MsgResError::String(format!("no console named {}", name)))
+    }
+
+    // for requests only ssh can do, no console given means the default ssh
+    fn ssh_console(&self, console: Option<TextConsole>) -> Result<AMOption<SSH>, MsgResError> {
+        match console {
+            None | Some(TextConsole::SSH) => self.ssh.get_default(),
+            console => match self.text_console(console)? {
+                TextTarget::SSH(c) => Some(c),
+                _ => return Err(MsgResError::String("not an ssh console".to_string())),
+            },
+        }
+        .ok_or_else(|| MsgResError::String("no ssh".to_string()))
+    }
+
+    // for requests only serial can do, no console given means the default serial
+    fn serial_console(
+        &self,
+        console: Option<TextConsole>,
+    ) -> Result<AMOption<Serial>, MsgResError> {
+        match console {
+            None | Some(TextConsole::Serial) => self.serial.get_default(),
+            console => match self.text_console(console)? {
+                TextTarget::Serial(c) => Some(c),
+                _ => return Err(MsgResError::String("not a serial console".to_string())),
+            },
+        }
+        .ok_or_else(|| MsgResError::String("no serial".to_string()))
+    }
+
     fn handle_req(&self, req: MsgReq) -> MsgRes {
         let res = match req {
             // common
@@ -255,14 +419,21 @@ impl Service {
                 MsgRes::ConfigValue(v)
             }
             // ssh
-            MsgReq::SSHScriptRunSeperate { cmd, timeout: _ } => {
-                let client = &self.ssh;
-                let res = client
-                    .map_mut(|c| c.exec_seperate(&cmd))
-                    .unwrap_or(Ok((-1, "no ssh".to_string())))
-                    .map_err(|_| MsgResError::Timeout);
+            MsgReq::SSHScriptRunSeperate {
+                console,
+                cmd,
+                env,
+                cwd,
+                timeout,
+            } => {
+                let res = self
+                    .ssh_console(console)
+                    .map(|c| c.map_mut(|c| c.exec_seperate(&cmd, &env, cwd.as_deref(), timeout)));
                 match res {
-                    Ok((code, value)) => MsgRes::ScriptRun { code, value },
+                    Ok(Some(Ok(res))) => MsgRes::ExecResult(res),
+                    Ok(Some(Err(ConsoleError::Timeout))) => MsgRes::Error(MsgResError::Timeout),
+                    Ok(Some(Err(e))) => MsgRes::Error(MsgResError::String(e.to_string())),
+                    Ok(None) => MsgRes::Error(MsgResError::String("no ssh".to_string())),
                     Err(e) => MsgRes::Error(e),
                 }
             }
@@ -271,18 +442,50 @@ impl Service {
                 console,
                 timeout,
             } => {
-                let res = match (console, self.ssh.is_some(), self.serial.is_some()) {
-                    (None | Some(t_binding::TextConsole::Serial), _, true) => self
-                        .serial
+                let res = match self.text_console(console) {
+                    Ok(TextTarget::Serial(c)) => c
                         .map_mut(|c| c.exec(timeout, &cmd))
                         .unwrap_or(Ok((1, "no serial".to_string())))
                         .map_err(|_| MsgResError::Timeout),
-                    (None | Some(t_binding::TextConsole::SSH), true, _) => self
-                        .ssh
+                    Ok(TextTarget::SSH(c)) => c
                         .map_mut(|c| c.exec(timeout, &cmd))
                         .unwrap_or(Ok((-1, "no ssh".to_string())))
                         .map_err(|_| MsgResError::Timeout),
-                    _ => Err(MsgResError::String("no console supported".to_string())),
+                    Ok(TextTarget::Telnet(c)) => c
+                        .map_mut(|c| c.exec(timeout, &cmd))
+                        .expect("no telnet")
+                        .map_err(|_| MsgResError::Timeout),
+                    Err(e) => Err(e),
+                };
+                match res {
+                    Ok((code, value)) => MsgRes::ScriptRun { code, value },
+                    Err(e) => MsgRes::Error(e),
+                }
+            }
+            MsgReq::ScriptRunStream {
+                cmd,
+                console,
+                timeout,
+                output,
+            } => {
+                // caller may stop listening, keep running until cmd finished
+                let on_output = |s: &str| {
+                    let _ = output.send(s.to_string());
+                };
+                let res = match self.text_console(console) {
+                    Ok(TextTarget::Serial(c)) => c
+                        .map_mut(|c| c.exec_stream(timeout, &cmd, on_output))
+                        .unwrap_or(Ok((1, "no serial".to_string())))
+                        .map_err(|_| MsgResError::Timeout),
+                    Ok(TextTarget::SSH(c)) => c
+                        .map_mut(|c| c.exec_stream(timeout, &cmd, on_output))
+                        .unwrap_or(Ok((-1, "no ssh".to_string())))
+                        .map_err(|_| MsgResError::Timeout),
+                    Ok(TextTarget::Telnet(c)) => c
+                        .map_mut(|c| c.exec_stream(timeout, &cmd, on_output))
+                        .expect("no telnet")
+                        .map_err(|_| MsgResError::Timeout),
+                    Err(e) => Err(e),
                 };
                 match res {
                     Ok((code, value)) => MsgRes::ScriptRun { code, value },
@@ -294,18 +497,20 @@ impl Service {
                 s,
                 timeout,
             } => {
-                if let Err(e) = match (console, self.ssh.is_some(), self.serial.is_some()) {
-                    (None | Some(t_binding::TextConsole::Serial), _, true) => self
-                        .serial
+                if let Err(e) = match self.text_console(console) {
+                    Ok(TextTarget::Serial(c)) => c
                         .map_mut(|c| c.write_string(&s, timeout))
                         .expect("no serial")
                         .map_err(|_| MsgResError::Timeout),
-                    (None | Some(t_binding::TextConsole::SSH), true, _) => self
-                        .ssh
+                    Ok(TextTarget::SSH(c)) => c
                         .map_mut(|c| c.write_string(&s, timeout))
                         .expect("no ssh")
                         .map_err(|_| MsgResError::Timeout),
-                    _ => Err(MsgResError::String("no console supported".to_string())),
+                    Ok(TextTarget::Telnet(c)) => c
+                        .map_mut(|c| c.write_string(&s, timeout))
+                        .expect("no telnet")
+                        .map_err(|_| MsgResError::Timeout),
+                    Err(e) => Err(e),
                 } {
                     MsgRes::Error(e)
                 } else {
@@ -314,44 +519,353 @@ impl Service {
             }
             MsgReq::WaitString {
                 console,
-                s,
+                patterns,
+                regex,
+                n,
                 timeout,
             } => {
-                if let Err(e) = match (console, self.ssh.is_some(), self.serial.is_some()) {
-                    (None | Some(t_binding::TextConsole::Serial), _, true) => self
-                        .serial
-                        .map_mut(|c| c.wait_string(timeout, &s))
+                let patterns = match patterns
+                    .iter()
+                    .map(|p| match regex {
+                        true => regex::Regex::new(p),
+                        false => regex::Regex::new(&regex::escape(p)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    Ok(patterns) => patterns,
+                    Err(e) => return MsgRes::Error(MsgResError::String(e.to_string())),
+                };
+                match match self.text_console(console) {
+                    Ok(TextTarget::Serial(c)) => c
+                        .map_mut(|c| c.wait_match(timeout, &patterns, n))
                         .expect("no serial")
                         .map_err(|_| MsgResError::Timeout),
-                    (None | Some(t_binding::TextConsole::SSH), true, _) => self
-                        .ssh
-                        .map_mut(|c| c.wait_string(timeout, &s))
+                    Ok(TextTarget::SSH(c)) => c
+                        .map_mut(|c| c.wait_match(timeout, &patterns, n))
                         .expect("no ssh")
                         .map_err(|_| MsgResError::Timeout),
-                    _ => Err(MsgResError::String("no console supported".to_string())),
+                    Ok(TextTarget::Telnet(c)) => c
+                        .map_mut(|c| c.wait_match(timeout, &patterns, n))
+                        .expect("no telnet")
+                        .map_err(|_| MsgResError::Timeout),
+                    Err(e) => Err(e),
                 } {
-                    MsgRes::Error(e)
-                } else {
-                    MsgRes::Done
+                    Ok(m) => MsgRes::Matched(m),
+                    Err(e) => MsgRes::Error(e),
+                }
+            }
+            MsgReq::WaitPrompt { console, timeout } => match match self.text_console(console) {
+                Ok(TextTarget::Serial(c)) => c
+                    .map_mut(|c| c.wait_prompt(timeout))
+                    .expect("no serial")
+                    .map_err(|_| MsgResError::Timeout),
+                Ok(TextTarget::SSH(c)) => c
+                    .map_mut(|c| c.wait_prompt(timeout))
+                    .expect("no ssh")
+                    .map_err(|_| MsgResError::Timeout),
+                Ok(TextTarget::Telnet(c)) => c
+                    .map_mut(|c| c.wait_prompt(timeout))
+                    .expect("no telnet")
+                    .map_err(|_| MsgResError::Timeout),
+                Err(e) => Err(e),
+            } {
+                Ok(()) => MsgRes::Done,
+                Err(e) => MsgRes::Error(e),
+            },
+            MsgReq::WaitConsoleReady { console, timeout } => {
+                let conns: Vec<ConnHealth> = match console {
+                    None => self.conns.read().iter().map(|(_, h)| h.clone()).collect(),
+                    console => match self.text_console(console) {
+                        Ok(TextTarget::Serial(c)) => {
+                            c.map_ref(|c| c.health()).into_iter().collect()
+                        }
+                        Ok(TextTarget::SSH(c)) => c.map_ref(|c| c.health()).into_iter().collect(),
+                        Ok(TextTarget::Telnet(c)) => {
+                            c.map_ref(|c| c.health()).into_iter().collect()
+                        }
+                        Err(e) => return MsgRes::Error(e),
+                    },
+                };
+                let deadline = Instant::now() + timeout;
+                match conns.iter().try_for_each(|h| {
+                    h.wait_connected(deadline.saturating_duration_since(Instant::now()))
+                }) {
+                    Ok(()) => MsgRes::Done,
+                    Err(_) => MsgRes::Error(MsgResError::Timeout),
                 }
             }
+            MsgReq::QMP(req) => {
+                let res = self.qemu.map_ref(|q| match req {
+                    t_binding::msg::QMP::SystemReset => q.system_reset(),
+                    t_binding::msg::QMP::SaveVM(tag) => q.savevm(&tag),
+                    t_binding::msg::QMP::LoadVM(tag) => q.loadvm(&tag),
+                    t_binding::msg::QMP::Quit => q.quit(),
+                });
+                match res {
+                    Some(Ok(())) => MsgRes::Done,
+                    Some(Err(e)) => MsgRes::Error(MsgResError::String(e.to_string())),
+                    None => MsgRes::Error(MsgResError::String("no qemu".to_string())),
+                }
+            }
+            MsgReq::Power {
+                action,
+                boot_timeout,
+            } => self.handle_power_req(action, boot_timeout),
+            MsgReq::Reboot {
+                cmd,
+                boot_timeout,
+                login_timeout,
+                ssh_timeout,
+            } => self.handle_reboot(cmd, boot_timeout, login_timeout, ssh_timeout),
             MsgReq::VNC(e) => self.handle_vnc_req(e),
+            MsgReq::Transfer { console, req } => {
+                use t_binding::msg::Transfer;
+                let log_dir = self
+                    .config
+                    .and_then_ref(|c| c.log_dir.clone())
+                    .unwrap_or_default();
+                let res = self.ssh_console(console).map(|c| {
+                    c.map_mut(|c| match req {
+                        Transfer::Upload {
+                            local,
+                            remote,
+                            mode,
+                        } => c.upload(&local, &remote, mode).map(|_| MsgRes::Done),
+                        Transfer::Download { remote, local } => c
+                            .download(&remote, &Path::new(&log_dir).join(local))
+                            .map(|_| MsgRes::Done),
+                        Transfer::Exists(remote) => c.remote_exists(&remote).map(MsgRes::Exists),
+                    })
+                });
+                match res {
+                    Ok(Some(Ok(res))) => res,
+                    Ok(Some(Err(e))) => MsgRes::Error(MsgResError::String(e.to_string())),
+                    Ok(None) => MsgRes::Error(MsgResError::String("no ssh".to_string())),
+                    Err(e) => MsgRes::Error(e),
+                }
+            }
+            MsgReq::SerialTransfer { console, req } => {
+                use t_binding::msg::SerialTransfer;
+                let log_dir = self
+                    .config
+                    .and_then_ref(|c| c.log_dir.clone())
+                    .unwrap_or_default();
+                let res = self.serial_console(console).map(|c| {
+                    c.map_mut(|c| match req {
+                        SerialTransfer::Upload {
+                            local,
+                            remote,
+                            mode,
+                            timeout,
+                        } => c.upload(&local, &remote, mode, timeout),
+                        SerialTransfer::Download {
+                            remote,
+                            local,
+                            timeout,
+                        } => c.download(&remote, &Path::new(&log_dir).join(local), timeout),
+                    })
+                });
+                match res {
+                    Ok(Some(Ok(()))) => MsgRes::Done,
+                    Ok(Some(Err(ConsoleError::Timeout))) => MsgRes::Error(MsgResError::Timeout),
+                    Ok(Some(Err(e))) => MsgRes::Error(MsgResError::String(e.to_string())),
+                    Ok(None) => MsgRes::Error(MsgResError::String("no serial".to_string())),
+                    Err(e) => MsgRes::Error(e),
+                }
+            }
+            MsgReq::Job { console, req } => {
+                use t_binding::msg::Job;
+                let res = self.ssh_console(console).map(|c| {
+                    c.map_mut(|c| match req {
+                        Job::Spawn(cmd) => c.spawn(&cmd).map(MsgRes::JobId),
+                        Job::Status(id) => c.job_status(id).map(MsgRes::JobStatus),
+                        Job::Wait { id, timeout } => c.job_wait(id, timeout).map(MsgRes::JobStatus),
+                        Job::Kill(id) => c.job_kill(id).map(MsgRes::JobStatus),
+                        Job::Output(id) => c.job_output(id).map(MsgRes::JobOutput),
+                    })
+                });
+                match res {
+                    Ok(Some(Ok(res))) => res,
+                    Ok(Some(Err(ConsoleError::Timeout))) => MsgRes::Error(MsgResError::Timeout),
+                    Ok(Some(Err(e))) => MsgRes::Error(MsgResError::String(e.to_string())),
+                    Ok(None) => MsgRes::Error(MsgResError::String("no ssh".to_string())),
+                    Err(e) => MsgRes::Error(e),
+                }
+            }
         };
         res
     }
 
-    pub fn handle_vnc_req(&self, req: t_binding::msg::VNC) -> MsgRes {
-        let nmg = NeedleManager::new(
-            self.config
-                .and_then_ref(|c| {
-                    c.vnc.as_ref().and_then(|vnc| {
-                        vnc.needle_dir
-                            .as_ref()
-                            .and_then(|d| PathBuf::from_str(d).ok())
-                    })
+    fn boot_pattern(&self) -> Result<regex::Regex, MsgResError> {
+        let pattern = self
+            .config
+            .and_then_ref(|c| c.power.as_ref().and_then(|p| p.boot_pattern.clone()));
+        regex::Regex::new(pattern.as_deref().unwrap_or("login:"))
+            .map_err(|e| MsgResError::String(e.to_string()))
+    }
+
+    // reboot, then wait boot pattern on serial, log in again and wait all ssh reconnected
+    fn handle_reboot(
+        &self,
+        cmd: String,
+        boot_timeout: Option<Duration>,
+        login_timeout: Option<Duration>,
+        ssh_timeout: Option<Duration>,
+    ) -> MsgRes {
+        let pattern = match self.boot_pattern() {
+            Ok(p) => p,
+            Err(e) => return MsgRes::Error(e),
+        };
+        let failed = |stage: &str, e: ConsoleError| {
+            error!(msg = "reboot failed", stage = stage, reason = %e);
+            MsgRes::Error(MsgResError::String(format!(
+                "reboot {} failed, {}",
+                stage, e
+            )))
+        };
+        // old ssh connections may not be noticed lost yet, only newer ones count
+        let ssh: Vec<(ConnHealth, u64)> = self
+            .conns
+            .read()
+            .iter()
+            .filter(|(name, _)| name.starts_with("ssh."))
+            .map(|(_, h)| (h.clone(), h.connects()))
+            .collect();
+
+        // same enter as exec
+        let line = format!("{}\r", cmd);
+        let serial = self.serial.get_default();
+        info!(msg = "reboot", cmd = cmd);
+        let sent = match (serial.as_ref(), self.ssh.get_default()) {
+            (Some(serial), _) => serial.map_ref(|s| {
+                // boot pattern of last boot is still in buffer
+                s.discard_buffer();
+                s.write_string(&line, Duration::from_secs(5))
+            }),
+            (None, Some(ssh)) => ssh.map_ref(|s| s.write_string(&line, Duration::from_secs(5))),
+            (None, None) => None,
+        };
+        match sent {
+            Some(Ok(())) => {}
+            Some(Err(e)) => return failed("cmd", e),
+            None => {
+                return MsgRes::Error(MsgResError::String(
+                    "no serial or ssh to reboot".to_string(),
+                ))
+            }
+        }
+        let start = Instant::now();
+
+        // only the boot and ssh stages tell when the machine is up
+        let mut boot_time = None;
+        if let (Some(serial), Some(timeout)) = (serial.as_ref(), boot_timeout) {
+            let res = serial.map_mut(|s| s.wait_match(timeout, std::slice::from_ref(&pattern), 1));
+            if let Some(Err(e)) = res {
+                return failed("boot", e);
+            }
+            boot_time = Some(start.elapsed());
+        }
+        if let (Some(serial), Some(timeout)) = (serial.as_ref(), login_timeout) {
+            if let Some(Err(e)) = serial.map_mut(|s| s.login(timeout)) {
+                return failed("login", e);
+            }
+        }
+        if let Some(timeout) = ssh_timeout {
+            let deadline = Instant::now() + timeout;
+            for (health, since) in ssh.iter() {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if let Err(e) = health.wait_reconnected(*since, left) {
+                    return failed("ssh", e);
+                }
+            }
+            if !ssh.is_empty() {
+                boot_time = boot_time.or(Some(start.elapsed()));
+            }
+        }
+
+        info!(msg = "reboot done", boot_time = ?boot_time);
+        if let Some(boot_time) = boot_time {
+            self.metrics.lock().push(Metric {
+                name: "boot_time".to_string(),
+                value: boot_time.as_secs_f64(),
+                unit: "s".to_string(),
+            });
+        }
+        MsgRes::Rebooted {
+            boot_time: boot_time.unwrap_or(start.elapsed()),
+        }
+    }
+
+    fn handle_power_req(
+        &self,
+        action: t_binding::msg::Power,
+        boot_timeout: Option<Duration>,
+    ) -> MsgRes {
+        use t_binding::msg::Power;
+
+        // don't hold the lock while the machine is off
+        let Some(power) = self.power.map_ref(|p| p.clone()) else {
+            return MsgRes::Error(MsgResError::String("no power control".to_string()));
+        };
+        let delay = self
+            .config
+            .and_then_ref(|c| c.power.as_ref().and_then(|p| p.cycle_delay));
+        let boot_timeout = boot_timeout.filter(|_| !matches!(action, Power::Off));
+        let pattern = match self.boot_pattern() {
+            Ok(p) => p,
+            Err(e) => return MsgRes::Error(e),
+        };
+
+        // login prompt of last boot is still in buffer
+        if boot_timeout.is_some() {
+            self.serial.map_ref(|s| s.discard_buffer());
+        }
+        info!(msg = "power control", action = ?action);
+        let res = match action {
+            Power::On => power.power_on(),
+            Power::Off => power.power_off(),
+            Power::Reset => power.reset(),
+            Power::Cycle => power.cycle(Duration::from_secs(delay.unwrap_or(3))),
+        };
+        if let Err(e) = res {
+            return MsgRes::Error(MsgResError::String(e.to_string()));
+        }
+
+        let Some(timeout) = boot_timeout else {
+            return MsgRes::Done;
+        };
+        match self
+            .serial
+            .map_mut(|s| s.wait_match(timeout, std::slice::from_ref(&pattern), 1))
+        {
+            Some(Ok(_)) => MsgRes::Done,
+            Some(Err(_)) => MsgRes::Error(MsgResError::Timeout),
+            None => MsgRes::Error(MsgResError::String(
+                "no serial to wait for boot".to_string(),
+            )),
+        }
+    }
+
+    fn needle_manager(&self) -> Arc<NeedleManager> {
+        let dir = self
+            .config
+            .and_then_ref(|c| {
+                c.vnc.as_ref().and_then(|vnc| {
+                    vnc.needle_dir
+                        .as_ref()
+                        .and_then(|d| PathBuf::from_str(d).ok())
                 })
-                .unwrap_or(current_dir().unwrap()),
-        );
+            })
+            .unwrap_or(current_dir().unwrap());
+        let mut nmg = self.needles.lock();
+        match nmg.as_ref() {
+            Some(n) if n.dir() == dir => n.clone(),
+            _ => nmg.insert(Arc::new(NeedleManager::new(dir))).clone(),
+        }
+    }
+
+    pub fn handle_vnc_req(&self, req: t_binding::msg::VNC) -> MsgRes {
+        let nmg = self.needle_manager();
         let mut take_screenshot = false;
         if let Some(res) = self.vnc.map_ref(|c| {
             let screenshotname;
@@ -381,7 +895,7 @@ impl Service {
                     }
                 }
                 t_binding::msg::VNC::CheckScreen {
-                    tag,
+                    tags,
                     threshold,
                     timeout,
                     click,
@@ -396,85 +910,90 @@ impl Service {
                     'res: loop {
                         i += 1;
                         if Instant::now() > deadline {
-                            let msg = "match timeout";
-                            info!(msg = msg, tag = tag, similarity = similarity);
-                            break 'res MsgRes::Error(MsgResError::String(
-                                msg.to_string()
-                            ));
+                            info!(msg = "match timeout", tags = ?tags, similarity = similarity);
+                            break 'res MsgRes::Error(MsgResError::Timeout);
                         }
                         match c.send(VNCEventReq::GetScreenShot) {
                             Ok(VNCEventRes::Screen(s)) => {
-                                let Some(needle) = nmg.load(&tag) else {
-                                    let msg = "assert screen failed, needle file not found";
-                                    error!(msg = msg, tag = tag);
-                                    if self.enable_screenshot && c.send(VNCEventReq::TakeScreenShot(format!(
-                                        "{screenshotname}-{i}-failed-noneedle"
-                                    )))
-                                    .is_err()
-                                    {
-                                        warn!("take screenshot failed, vnc server may stopped unexpectedly")
-                                    }
-                                    if Instant::now() > deadline {
-                                        break 'res MsgRes::Error(MsgResError::String(
-                                            msg.to_string()
-                                        ));
+                                let needles = match nmg.load_tags(&tags) {
+                                    Ok(needles) => needles,
+                                    Err(e) => {
+                                        let msg = format!("assert screen failed, {}", e);
+                                        error!(msg = msg, tags = ?tags);
+                                        if self.enable_screenshot && c.send(VNCEventReq::TakeScreenShot(format!(
+                                            "{screenshotname}-{i}-failed-noneedle"
+                                        )))
+                                        .is_err()
+                                        {
+                                            warn!("take screenshot failed, vnc server may stopped unexpectedly")
+                                        }
+                                        // a needle may be fixed meanwhile, report why if not
+                                        if Instant::now() + Duration::from_millis(1000) > deadline {
+                                            break 'res MsgRes::Error(MsgResError::Needle(msg));
+                                        }
+                                        thread::sleep(Duration::from_millis(1000));
+                                        continue;
                                     }
-                                    thread::sleep(Duration::from_millis(1000));
+                                };
+                                // the most similar one of all variants carrying the tags
+                                let gray = Gray::new(&s);
+                                let Some((needle, found)) = needles
+                                    .into_iter()
+                                    .map(|n| {
+                                        let found = Needle::cmp_gray(&s, &gray, &n, Some(threshold));
+                                        (n, found)
+                                    })
+                                    .max_by(|a, b| a.1.similarity.total_cmp(&b.1.similarity))
+                                else {
+                                    // load_tags never gives an empty list, don't spin if it does
+                                    thread::sleep(Duration::from_millis(200));
                                     continue;
                                 };
 
-                                let (res_similarity, needle_match) = Needle::cmp(
-                                    &s,
-                                    &needle,
-                                    Some(threshold),
-                                ) ;
-
-                                similarity = res_similarity;
+                                similarity = found.similarity;
 
-                                if needle_match {
+                                if found.matched {
                                     info!(
                                         msg = "match success",
-                                        tag = tag,
-                                        similarity = similarity
+                                        tags = ?tags,
+                                        needle = needle.name,
+                                        similarity = similarity,
+                                        offsets = ?found.areas.iter().map(|a| a.offset).collect::<Vec<_>>()
                                     );
                                     if let Some(delay) = delay {
                                         thread::sleep(delay);
                                     }
                                     if click || r#move {
-                                        for area in needle.config.areas {
-                                            if let Some(point) = area.click {
-                                                let x = point.left + area.left;
-                                                let y = point.top + area.top;
-                                                    if r#move && !matches!(c.send(VNCEventReq::MouseMove(x, y)), Ok(VNCEventRes::Done)) {
-                                                        let msg ="check screen success, but mouse move failed";
-                                                        warn!(msg = msg);
-                                                        break 'res MsgRes::Error(MsgResError::String(msg.to_string()));
+                                        // area may be found a bit away from where it is in needle
+                                        if let Some((x, y)) = needle.click_point(&found) {
+                                            if r#move && !matches!(c.send(VNCEventReq::MouseMove(x, y)), Ok(VNCEventRes::Done)) {
+                                                let msg ="check screen success, but mouse move failed";
+                                                warn!(msg = msg);
+                                                break 'res MsgRes::Error(MsgResError::String(msg.to_string()));
+                                            }
+                                            if click {
+                                                thread::sleep(Duration::from_millis(1000));
+                                                if !matches!(c.send(VNCEventReq::MouseMove(x, y)), Ok(VNCEventRes::Done)) {
+                                                    let msg ="check screen success, but mouse move failed";
+                                                    warn!(msg = msg);
+                                                    break 'res MsgRes::Error(MsgResError::String(msg.to_string()));
                                                 }
-                                                if click {
-                                                    thread::sleep(Duration::from_millis(1000));
-                                                    if !matches!(c.send(VNCEventReq::MouseMove(x, y)), Ok(VNCEventRes::Done)) {
-                                                        let msg ="check screen success, but mouse move failed";
-                                                        warn!(msg = msg);
-                                                        break 'res MsgRes::Error(MsgResError::String(msg.to_string()));
-                                                    }
-                                                    thread::sleep(Duration::from_millis(1000));
-                                                    if !matches!(c.send(VNCEventReq::MouseClick(1)), Ok(VNCEventRes::Done)) {
-                                                        let msg ="check screen and mouse move success, but mouse click failed";
-                                                        warn!(msg = msg);
-                                                        break 'res MsgRes::Error(MsgResError::String(msg.to_string()));
-                                                    }
-                                                    thread::sleep(Duration::from_millis(1000));
+                                                thread::sleep(Duration::from_millis(1000));
+                                                if !matches!(c.send(VNCEventReq::MouseClick(1)), Ok(VNCEventRes::Done)) {
+                                                    let msg ="check screen and mouse move success, but mouse click failed";
+                                                    warn!(msg = msg);
+                                                    break 'res MsgRes::Error(MsgResError::String(msg.to_string()));
                                                 }
-                                                break;
+                                                thread::sleep(Duration::from_millis(1000));
                                             }
                                         }
-                                            if !r#move && !matches!(c.send(VNCEventReq::MouseHide), Ok(VNCEventRes::Done)) {
-                                                let msg ="check screen success, but mouse hide after click failed";
-                                                warn!(msg = msg);
-                                                break 'res MsgRes::Error(MsgResError::String(msg.to_string()));
-                                            }
+                                        if !r#move && !matches!(c.send(VNCEventReq::MouseHide), Ok(VNCEventRes::Done)) {
+                                            let msg ="check screen success, but mouse hide after click failed";
+                                            warn!(msg = msg);
+                                            break 'res MsgRes::Error(MsgResError::String(msg.to_string()));
+                                        }
                                     }
-                                    break 'res MsgRes::Done;
+                                    break 'res MsgRes::Needle(needle.name.clone());
                                 } else {
                                     if  self.enable_screenshot && c.send(VNCEventReq::TakeScreenShot(
                                         format!(
@@ -483,7 +1002,7 @@ impl Service {
                                     )).is_err() {
                                         warn!("take screenshot failed, vnc server may stopped unexpectedly")
                                     }
-                                    warn!(msg = "match failed", tag = tag, similarity = similarity);
+                                    warn!(msg = "match failed", tags = ?tags, needle = needle.name, similarity = similarity);
                                 }
                             }
                             Ok(_) => {
@@ -579,7 +1098,154 @@ impl Service {
 
 #[cfg(test)]
 mod test {
+    use crate::DriverBuilder;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+    use t_binding::{MsgReq, MsgRes, MsgResError, TextConsole};
+    use t_config::Config;
 
     #[test]
     fn test_runner() {}
+
+    #[test]
+    fn test_reboot() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            let mut buf = [0; 64];
+            let n = s.read(&mut buf).unwrap();
+            assert_eq!(&buf[..n], b"reboot\r");
+            thread::sleep(Duration::from_millis(300));
+            s.write_all(b"[    0.000000] Linux version 6.1\nubuntu login: ")
+                .unwrap();
+            // second reboot waits nothing
+            let n = s.read(&mut buf).unwrap();
+            assert_eq!(&buf[..n], b"reboot\r");
+            // keep serial open until the test is done
+            let _ = s.read(&mut buf);
+        });
+
+        let log_dir = std::env::temp_dir().join("autotest-reboot");
+        let config = Config::from_toml_str(&format!(
+            r#"
+            log_dir = "{}"
+            [serial]
+            serial_file = "{}"
+            type = "Tcp"
+            disable_echo = true
+            "#,
+            log_dir.display(),
+            addr
+        ))
+        .unwrap();
+        let mut driver = DriverBuilder::new(Some(config)).build().unwrap();
+        driver.start();
+
+        let (tx, rx) = mpsc::channel();
+        driver
+            .msg_tx
+            .send((
+                MsgReq::Reboot {
+                    cmd: "reboot".to_string(),
+                    boot_timeout: Some(Duration::from_secs(5)),
+                    login_timeout: None,
+                    // no ssh to wait
+                    ssh_timeout: Some(Duration::from_secs(1)),
+                },
+                tx,
+            ))
+            .unwrap();
+        let res = rx.recv().unwrap();
+        let MsgRes::Rebooted { boot_time } = res else {
+            panic!("reboot failed, {:?}", res);
+        };
+        assert!(boot_time >= Duration::from_millis(300));
+
+        let metrics = driver.take_metrics();
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].name, "boot_time");
+        assert!(driver.take_metrics().is_empty());
+
+        // nothing waited, nothing measured
+        let (tx, rx) = mpsc::channel();
+        driver
+            .msg_tx
+            .send((
+                MsgReq::Reboot {
+                    cmd: "reboot".to_string(),
+                    boot_timeout: None,
+                    login_timeout: None,
+                    ssh_timeout: None,
+                },
+                tx,
+            ))
+            .unwrap();
+        let res = rx.recv().unwrap();
+        assert!(matches!(res, MsgRes::Rebooted { .. }), "{:?}", res);
+        assert!(driver.take_metrics().is_empty());
+        driver.stop();
+    }
+
+    #[test]
+    fn test_console_by_name() {
+        // accept and hold connections, nothing is run on them
+        let listen = || {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            thread::spawn(move || {
+                let conns: Vec<_> = listener.incoming().collect();
+                drop(conns);
+            });
+            addr
+        };
+        let (serial, telnet) = (listen(), listen());
+        let config = Config::from_toml_str(&format!(
+            r#"
+            log_dir = "{}"
+            [serial.dut]
+            serial_file = "{}"
+            type = "Tcp"
+            disable_echo = true
+            [telnet.dut]
+            host = "127.0.0.1"
+            port = {}
+            "#,
+            std::env::temp_dir().join("autotest-console-name").display(),
+            serial,
+            telnet.port()
+        ))
+        .unwrap();
+        let mut driver = DriverBuilder::new(Some(config)).build().unwrap();
+        driver.start();
+
+        let req = |req| {
+            let (tx, rx) = mpsc::channel();
+            driver.msg_tx.send((req, tx)).unwrap();
+            match rx.recv().unwrap() {
+                MsgRes::Error(MsgResError::String(e)) => e,
+                res => panic!("expect error, got {:?}", res),
+            }
+        };
+        let e = req(MsgReq::WriteString {
+            console: Some(TextConsole::Named("dut".to_string())),
+            s: "ls\n".to_string(),
+            timeout: Duration::from_secs(1),
+        });
+        assert!(e.contains("ambiguous, found in serial, telnet"), "{e}");
+        let e = req(MsgReq::SSHScriptRunSeperate {
+            console: Some(TextConsole::Named("serial.dut".to_string())),
+            cmd: "ls".to_string(),
+            env: Vec::new(),
+            cwd: None,
+            timeout: Duration::from_secs(1),
+        });
+        assert_eq!(e, "not an ssh console");
+        driver.stop();
+    }
 }
@@ -12,11 +12,19 @@ use parking_lot::RwLock;
 use regex::Regex;
 use tracing::{error, info, trace};
 
-#[derive(Clone)]
 pub struct AMOption<T> {
     inner: Arc<RwLock<Option<T>>>,
 }
 
+// clones share the value, no need for T: Clone
+impl<T> Clone for AMOption<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
 impl<T> AMOption<T> {
     pub fn new(val: Option<T>) -> Self {
         Self {
@@ -62,6 +70,83 @@ impl<T> AMOption<T> {
     }
 }
 
+type Named<T> = (String, AMOption<T>);
+
+/// values by name, each one has its own lock so they can be used at the same time.
+/// the first one is the default, map_* and is_some work on it
+pub struct AMMap<T> {
+    inner: Arc<RwLock<Vec<Named<T>>>>,
+}
+
+impl<T> Clone for AMMap<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Default for AMMap<T> {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+}
+
+impl<T> AMMap<T> {
+    pub fn set(&self, vals: Vec<(String, T)>) {
+        *self.inner.write() = vals
+            .into_iter()
+            .map(|(name, v)| (name, AMOption::new(Some(v))))
+            .collect();
+    }
+
+    pub fn get(&self, name: &str) -> Option<AMOption<T>> {
+        self.inner
+            .read()
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.clone())
+    }
+
+    pub fn get_default(&self) -> Option<AMOption<T>> {
+        self.inner.read().first().map(|(_, v)| v.clone())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.inner.read().iter().map(|(n, _)| n.clone()).collect()
+    }
+
+    pub fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&str, &T),
+    {
+        for (name, v) in self.inner.read().iter() {
+            v.map_ref(|v| f(name, v));
+        }
+    }
+
+    // the map lock is released before locking the value
+    pub fn map_mut<R, F>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        self.get_default()?.map_mut(f)
+    }
+
+    pub fn map_ref<R, F>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.get_default()?.map_ref(f)
+    }
+
+    pub fn is_some(&self) -> bool {
+        !self.inner.read().is_empty()
+    }
+}
+
 #[derive(Debug)]
 pub enum RegexError {
     RegexBuildError(regex::Error),
@@ -126,6 +211,7 @@ where
 pub enum ExecutorError {
     SpawnCommand(std::io::Error),
     WaitProcess(std::io::Error),
+    ExitStatus(std::process::ExitStatus),
 }
 impl Error for ExecutorError {}
 impl Display for ExecutorError {
@@ -133,6 +219,7 @@ impl Display for ExecutorError {
         match self {
             ExecutorError::SpawnCommand(e) => write!(f, "{}", e),
             ExecutorError::WaitProcess(e) => write!(f, "{}", e),
+            ExecutorError::ExitStatus(s) => write!(f, "command failed, {}", s),
         }
     }
 }
@@ -144,7 +231,10 @@ pub fn execute_shell(command: &str) -> Result<(), ExecutorError> {
         .spawn()
         .map_err(ExecutorError::SpawnCommand)?;
 
-    cmd.wait().map_err(ExecutorError::WaitProcess)?;
+    let status = cmd.wait().map_err(ExecutorError::WaitProcess)?;
+    if !status.success() {
+        return Err(ExecutorError::ExitStatus(status));
+    }
 
     Ok(())
 }
@@ -154,6 +244,35 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_execute_shell() {
+        assert!(execute_shell("true").is_ok());
+        assert!(matches!(
+            execute_shell("exit 3"),
+            Err(ExecutorError::ExitStatus(s)) if s.code() == Some(3)
+        ));
+    }
+
+    #[test]
+    fn test_ammap() {
+        let m = AMMap::default();
+        assert!(!m.is_some());
+        assert_eq!(m.map_ref(|v: &i32| *v), None);
+
+        m.set(vec![("a".to_string(), 1), ("b".to_string(), 2)]);
+        assert_eq!(m.map_ref(|v| *v), Some(1));
+        assert_eq!(m.names(), ["a", "b"]);
+
+        // value lock is separate, the map is still usable while b is held
+        let b = m.get("b").unwrap();
+        b.map_mut(|v| {
+            *v += 1;
+            assert_eq!(m.map_mut(|v| *v), Some(1));
+        });
+        assert_eq!(m.get("b").unwrap().map_ref(|v| *v), Some(3));
+        assert!(m.get("c").is_none());
+    }
+
     #[test]
     fn test_exec_cmd() {
         let output = Command::new("bash")